@@ -0,0 +1,267 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! Keys under which the responses of the emulator service are archived.
+
+use super::keccak_hash::keccak;
+
+use std::fmt;
+
+/// Emulator request whose response is archived. The key is the session id
+/// and the operation name, followed by the keccak of the canonical encoding
+/// of the whole request, so that keys stay short whatever the request size
+/// and requests of different operations never share a key
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ArchiveKey {
+    New {
+        id: String,
+    },
+    Run {
+        id: String,
+        times: Vec<u64>,
+    },
+    Step {
+        id: String,
+        time: u64,
+    },
+    Read {
+        id: String,
+        time: u64,
+        address: u64,
+        length: u64,
+    },
+    Write {
+        id: String,
+        time: u64,
+        address: u64,
+        data: Vec<u8>,
+    },
+    Replace {
+        id: String,
+        time: u64,
+        address: u64,
+        path: String,
+    },
+    Proof {
+        id: String,
+        time: u64,
+        address: u64,
+        log2_size: u64,
+    },
+    End {
+        id: String,
+    },
+    /// Version of the machine manager, asked again every `period`
+    Version {
+        id: String,
+        period: u64,
+    },
+}
+
+/// Length prefixed encoding of the variable sized fields, so that no two
+/// requests share their canonical bytes
+fn push_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    push_u64(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn push_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+impl ArchiveKey {
+    pub fn id(&self) -> &String {
+        match self {
+            ArchiveKey::New { id }
+            | ArchiveKey::Run { id, .. }
+            | ArchiveKey::Step { id, .. }
+            | ArchiveKey::Read { id, .. }
+            | ArchiveKey::Write { id, .. }
+            | ArchiveKey::Replace { id, .. }
+            | ArchiveKey::Proof { id, .. }
+            | ArchiveKey::End { id }
+            | ArchiveKey::Version { id, .. } => id,
+        }
+    }
+
+    pub fn operation(&self) -> &'static str {
+        match self {
+            ArchiveKey::New { .. } => "new",
+            ArchiveKey::Run { .. } => "run",
+            ArchiveKey::Step { .. } => "step",
+            ArchiveKey::Read { .. } => "read",
+            ArchiveKey::Write { .. } => "write",
+            ArchiveKey::Replace { .. } => "replace",
+            ArchiveKey::Proof { .. } => "proof",
+            ArchiveKey::End { .. } => "end",
+            ArchiveKey::Version { .. } => "version",
+        }
+    }
+
+    /// Operation tag followed by every field of the request
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        push_bytes(&mut out, self.operation().as_bytes());
+        push_bytes(&mut out, self.id().as_bytes());
+        match self {
+            ArchiveKey::Run { times, .. } => {
+                push_u64(&mut out, times.len() as u64);
+                for time in times {
+                    push_u64(&mut out, *time);
+                }
+            }
+            ArchiveKey::Step { time, .. } => {
+                push_u64(&mut out, *time);
+            }
+            ArchiveKey::Read {
+                time,
+                address,
+                length,
+                ..
+            } => {
+                push_u64(&mut out, *time);
+                push_u64(&mut out, *address);
+                push_u64(&mut out, *length);
+            }
+            ArchiveKey::Write {
+                time,
+                address,
+                data,
+                ..
+            } => {
+                push_u64(&mut out, *time);
+                push_u64(&mut out, *address);
+                push_bytes(&mut out, data);
+            }
+            ArchiveKey::Replace {
+                time,
+                address,
+                path,
+                ..
+            } => {
+                push_u64(&mut out, *time);
+                push_u64(&mut out, *address);
+                push_bytes(&mut out, path.as_bytes());
+            }
+            ArchiveKey::Proof {
+                time,
+                address,
+                log2_size,
+                ..
+            } => {
+                push_u64(&mut out, *time);
+                push_u64(&mut out, *address);
+                push_u64(&mut out, *log2_size);
+            }
+            ArchiveKey::Version { period, .. } => {
+                push_u64(&mut out, *period);
+            }
+            ArchiveKey::New { .. } | ArchiveKey::End { .. } => {}
+        }
+        out
+    }
+}
+
+impl fmt::Display for ArchiveKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}_{}_{:x}",
+            self.id(),
+            self.operation(),
+            keccak(&self.canonical_bytes())
+        )
+    }
+}
+
+impl From<ArchiveKey> for String {
+    fn from(key: ArchiveKey) -> String {
+        key.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tests::MACHINEID;
+
+    fn id() -> String {
+        String::from(MACHINEID)
+    }
+
+    #[test]
+    fn it_should_not_alias_write_and_replace() {
+        let write = ArchiveKey::Write {
+            id: id(),
+            time: 0,
+            address: 0x100,
+            data: b"/tmp/drive".to_vec(),
+        };
+        let replace = ArchiveKey::Replace {
+            id: id(),
+            time: 0,
+            address: 0x100,
+            path: String::from("/tmp/drive"),
+        };
+        assert_ne!(write.to_string(), replace.to_string());
+        assert!(replace.to_string().starts_with("Machine000_replace_"));
+    }
+
+    #[test]
+    fn it_should_not_alias_ids_and_fields() {
+        // the same characters split differently between id and times
+        let a = ArchiveKey::Run {
+            id: String::from("m_1"),
+            times: vec![2],
+        };
+        let b = ArchiveKey::Run {
+            id: String::from("m"),
+            times: vec![1, 2],
+        };
+        assert_ne!(a.to_string(), b.to_string());
+        let empty = ArchiveKey::Run {
+            id: id(),
+            times: vec![],
+        };
+        let end = ArchiveKey::End { id: id() };
+        assert_ne!(empty.canonical_bytes(), end.canonical_bytes());
+    }
+
+    #[test]
+    fn it_should_keep_keys_short() {
+        let key = ArchiveKey::Write {
+            id: id(),
+            time: 0,
+            address: 0,
+            data: vec![0xff; 1 << 20],
+        };
+        // id, operation, two separators and 64 hex digits
+        assert_eq!(
+            key.to_string().len(),
+            MACHINEID.len() + "write".len() + 2 + 64
+        );
+        assert_eq!(key.clone().to_string(), String::from(key));
+    }
+}
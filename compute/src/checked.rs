@@ -0,0 +1,85 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! Checked conversions of the values read from contracts, which a hostile
+//! or buggy contract may set beyond what the DApps can handle.
+
+use super::error::*;
+use super::ethereum_types::U256;
+
+use std::convert::TryFrom;
+
+/// Conversions that fail with an `InvalidContractState` naming the value,
+/// instead of panicking like `U256::as_u64`
+pub trait CheckedConversion {
+    fn to_u64(&self, name: &str) -> Result<u64>;
+    fn to_usize(&self, name: &str) -> Result<usize>;
+}
+
+fn out_of_range(name: &str, value: &U256) -> Error {
+    Error::from(ErrorKind::InvalidContractState(format!(
+        "{} {} is out of range",
+        name, value
+    )))
+}
+
+impl CheckedConversion for U256 {
+    fn to_u64(&self, name: &str) -> Result<u64> {
+        if *self > U256::from(u64::max_value()) {
+            return Err(out_of_range(name, self));
+        }
+        Ok(self.low_u64())
+    }
+
+    fn to_usize(&self, name: &str) -> Result<usize> {
+        usize::try_from(self.to_u64(name)?).map_err(|_| out_of_range(name, self))
+    }
+}
+
+/// Every value of `values` as a u64
+pub fn to_u64s(values: &[U256], name: &str) -> Result<Vec<u64>> {
+    values.iter().map(|value| value.to_u64(name)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_reject_values_beyond_64_bits() {
+        let max = U256::from(u64::max_value());
+        assert_eq!(max.to_u64("deadline").unwrap(), u64::max_value());
+        assert_eq!(U256::from(7).to_usize("query_size").unwrap(), 7);
+
+        let error = (max + 1).to_u64("deadline").unwrap_err();
+        assert!(format!("{:?}", error).contains("deadline 18446744073709551616 is out of range"));
+        assert!(U256::max_value().to_usize("query_size").is_err());
+        assert!(to_u64s(&[U256::from(1), max + 1], "query_array").is_err());
+        assert_eq!(
+            to_u64s(&[U256::from(1), max], "query_array").unwrap(),
+            vec![1, u64::max_value()]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! Clocks against which contract deadlines are checked.
+
+use super::error::*;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds the default clock waits after a deadline before considering
+/// it over
+pub const DEFAULT_SAFETY_MARGIN: u64 = 15;
+
+/// Current time, in seconds since the UNIX epoch, as far as contract
+/// deadlines are concerned
+pub trait Clock {
+    fn now(&self) -> Result<u64>;
+
+    /// Seconds waited after a deadline before considering it over
+    fn safety_margin(&self) -> u64 {
+        0
+    }
+
+    /// Whether `deadline` is over, i.e. a `claimVictoryByTime` sent now
+    /// would not revert
+    fn is_over(&self, deadline: u64) -> Result<bool> {
+        Ok(self.now()? > deadline.saturating_add(self.safety_margin()))
+    }
+}
+
+/// Local wall clock, which may run ahead of the chain. Deadlines are only
+/// considered over `safety_margin` seconds after they pass, so that blocks
+/// lagging behind the wall clock by less than that still see them over
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock {
+    pub safety_margin: u64,
+}
+
+impl SystemClock {
+    pub const fn new(safety_margin: u64) -> Self {
+        SystemClock { safety_margin }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Result<u64> {
+        Ok(SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .chain_err(|| "System time before UNIX_EPOCH")?
+            .as_secs())
+    }
+
+    fn safety_margin(&self) -> u64 {
+        self.safety_margin
+    }
+}
+
+/// Timestamp of the latest block known to the dispatcher. Deadlines are
+/// only considered over `safety_margin` seconds after they pass, so that
+/// the transaction still lands after the deadline if the next block
+/// timestamp is not far ahead
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BlockClock {
+    pub timestamp: u64,
+    pub safety_margin: u64,
+}
+
+impl BlockClock {
+    pub fn new(timestamp: u64, safety_margin: u64) -> Self {
+        BlockClock {
+            timestamp,
+            safety_margin,
+        }
+    }
+}
+
+impl Clock for BlockClock {
+    fn now(&self) -> Result<u64> {
+        Ok(self.timestamp)
+    }
+
+    fn safety_margin(&self) -> u64 {
+        self.safety_margin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_check_deadlines_against_block_timestamp() {
+        let deadline = 1_000;
+        assert!(!BlockClock::new(999, 0).is_over(deadline).unwrap());
+        assert!(!BlockClock::new(1_000, 0).is_over(deadline).unwrap());
+        assert!(BlockClock::new(1_001, 0).is_over(deadline).unwrap());
+    }
+
+    #[test]
+    fn it_should_apply_safety_margin() {
+        let deadline = 1_000;
+        assert!(!BlockClock::new(1_001, 15).is_over(deadline).unwrap());
+        assert!(!BlockClock::new(1_015, 15).is_over(deadline).unwrap());
+        assert!(BlockClock::new(1_016, 15).is_over(deadline).unwrap());
+        assert!(!BlockClock::new(u64::max_value(), 15)
+            .is_over(u64::max_value())
+            .unwrap());
+    }
+
+    #[test]
+    fn it_should_use_wall_clock() {
+        let clock = SystemClock::default();
+        assert!(clock.is_over(0).unwrap());
+        assert!(!clock.is_over(u64::max_value()).unwrap());
+        let now = clock.now().unwrap();
+        assert!(!SystemClock::new(3_600).is_over(now - 60).unwrap());
+    }
+}
@@ -0,0 +1,216 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! Compatibility of the machine manager we talk to. Hashes, step logs
+//! and proofs must be reproducible by the contracts' algorithm, so we
+//! only play disputes against machine manager versions known to produce
+//! them the way this crate expects, checked before the reactions that
+//! produce hashes. The answer is only trusted for a while, so that a
+//! machine manager upgraded in place is noticed.
+
+use super::dispatcher::Archive;
+use super::error::*;
+use super::ArchiveKey;
+use super::get_emulator_response;
+use clock::Clock;
+use emulator_service::{
+    GetVersionRequest, GetVersionResponse, SemanticVersion, EMULATOR_METHOD_VERSION,
+};
+
+use std::fmt;
+
+/// Seconds a version answer is trusted before the machine manager is
+/// asked again
+pub const VERSION_RECHECK_SECONDS: u64 = 600;
+
+/// Range of semantic versions, from `min` included to `max` excluded
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionRange {
+    pub min: (u32, u32, u32),
+    pub max: (u32, u32, u32),
+}
+
+/// Machine manager versions this crate plays disputes against
+pub const SUPPORTED_EMULATOR_VERSIONS: VersionRange = VersionRange {
+    min: (0, 8, 0),
+    max: (0, 9, 0),
+};
+
+impl VersionRange {
+    /// Whether `version` is in the range. Pre-releases of `min` come
+    /// before it, so they are not
+    pub fn contains(&self, version: &SemanticVersion) -> bool {
+        let triple = (version.major, version.minor, version.patch);
+        if triple == self.min && !version.pre_release.is_empty() {
+            return false;
+        }
+        self.min <= triple && triple < self.max
+    }
+}
+
+impl fmt::Display for VersionRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            ">={}.{}.{}, <{}.{}.{}",
+            self.min.0, self.min.1, self.min.2, self.max.0, self.max.1, self.max.2
+        )
+    }
+}
+
+/// The machine manager version is out of the supported range, so the
+/// hashes it produces may not be the ones the contracts expect
+#[derive(Debug, Clone, PartialEq)]
+pub struct IncompatibleEmulator {
+    pub machine_id: String,
+    pub version: SemanticVersion,
+    pub supported: VersionRange,
+}
+
+impl fmt::Display for IncompatibleEmulator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "machine manager of {} runs version {}, supported versions are {}",
+            self.machine_id, self.version, self.supported
+        )
+    }
+}
+
+impl std::error::Error for IncompatibleEmulator {}
+
+/// Archive key of the version asked for session `id` at time `now`; it
+/// changes every `VERSION_RECHECK_SECONDS`
+pub fn version_key(id: &str, now: u64) -> ArchiveKey {
+    ArchiveKey::Version {
+        id: id.to_string(),
+        period: now / VERSION_RECHECK_SECONDS,
+    }
+}
+
+/// Version of the machine manager serving session `id`, failing if it is
+/// out of `SUPPORTED_EMULATOR_VERSIONS`
+pub fn check_emulator_version(
+    archive: &Archive,
+    contract: String,
+    id: &str,
+    clock: &dyn Clock,
+) -> Result<SemanticVersion> {
+    let response: GetVersionResponse = get_emulator_response(
+        archive,
+        contract,
+        version_key(id, clock.now()?).to_string(),
+        EMULATOR_METHOD_VERSION,
+        GetVersionRequest {}.into(),
+    )?;
+    if !SUPPORTED_EMULATOR_VERSIONS.contains(&response.version) {
+        let incompatible = IncompatibleEmulator {
+            machine_id: id.to_string(),
+            version: response.version,
+            supported: SUPPORTED_EMULATOR_VERSIONS,
+        };
+        error!("Refusing to use the emulator: {}", incompatible);
+        return Err(incompatible)
+            .chain_err(|| format!("Incompatible machine manager for {}", id));
+    }
+    Ok(response.version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::BlockClock;
+    use emulator_service::EMULATOR_SERVICE_NAME;
+    use grpc::marshall::Marshaller;
+    use versioning;
+    use tests::MACHINEID;
+
+    fn version(major: u32, minor: u32, patch: u32, pre_release: &str) -> SemanticVersion {
+        SemanticVersion {
+            pre_release: pre_release.to_string(),
+            ..SemanticVersion::new(major, minor, patch)
+        }
+    }
+
+    #[test]
+    fn it_should_check_supported_range() {
+        let range = VersionRange {
+            min: (0, 8, 0),
+            max: (0, 9, 0),
+        };
+        assert!(range.contains(&version(0, 8, 0, "")));
+        assert!(range.contains(&version(0, 8, 7, "")));
+        assert!(range.contains(&version(0, 8, 1, "rc1")));
+        assert!(!range.contains(&version(0, 8, 0, "rc1")));
+        assert!(!range.contains(&version(0, 7, 9, "")));
+        assert!(!range.contains(&version(0, 9, 0, "")));
+        assert!(!range.contains(&version(1, 8, 0, "")));
+        assert_eq!(range.to_string(), ">=0.8.0, <0.9.0");
+        assert_eq!(version(0, 8, 1, "rc1").to_string(), "0.8.1-rc1");
+    }
+
+    #[test]
+    fn it_should_reject_unsupported_versions() {
+        let id = String::from(MACHINEID);
+        let clock = BlockClock::new(1_000, 0);
+        let key = version_key(&id, 1_000).to_string();
+        let mut archive = Archive::new().unwrap();
+        let error = check_emulator_version(&archive, "Test".into(), &id, &clock).unwrap_err();
+        match error.kind() {
+            ErrorKind::ResponseMissError(service, k, method, _) => {
+                assert_eq!(service, EMULATOR_SERVICE_NAME);
+                assert_eq!(k, &key);
+                assert_eq!(method, EMULATOR_METHOD_VERSION);
+            }
+            _ => panic!("expected a version request, got {:?}", error),
+        }
+
+        let response = |version: SemanticVersion| -> Vec<u8> {
+            let message: versioning::GetVersionResponse = GetVersionResponse { version }.into();
+            let marshaller: Box<dyn Marshaller<versioning::GetVersionResponse> + Sync + Send> =
+                Box::new(grpc::protobuf::MarshallerProtobuf);
+            marshaller.write(&message).unwrap()
+        };
+        archive.insert_response(key.clone(), Ok(response(version(0, 8, 2, ""))));
+        assert_eq!(
+            check_emulator_version(&archive, "Test".into(), &id, &clock).unwrap(),
+            version(0, 8, 2, "")
+        );
+        // the answer is only trusted for a while
+        let later = BlockClock::new(1_000 + VERSION_RECHECK_SECONDS, 0);
+        let error = check_emulator_version(&archive, "Test".into(), &id, &later).unwrap_err();
+        match error.kind() {
+            ErrorKind::ResponseMissError(_, k, _, _) => assert_ne!(k, &key),
+            _ => panic!("expected a new version request, got {:?}", error),
+        }
+
+        archive.insert_response(key, Ok(response(version(0, 7, 0, ""))));
+        let error = format!(
+            "{:?}",
+            check_emulator_version(&archive, "Test".into(), &id, &clock).unwrap_err()
+        );
+        assert!(error.contains("IncompatibleEmulator"), "{}", error);
+    }
+}
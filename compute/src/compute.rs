@@ -23,7 +23,7 @@
 // be used independently under the Apache v2 license. After this component is
 // rewritten, the entire component will be released under the Apache v2 license.
 
-use super::build_session_run_key;
+use super::ArchiveKey;
 use super::configuration::Concern;
 use super::dispatcher::{AddressField, Bytes32Field, String32Field, U256Field};
 use super::dispatcher::{Archive, DApp, Reaction};
@@ -31,68 +31,597 @@ use super::error::Result;
 use super::error::*;
 use super::ethabi::Token;
 use super::ethereum_types::{Address, H256, U256};
-use super::transaction;
+use super::keccak_hash::keccak;
 use super::transaction::TransactionRequest;
-use super::{Role, get_run_result};
-use vg::{VG, VGCtx, VGCtxParsed};
+use super::{cartesi_machine, end_session, get_run_result, new_session, Role};
+use checked::CheckedConversion;
+use compatibility::check_emulator_version;
+use clock::{BlockClock, Clock, SystemClock, DEFAULT_SAFETY_MARGIN};
+use urgency::{DefaultPolicy, TransactionPolicy, Urgency};
+use states::{ComputeState, VGState};
+use vg::{self, VG, VGCtx, VGCtxParsed};
 use emulator_service::SessionRunRequest;
+use super::forensic;
+use journal::Journal;
+use pretty::{compute_next, role_of, Progress};
+use run_progress::RunProgress;
+use feasibility::{self, DeadlineAlert};
+use input::InputDrive;
+use output::{get_output, Output, OutputRange};
 
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 pub struct Compute();
 
-// !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
-// these two structs and the From trait below shuld be
-// obtained from a simple derive
-// !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
-#[derive(Serialize, Deserialize)]
-pub struct ComputeCtxParsed(
-    AddressField,  // challenger
-    AddressField,  // claimer
-    U256Field,     // deadline
-    AddressField,  // machine
-    Bytes32Field,  // initialHash
-    U256Field,     // finalTime
-    Bytes32Field,  // claimedFinalHash
-    String32Field, // currentState
-);
-
-#[derive(Serialize, Debug)]
+/// The emulator session does not start from the hash the contract was
+/// instantiated with, so every hash it produces would lose the dispute
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitialHashMismatch {
+    pub machine_id: String,
+    /// `initialHash` of the contract
+    pub expected: H256,
+    /// Hash of the emulator session at cycle 0
+    pub found: H256,
+}
+
+impl fmt::Display for InitialHashMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "machine {} starts from hash {:?}, but the contract expects {:?}",
+            self.machine_id, self.found, self.expected
+        )
+    }
+}
+
+impl std::error::Error for InitialHashMismatch {}
+
+/// Pass control to the active verification game. Anything but a missing
+/// emulator response or a refused session means the game reached a state
+/// an honest party should never see, and the dispute is dumped for triage
+fn react_to_vg<P: DisputeParams>(
+    instance: &state::Instance,
+    vg_instance: &state::Instance,
+    archive: &Archive,
+    params: &InstanceParams<P>,
+) -> Result<Reaction> {
+    VG::react(vg_instance, archive, &None, params).map_err(|e| {
+        match e.kind() {
+            ErrorKind::ResponseMissError(..) | ErrorKind::ServiceNeedsRetry(..) => {}
+            _ if params.refused.get() => {}
+            _ => {
+                let causes: Vec<String> = e.iter().map(|cause| cause.to_string()).collect();
+                forensic::report(&causes.join(": "), instance, archive, params);
+            }
+        }
+        e
+    })
+}
+
+/// Output at the final time, proven against `hash`, when the claim covers
+/// an output range
+fn final_output<P: DisputeParams>(
+    archive: &Archive,
+    machine_id: &str,
+    ctx: &ComputeCtx,
+    hash: H256,
+    params: &P,
+) -> Result<Option<Output>> {
+    match params.output_range() {
+        Some(range) => {
+            let final_time = ctx.final_time.to_u64("Compute final time")?;
+            get_output(archive, "Compute", machine_id, final_time, range, hash).map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+/// Pretty view of a Compute instance, with its progress and its output
+/// once a final hash is claimed
+#[derive(Serialize)]
+struct PrettyCompute<'a> {
+    #[serde(flatten)]
+    ctx: &'a ComputeCtx,
+    progress: Progress,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<Output>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deadline_alert: Option<DeadlineAlert>,
+}
+
+/// Hash of the machine at the final time. While the emulator is still
+/// running, alert if its observed speed cannot finish before `deadline`
+/// on the clock of `params`
+fn final_hash<P: DisputeParams>(
+    archive: &Archive,
+    machine_id: &str,
+    ctx: &ComputeCtx,
+    deadline: u64,
+    params: &P,
+) -> Result<H256> {
+    let final_time = ctx.final_time.to_u64("Compute final time")?;
+    let sample_points: Vec<u64> = vec![0, final_time];
+    let request = SessionRunRequest {
+        session_id: machine_id.to_string(),
+        times: sample_points.clone(),
+    };
+    let archive_key = ArchiveKey::Run {
+        id: machine_id.to_string(),
+        times: sample_points,
+    };
+
+    trace!("Calculating final hash of machine {}", machine_id);
+    // have we sampled the final time?
+    let processed_result = get_run_result(
+        archive,
+        "Compute".to_string(),
+        &archive_key,
+        request.into(),
+        params.run_progress(),
+    )
+    .map_err(|e| {
+        if let ErrorKind::ServiceNeedsRetry(..) = e.kind() {
+            if let Some(alert) =
+                final_run_alert(&archive_key, machine_id, final_time, deadline, params)
+            {
+                warn!(
+                    "Deadline alert for Compute: {}",
+                    serde_json::to_string(&alert).unwrap()
+                );
+            }
+        }
+        e
+    })?;
+    processed_result
+        .hashes
+        .get(1)
+        .cloned()
+        .ok_or(Error::from("Run of the final time returned no hash"))
+}
+
+/// Alert for the run of the final time archived under `archive_key`
+fn final_run_alert<P: DisputeParams>(
+    archive_key: &ArchiveKey,
+    machine_id: &str,
+    final_time: u64,
+    deadline: u64,
+    params: &P,
+) -> Option<DeadlineAlert> {
+    let estimate = params.run_progress()?.estimate(archive_key)?;
+    feasibility::check(machine_id, &estimate, final_time, deadline, params.clock())
+}
+
+/// Hash of an already open emulator session at cycle 0
+fn initial_hash_of(
+    archive: &Archive,
+    machine_id: &str,
+    runs: Option<&RunProgress>,
+) -> Result<H256> {
+    let sample_points: Vec<u64> = vec![0];
+    let request = SessionRunRequest {
+        session_id: machine_id.to_string(),
+        times: sample_points.clone(),
+    };
+    let archive_key = ArchiveKey::Run {
+        id: machine_id.to_string(),
+        times: sample_points,
+    };
+    let processed_result = get_run_result(
+        archive,
+        "Compute".to_string(),
+        &archive_key,
+        request.into(),
+        runs,
+    )?;
+    processed_result
+        .hashes
+        .first()
+        .cloned()
+        .ok_or(Error::from("Run of cycle 0 returned no hash"))
+}
+
+/// Make sure the emulator session of the Compute `instance` can produce
+/// the hashes the contracts expect: the machine manager is compatible, the
+/// session is open with the input injected, and it starts from the
+/// contract's `initialHash`. Only the reactions that produce hashes need
+/// it, so that waiting on the other party never depends on the emulator:
+/// the verification game asks for it through
+/// `DisputeParams::prepare_session`
+fn prepare_session<P: DisputeParams>(
+    instance: &state::Instance,
+    archive: &Archive,
+    ctx: &ComputeCtx,
+    params: &P,
+) -> Result<()> {
+    let machine_id = params.machine_id();
+    // never risk hashes the contracts cannot reproduce
+    check_emulator_version(archive, "Compute".to_string(), machine_id, params.clock())?;
+
+    // open the machine session the first time it is needed, inject its
+    // input, and make sure it is the machine the contract
+    // refers to
+    let session_hash = match params.machine_request() {
+        Some(machine) => Some(new_session(
+            archive,
+            "Compute".to_string(),
+            machine_id.clone(),
+            machine.clone(),
+        )?),
+        None => None,
+    };
+    let local_initial_hash = match (session_hash, params.input_drive()) {
+        (Some(hash), None) => hash,
+        (_, Some(drive)) => {
+            drive.inject(archive, machine_id)?;
+            initial_hash_of(archive, machine_id, params.run_progress())?
+        }
+        (None, None) => initial_hash_of(archive, machine_id, params.run_progress())?,
+    };
+    if local_initial_hash != ctx.initial_hash {
+        let mismatch = InitialHashMismatch {
+            machine_id: machine_id.clone(),
+            expected: ctx.initial_hash,
+            found: local_initial_hash,
+        };
+        error!("Refusing to play Compute (index {}): {}", instance.index, mismatch);
+        return Err(mismatch)
+            .chain_err(|| format!("Refusing to play Compute (index {})", instance.index));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Debug, ContractCtx)]
+#[ctx(parsed = "ComputeCtxParsed")]
 pub struct ComputeCtx {
+    #[ctx(field = "AddressField")]
     pub challenger: Address,
+    #[ctx(field = "AddressField")]
     pub claimer: Address,
+    #[ctx(field = "U256Field")]
     pub deadline: U256,
+    #[ctx(field = "AddressField")]
     pub machine: Address,
+    #[ctx(field = "Bytes32Field")]
     pub initial_hash: H256,
+    #[ctx(field = "U256Field")]
     pub final_time: U256,
+    #[ctx(field = "Bytes32Field")]
     pub claimed_final_hash: H256,
-    pub current_state: String,
+    #[ctx(field = "String32Field", parse)]
+    pub current_state: ComputeState,
+}
+
+/// Clock of parameters without a block timestamp: the wall clock, waiting
+/// the default safety margin after deadlines
+static DEFAULT_CLOCK: SystemClock = SystemClock::new(DEFAULT_SAFETY_MARGIN);
+
+/// Parameters shared by the Compute, VG and Partition DApps
+pub trait DisputeParams {
+    /// Emulator session of the machine under dispute. Sessions opened from
+    /// a machine request are named after it, see `InstanceParams`
+    fn machine_id(&self) -> &String;
+
+    /// Clock against which contract deadlines are checked
+    fn clock(&self) -> &dyn Clock {
+        &DEFAULT_CLOCK
+    }
+
+    /// Transaction settings as the deadline approaches
+    fn transaction_policy(&self) -> &dyn TransactionPolicy {
+        &DefaultPolicy
+    }
+
+    /// Machine from which the emulator session is opened while the
+    /// instance is active, and ended once it is over. Without one, the
+    /// session is assumed to be managed by someone else
+    fn machine_request(&self) -> Option<&cartesi_machine::MachineRequest> {
+        None
+    }
+
+    /// Directory where forensic bundles are written, besides being logged
+    fn forensic_dir(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Memory range whose contents at the final time are covered by the
+    /// claim, besides the final hash. Without one, only the hash is
+    fn output_range(&self) -> Option<OutputRange> {
+        None
+    }
+
+    /// Input injected into the emulator session at cycle 0, before its
+    /// hash is checked against the contract's `initialHash`. Answers are
+    /// archived by session, so a session managed by someone else must only
+    /// ever hold one input
+    fn input_drive(&self) -> Option<&InputDrive> {
+        None
+    }
+
+    /// Whether to start the runs the next partition rounds may need
+    /// while waiting for the other party
+    fn prefetch_runs(&self) -> bool {
+        false
+    }
+
+    /// Journal where the reactions to each dispute are recorded. Without
+    /// one, nothing is recorded, and forensic bundles are written on
+    /// every reaction that sees the dispute lost
+    fn journal(&self) -> Option<&Journal> {
+        None
+    }
+
+    /// Progress samples of the emulator runs in progress. Without them,
+    /// nothing estimates when a run completes
+    fn run_progress(&self) -> Option<&RunProgress> {
+        None
+    }
+
+    /// Make sure the emulator session can produce the hashes the
+    /// contracts expect, before any reaction that produces hashes.
+    /// Without a Compute instance there is nothing to check it against
+    fn prepare_session(&self, _archive: &Archive) -> Result<()> {
+        Ok(())
+    }
 }
 
-impl From<ComputeCtxParsed> for ComputeCtx {
-    fn from(parsed: ComputeCtxParsed) -> ComputeCtx {
-        ComputeCtx {
-            challenger: parsed.0.value,
-            claimer: parsed.1.value,
-            deadline: parsed.2.value,
-            machine: parsed.3.value,
-            initial_hash: parsed.4.value,
-            final_time: parsed.5.value,
-            claimed_final_hash: parsed.6.value,
-            current_state: parsed.7.value,
+/// A bare machine id checks deadlines against the default clock
+impl DisputeParams for String {
+    fn machine_id(&self) -> &String {
+        self
+    }
+}
+
+pub struct ComputeParams {
+    pub machine_id: String,
+    pub clock: Box<dyn Clock>,
+    pub policy: Box<dyn TransactionPolicy>,
+    pub machine: Option<cartesi_machine::MachineRequest>,
+    pub forensic_dir: Option<PathBuf>,
+    pub output: Option<OutputRange>,
+    pub input: Option<InputDrive>,
+    pub prefetch: bool,
+    pub journal: Arc<Journal>,
+    pub runs: Arc<RunProgress>,
+}
+
+impl ComputeParams {
+    /// Check deadlines against the local wall clock, waiting the default
+    /// safety margin after them. Nodes that know the timestamp of the
+    /// block the contract states were fetched at should pass it through
+    /// `with_block_timestamp` instead
+    pub fn new(machine_id: String) -> Self {
+        ComputeParams::with_clock(machine_id, DEFAULT_CLOCK)
+    }
+
+    /// Check deadlines against the latest block timestamp, waiting
+    /// `safety_margin` seconds after a deadline before claiming victory
+    pub fn with_block_timestamp(machine_id: String, timestamp: u64, safety_margin: u64) -> Self {
+        ComputeParams::with_clock(machine_id, BlockClock::new(timestamp, safety_margin))
+    }
+
+    fn with_clock<C: Clock + 'static>(machine_id: String, clock: C) -> Self {
+        ComputeParams {
+            machine_id,
+            clock: Box::new(clock),
+            policy: Box::new(DefaultPolicy),
+            machine: None,
+            forensic_dir: None,
+            output: None,
+            input: None,
+            prefetch: false,
+            journal: Arc::new(Journal::new()),
+            runs: Arc::new(RunProgress::new()),
         }
     }
+
+    /// Open and end the emulator session from `machine`
+    pub fn with_machine(mut self, machine: cartesi_machine::MachineRequest) -> Self {
+        self.machine = Some(machine);
+        self
+    }
+
+    /// Write the forensic bundles of lost or buggy disputes to `dir`
+    pub fn with_forensic_dir(mut self, dir: PathBuf) -> Self {
+        self.forensic_dir = Some(dir);
+        self
+    }
+
+    /// Claim and confirm the contents of `range` along with the final hash
+    pub fn with_output(mut self, range: OutputRange) -> Self {
+        self.output = Some(range);
+        self
+    }
+
+    /// Inject `drive` into the emulator session before playing
+    pub fn with_input_drive(mut self, drive: InputDrive) -> Self {
+        self.input = Some(drive);
+        self
+    }
+
+    /// Keep the emulator ahead of the partition, running the hashes of
+    /// every query the next round may bring
+    pub fn with_prefetch(mut self) -> Self {
+        self.prefetch = true;
+        self
+    }
+
+    /// Record the reactions in `journal` rather than in one of our own,
+    /// so it outlives the parameters or is shared with others
+    pub fn with_journal(mut self, journal: Arc<Journal>) -> Self {
+        self.journal = journal;
+        self
+    }
+
+    /// Sample the runs in progress into `runs` rather than into samples of
+    /// our own, so they outlive the parameters
+    pub fn with_run_progress(mut self, runs: Arc<RunProgress>) -> Self {
+        self.runs = runs;
+        self
+    }
 }
 
-impl DApp<String> for Compute {
-    /// React to the compute contract, submitting solutions, confirming
-    /// or challenging them when appropriate
-    fn react(
+impl DisputeParams for ComputeParams {
+    fn machine_id(&self) -> &String {
+        &self.machine_id
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    fn transaction_policy(&self) -> &dyn TransactionPolicy {
+        self.policy.as_ref()
+    }
+
+    fn machine_request(&self) -> Option<&cartesi_machine::MachineRequest> {
+        self.machine.as_ref()
+    }
+
+    fn forensic_dir(&self) -> Option<&Path> {
+        self.forensic_dir.as_ref().map(|dir| dir.as_path())
+    }
+
+    fn output_range(&self) -> Option<OutputRange> {
+        self.output
+    }
+
+    fn input_drive(&self) -> Option<&InputDrive> {
+        self.input.as_ref()
+    }
+
+    fn prefetch_runs(&self) -> bool {
+        self.prefetch
+    }
+
+    fn journal(&self) -> Option<&Journal> {
+        Some(&self.journal)
+    }
+
+    fn run_progress(&self) -> Option<&RunProgress> {
+        Some(&self.runs)
+    }
+}
+
+/// Parameters of a single Compute instance. A session opened from a
+/// machine request belongs to the instance, so its id is derived from the
+/// instance and its input drive: another instance or input of the same
+/// machine never reuses its archived answers, and ending it leaves the
+/// other sessions open. Sessions managed by someone else keep the id they
+/// were given, and must not be shared by instances of different inputs
+pub struct InstanceParams<'a, P: 'a> {
+    params: &'a P,
+    instance: &'a state::Instance,
+    session_id: String,
+    /// Whether the session was refused while reacting, which is no bug of
+    /// the dispute
+    refused: Cell<bool>,
+}
+
+impl<'a, P: DisputeParams> InstanceParams<'a, P> {
+    pub fn new(instance: &'a state::Instance, params: &'a P) -> Self {
+        let session_id = match params.machine_request() {
+            Some(_) => instance_session_id(instance, params.machine_id(), params.input_drive()),
+            None => params.machine_id().clone(),
+        };
+        InstanceParams {
+            params,
+            instance,
+            session_id,
+            refused: Cell::new(false),
+        }
+    }
+}
+
+/// Id of the session of `machine_id` opened for the Compute `instance`,
+/// with `drive` as its input
+fn instance_session_id(
+    instance: &state::Instance,
+    machine_id: &str,
+    drive: Option<&InputDrive>,
+) -> String {
+    let mut index = [0u8; 32];
+    instance.index.to_big_endian(&mut index);
+    let mut seed = Vec::with_capacity(104);
+    seed.extend_from_slice(instance.concern.contract_address.as_bytes());
+    seed.extend_from_slice(instance.concern.user_address.as_bytes());
+    seed.extend_from_slice(&index);
+    if let Some(drive) = drive {
+        seed.extend_from_slice(drive.fingerprint().as_bytes());
+    }
+    format!("{}_{:x}", machine_id, keccak(&seed))
+}
+
+impl<'a, P: DisputeParams> DisputeParams for InstanceParams<'a, P> {
+    fn machine_id(&self) -> &String {
+        &self.session_id
+    }
+
+    fn clock(&self) -> &dyn Clock {
+        self.params.clock()
+    }
+
+    fn transaction_policy(&self) -> &dyn TransactionPolicy {
+        self.params.transaction_policy()
+    }
+
+    fn machine_request(&self) -> Option<&cartesi_machine::MachineRequest> {
+        self.params.machine_request()
+    }
+
+    fn forensic_dir(&self) -> Option<&Path> {
+        self.params.forensic_dir()
+    }
+
+    fn output_range(&self) -> Option<OutputRange> {
+        self.params.output_range()
+    }
+
+    fn input_drive(&self) -> Option<&InputDrive> {
+        self.params.input_drive()
+    }
+
+    fn prefetch_runs(&self) -> bool {
+        self.params.prefetch_runs()
+    }
+
+    fn journal(&self) -> Option<&Journal> {
+        self.params.journal()
+    }
+
+    fn run_progress(&self) -> Option<&RunProgress> {
+        self.params.run_progress()
+    }
+
+    fn prepare_session(&self, archive: &Archive) -> Result<()> {
+        let parsed: ComputeCtxParsed =
+            serde_json::from_str(&self.instance.json_data).chain_err(|| {
+                format!(
+                    "Could not parse compute instance json_data: {}",
+                    &self.instance.json_data
+                )
+            })?;
+        let ctx = ComputeCtx::try_from(parsed)?;
+        prepare_session(self.instance, archive, &ctx, self).map_err(|e| {
+            match e.kind() {
+                ErrorKind::ResponseMissError(..) | ErrorKind::ServiceNeedsRetry(..) => {}
+                _ => self.refused.set(true),
+            }
+            e
+        })
+    }
+}
+
+impl Compute {
+    fn play<P: DisputeParams>(
         instance: &state::Instance,
         archive: &Archive,
-        _post_payload: &Option<String>,
-        machine_id: &String,
+        params: &InstanceParams<P>,
     ) -> Result<Reaction> {
+        let machine_id = params.machine_id();
         // get context (state) of the compute instance
         let parsed: ComputeCtxParsed =
             serde_json::from_str(&instance.json_data).chain_err(|| {
@@ -101,13 +630,23 @@ impl DApp<String> for Compute {
                     &instance.json_data
                 )
             })?;
-        let ctx: ComputeCtx = parsed.into();
+        let ctx = ComputeCtx::try_from(parsed)?;
         trace!("Context for compute (index {}) {:?}", instance.index, ctx);
 
         // these states should not occur as they indicate an innactive instance,
         // but it is possible that the blockchain state changed between queries
-        match ctx.current_state.as_ref() {
-            "ClaimerMissedDeadline" | "ChallengerWon" | "ClaimerWon" | "ConsensusResult" => {
+        match ctx.current_state {
+            ComputeState::ClaimerMissedDeadline
+            | ComputeState::ChallengerWon
+            | ComputeState::ClaimerWon
+            | ComputeState::ConsensusResult => {
+                // the machine will not be needed anymore
+                if params.machine_request().is_some() {
+                    end_session(archive, machine_id.clone())?;
+                }
+                if let Some(journal) = params.journal() {
+                    journal.forget(instance);
+                }
                 return Ok(Reaction::Idle);
             }
             _ => {}
@@ -125,35 +664,35 @@ impl DApp<String> for Compute {
         };
         trace!("Role played (index {}) is: {:?}", instance.index, role);
 
+        // the closer the deadline, the more aggressive our transactions
+        let deadline = ctx.deadline.to_u64("Compute deadline")?;
+        let urgency = Urgency::of(deadline, params.clock())?;
+        let policy = params.transaction_policy();
+        if urgency != Urgency::Normal {
+            debug!(
+                "{:?} deadline for Compute (index {}): {}",
+                urgency, instance.index, ctx.deadline
+            );
+        }
+
         match role {
-            Role::Claimer => match ctx.current_state.as_ref() {
-                "WaitingConfirmation" => {
+            Role::Claimer => match ctx.current_state {
+                ComputeState::WaitingConfirmation => {
                     return win_by_deadline_or_idle(
                         &instance.concern,
                         instance.index,
-                        ctx.deadline.as_u64(),
+                        deadline,
+                        params,
                     );
                 }
-                "WaitingClaim" => {
+                ComputeState::WaitingClaim => {
+                    prepare_session(instance, archive, &ctx, params)?;
                     // machine id
                     let id = machine_id.clone();
-                    let sample_points: Vec<u64> = vec![0, ctx.final_time.as_u64()];
-                    let request = SessionRunRequest {
-                        session_id: id.clone(),
-                        times: sample_points.clone(),
-                    };
-                    let archive_key = build_session_run_key(id.clone(), sample_points.clone());
-
-                    trace!("Calculating final hash of machine {}", id);
-                    // have we sampled the final time?
-                    let processed_result = get_run_result(
-                        archive,
-                        "Compute".to_string(),
-                        archive_key,
-                        request.into(),
-                    )?;
-
-                    let hash = processed_result.hashes[1];
+                    let hash = final_hash(archive, &id, &ctx, deadline, params)?;
+                    if let Some(output) = final_output(archive, &id, &ctx, hash, params)? {
+                        info!("Claiming output {:?} for {}", output, id);
+                    }
 
                     info!("Submitting claim for Compute (index: {}, hash: {:?})", instance.index, hash);
                     let request = TransactionRequest {
@@ -170,12 +709,12 @@ impl DApp<String> for Compute {
                             Token::Uint(instance.index),
                             Token::FixedBytes(hash.0.to_vec()),
                         ],
-                        gas: None,
-                        strategy: transaction::Strategy::Simplest,
+                        gas: policy.gas(urgency),
+                        strategy: policy.strategy(urgency),
                     };
                     return Ok(Reaction::Transaction(request));
                 }
-                "WaitingChallenge" => {
+                ComputeState::WaitingChallenge => {
                     // we inspect the verification contract
                     let vg_instance = instance.sub_instances.get(0).ok_or(Error::from(
                         ErrorKind::InvalidContractState(format!(
@@ -190,10 +729,10 @@ impl DApp<String> for Compute {
                                 &vg_instance.json_data
                             )
                         })?;
-                    let vg_ctx: VGCtx = vg_parsed.into();
+                    let vg_ctx = VGCtx::try_from(vg_parsed)?;
 
-                    match vg_ctx.current_state.as_ref() {
-                        "FinishedClaimerWon" => {
+                    match vg_ctx.current_state {
+                        VGState::FinishedClaimerWon => {
                             // claim victory in compute contract
                             info!("Claiming victory for Compute (index: {})", instance.index);
                             let request = TransactionRequest {
@@ -202,53 +741,54 @@ impl DApp<String> for Compute {
                                 value: U256::from(0),
                                 function: "winByVG".into(),
                                 data: vec![Token::Uint(instance.index)],
-                                gas: None,
-                                strategy: transaction::Strategy::Simplest,
+                                gas: policy.gas(urgency),
+                                strategy: policy.strategy(urgency),
                             };
                             return Ok(Reaction::Transaction(request));
                         }
-                        "FinishedChallengerWon" => {
+                        VGState::FinishedChallengerWon => {
                             error!("we lost a verification game {:?}", vg_ctx);
+                            forensic::report(
+                                &format!("lost the verification game as {:?}", role),
+                                instance,
+                                archive,
+                                params,
+                            );
                             return Ok(Reaction::Idle);
                         }
-                        _ => {
-                            // verification game is still active,
-                            // pass control to the appropriate dapp
-                            return VG::react(vg_instance, archive, &None, machine_id);
+                        VGState::WaitPartition | VGState::WaitMemoryProveValues => {
+                            // verification game is still active, pass
+                            // control to the appropriate dapp, which only
+                            // prepares the session to produce hashes
+                            return react_to_vg(instance, vg_instance, archive, params);
                         }
                     }
                 }
-                _ => {
-                    return Err(Error::from(ErrorKind::InvalidContractState(format!(
-                        "Unknown current state {}",
-                        ctx.current_state
-                    ))));
+                ComputeState::ClaimerMissedDeadline
+                | ComputeState::ChallengerWon
+                | ComputeState::ClaimerWon
+                | ComputeState::ConsensusResult => {
+                    return Ok(Reaction::Idle);
                 }
             },
-            Role::Challenger => match ctx.current_state.as_ref() {
-                "WaitingConfirmation" => {
+            Role::Challenger => match ctx.current_state {
+                ComputeState::WaitingConfirmation => {
                     // here goes the calculation of the final hash
                     // to check the claim and potentialy raise challenge
+                    prepare_session(instance, archive, &ctx, params)?;
                     // machine id
                     let id = machine_id.clone();
-                    let sample_points: Vec<u64> = vec![0, ctx.final_time.as_u64()];
-                    let request = SessionRunRequest {
-                        session_id: id.clone(),
-                        times: sample_points.clone(),
-                    };
-                    let archive_key = build_session_run_key(id.clone(), sample_points.clone());
-
-                    trace!("Calculating final hash of machine {}", id);
-                    // have we sampled the final time?
-                    let processed_result = get_run_result(
-                        archive,
-                        "Compute".to_string(),
-                        archive_key,
-                        request.into(),
-                    )?;
-
-                    let hash = processed_result.hashes[1];
+                    let hash = final_hash(archive, &id, &ctx, deadline, params)?;
                     if hash == ctx.claimed_final_hash {
+                        // the hash covers the output, so failing to read
+                        // it never keeps us from confirming
+                        match final_output(archive, &id, &ctx, hash, params) {
+                            Ok(Some(output)) => {
+                                info!("Confirming output {:?} for {}", output, id)
+                            }
+                            Ok(None) => {}
+                            Err(e) => warn!("Confirming without the output of {}: {}", id, e),
+                        }
                         info!("Confirming final hash {:?} for {}", hash, id);
                         let request = TransactionRequest {
                             contract_name: None, // Name not needed, is concern
@@ -256,8 +796,8 @@ impl DApp<String> for Compute {
                             value: U256::from(0),
                             function: "confirm".into(),
                             data: vec![Token::Uint(instance.index)],
-                            gas: None,
-                            strategy: transaction::Strategy::Simplest,
+                            gas: policy.gas(urgency),
+                            strategy: policy.strategy(urgency),
                         };
                         return Ok(Reaction::Transaction(request));
                     } else {
@@ -271,21 +811,22 @@ impl DApp<String> for Compute {
                             value: U256::from(0),
                             function: "challenge".into(),
                             data: vec![Token::Uint(instance.index)],
-                            gas: None,
-                            strategy: transaction::Strategy::Simplest,
+                            gas: policy.gas(urgency),
+                            strategy: policy.strategy(urgency),
                         };
 
                         return Ok(Reaction::Transaction(request));
                     }
                 }
-                "WaitingClaim" => {
+                ComputeState::WaitingClaim => {
                     return win_by_deadline_or_idle(
                         &instance.concern,
                         instance.index,
-                        ctx.deadline.as_u64(),
+                        deadline,
+                        params,
                     );
                 }
-                "WaitingChallenge" => {
+                ComputeState::WaitingChallenge => {
                     // we inspect the verification contract
                     let vg_instance = instance.sub_instances.get(0).ok_or(Error::from(
                         ErrorKind::InvalidContractState(format!(
@@ -300,10 +841,10 @@ impl DApp<String> for Compute {
                                 &vg_instance.json_data
                             )
                         })?;
-                    let vg_ctx: VGCtx = vg_parsed.into();
+                    let vg_ctx = VGCtx::try_from(vg_parsed)?;
 
-                    match vg_ctx.current_state.as_ref() {
-                        "FinishedChallengerWon" => {
+                    match vg_ctx.current_state {
+                        VGState::FinishedChallengerWon => {
                             info!("Claiming victory for Compute (index: {})", instance.index);
                             // claim victory in compute contract
                             let request = TransactionRequest {
@@ -312,37 +853,64 @@ impl DApp<String> for Compute {
                                 value: U256::from(0),
                                 function: "winByVG".into(),
                                 data: vec![Token::Uint(instance.index)],
-                                gas: None,
-                                strategy: transaction::Strategy::Simplest,
+                                gas: policy.gas(urgency),
+                                strategy: policy.strategy(urgency),
                             };
                             return Ok(Reaction::Transaction(request));
                         }
-                        "FinishedClaimerWon" => {
+                        VGState::FinishedClaimerWon => {
                             error!("we lost a verification game {:?}", vg_ctx);
+                            forensic::report(
+                                &format!("lost the verification game as {:?}", role),
+                                instance,
+                                archive,
+                                params,
+                            );
                             return Ok(Reaction::Idle);
                         }
-                        _ => {
-                            // verification game is still active,
-                            // pass control to the appropriate dapp
-                            return VG::react(vg_instance, archive, &None, machine_id);
+                        VGState::WaitPartition | VGState::WaitMemoryProveValues => {
+                            // verification game is still active, pass
+                            // control to the appropriate dapp, which only
+                            // prepares the session to produce hashes
+                            return react_to_vg(instance, vg_instance, archive, params);
                         }
                     }
                 }
-                _ => {
-                    return Err(Error::from(ErrorKind::InvalidContractState(format!(
-                        "Unknown current state {}",
-                        ctx.current_state
-                    ))));
+                ComputeState::ClaimerMissedDeadline
+                | ComputeState::ChallengerWon
+                | ComputeState::ClaimerWon
+                | ComputeState::ConsensusResult => {
+                    return Ok(Reaction::Idle);
                 }
             },
         }
     }
 
+}
+
+impl<P: DisputeParams> DApp<P> for Compute {
+    /// React to the compute contract, submitting solutions, confirming
+    /// or challenging them when appropriate
+    fn react(
+        instance: &state::Instance,
+        archive: &Archive,
+        _post_payload: &Option<String>,
+        params: &P,
+    ) -> Result<Reaction> {
+        let params = InstanceParams::new(instance, params);
+        let reaction = Compute::play(instance, archive, &params)?;
+        if let Some(journal) = params.journal() {
+            journal.record(instance, &reaction);
+        }
+        Ok(reaction)
+    }
+
     fn get_pretty_instance(
         instance: &state::Instance,
         archive: &Archive,
-        machine_id: &String,
+        params: &P,
     ) -> Result<state::Instance> {
+        let params = &InstanceParams::new(instance, params);
         // get context (state) of the compute instance
         let parsed: ComputeCtxParsed =
             serde_json::from_str(&instance.json_data).chain_err(|| {
@@ -351,8 +919,66 @@ impl DApp<String> for Compute {
                     &instance.json_data
                 )
             })?;
-        let ctx: ComputeCtx = parsed.into();
-        let json_data = serde_json::to_string(&ctx).unwrap();
+        let ctx = ComputeCtx::try_from(parsed)?;
+        // the output is only known once claimed, and only the claim of
+        // the winner is settled
+        let output = match ctx.current_state {
+            ComputeState::WaitingConfirmation
+            | ComputeState::WaitingChallenge
+            | ComputeState::ClaimerWon
+            | ComputeState::ConsensusResult => final_output(
+                archive,
+                params.machine_id(),
+                &ctx,
+                ctx.claimed_final_hash,
+                params,
+            )
+            .unwrap_or_else(|e| {
+                debug!("No output for Compute (index {}): {}", instance.index, e);
+                None
+            }),
+            _ => None,
+        };
+        let vg_next = instance.sub_instances.get(0).and_then(|sub| vg::next_to_act(sub));
+        let mut progress = Progress::new(
+            role_of(instance.concern.user_address, ctx.claimer, ctx.challenger),
+            compute_next(ctx.current_state, vg_next),
+        );
+        let mut deadline_alert = None;
+        if let ComputeState::WaitingClaim | ComputeState::WaitingConfirmation = ctx.current_state {
+            // while challenged, the deadline that matters is the game's
+            progress = progress.with_deadline(ctx.deadline, params.clock());
+            if let (Ok(final_time), Ok(deadline)) = (
+                ctx.final_time.to_u64("Compute final time"),
+                ctx.deadline.to_u64("Compute deadline"),
+            ) {
+                let archive_key = ArchiveKey::Run {
+                    id: params.machine_id().clone(),
+                    times: vec![0, final_time],
+                };
+                deadline_alert = final_run_alert(
+                    &archive_key,
+                    params.machine_id(),
+                    final_time,
+                    deadline,
+                    params,
+                );
+            }
+        }
+        progress.last_reaction = params
+            .journal()
+            .and_then(|journal| journal.last_reaction(instance));
+        progress.runs = params
+            .run_progress()
+            .map(|runs| runs.estimates(params.machine_id()))
+            .unwrap_or_default();
+        let json_data = serde_json::to_string(&PrettyCompute {
+            ctx: &ctx,
+            progress,
+            output,
+            deadline_alert,
+        })
+        .unwrap();
 
         // get context (state) of the sub instances
 
@@ -360,7 +986,7 @@ impl DApp<String> for Compute {
 
         for sub in &instance.sub_instances {
             pretty_sub_instances.push(Box::new(
-                VG::get_pretty_instance(sub, archive, machine_id).unwrap(),
+                VG::get_pretty_instance(sub, archive, params).unwrap(),
             ))
         }
 
@@ -377,27 +1003,28 @@ impl DApp<String> for Compute {
     }
 }
 
-pub fn win_by_deadline_or_idle(
+/// Claim victory by time once the other party missed `deadline`, with
+/// the transaction settings the policy of `params` gives the claim
+pub fn win_by_deadline_or_idle<P: DisputeParams>(
     concern: &Concern,
     index: U256,
     deadline: u64,
+    params: &P,
 ) -> Result<Reaction> {
-    let current_time = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .chain_err(|| "System time before UNIX_EPOCH")?
-        .as_secs();
-
+    let clock = params.clock();
     // if other party missed the deadline
-    if current_time > deadline {
+    if clock.is_over(deadline)? {
         info!("Claiming victory by time (index: {})", index);
+        let urgency = Urgency::of(deadline, clock)?;
+        let policy = params.transaction_policy();
         let request = TransactionRequest {
             contract_name: None, // Name not needed, is concern
             concern: concern.clone(),
             value: U256::from(0),
             function: "claimVictoryByTime".into(),
             data: vec![Token::Uint(index)],
-            gas: None,
-            strategy: transaction::Strategy::Simplest,
+            gas: policy.gas(urgency),
+            strategy: policy.strategy(urgency),
         };
         return Ok(Reaction::Transaction(request));
     } else {
@@ -28,9 +28,13 @@
 //! together with the conversion functions from the automatically
 //! generated types.
 
+use super::error::*;
 use super::ethereum_types::H256;
 use super::grpc::marshall::Marshaller;
-use super::{cartesi_machine, machine_manager};
+use super::{cartesi_machine, machine_manager, versioning};
+
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
 
 pub const EMULATOR_SERVICE_NAME: &'static str = "emulator";
 pub const EMULATOR_METHOD_NEW: &'static str =
@@ -49,6 +53,8 @@ pub const EMULATOR_METHOD_PROOF: &'static str =
     "/CartesiMachineManager.MachineManager/SessionGetProof";
 pub const EMULATOR_METHOD_END: &'static str =
     "/CartesiMachineManager.MachineManager/EndSession";
+pub const EMULATOR_METHOD_VERSION: &'static str =
+    "/CartesiMachineManager.MachineManager/GetVersion";
 
 /// Representation of a request for new session
 #[derive(Debug, Clone)]
@@ -90,20 +96,34 @@ pub struct SessionRunResult {
     pub hashes: Vec<H256>,
 }
 
-impl From<machine_manager::SessionRunResponse_oneof_run_oneof>
+/// Decode a hash sent by the emulator, which must be exactly 32 bytes long
+fn to_hash(data: &[u8], field: &str) -> Result<H256> {
+    if data.len() != 32 {
+        return Err(Error::from(format!(
+            "{} has {} bytes instead of 32",
+            field,
+            data.len()
+        )));
+    }
+    Ok(H256::from_slice(data))
+}
+
+impl TryFrom<machine_manager::SessionRunResponse_oneof_run_oneof>
     for SessionRunResponseOneOf
 {
-    fn from(
+    type Error = Error;
+
+    fn try_from(
         one_of: machine_manager::SessionRunResponse_oneof_run_oneof,
-    ) -> Self {
-        match one_of {
+    ) -> Result<Self> {
+        Ok(match one_of {
             machine_manager::SessionRunResponse_oneof_run_oneof::progress(
                 s,
             ) => SessionRunResponseOneOf::RunProgress(s.into()),
             machine_manager::SessionRunResponse_oneof_run_oneof::result(p) => {
-                SessionRunResponseOneOf::RunResult(p.into())
+                SessionRunResponseOneOf::RunResult(p.try_into()?)
             }
-        }
+        })
     }
 }
 impl From<SessionRunResponseOneOf>
@@ -163,24 +183,31 @@ impl From<SessionRunResult> for machine_manager::SessionRunResult {
     }
 }
 
-impl From<machine_manager::SessionRunResult> for SessionRunResult {
-    fn from(run_result: machine_manager::SessionRunResult) -> Self {
-        SessionRunResult {
+impl TryFrom<machine_manager::SessionRunResult> for SessionRunResult {
+    type Error = Error;
+
+    fn try_from(run_result: machine_manager::SessionRunResult) -> Result<Self> {
+        Ok(SessionRunResult {
             hashes: run_result
                 .hashes
                 .into_vec()
                 .into_iter()
-                .map(|hash| H256::from_slice(&hash.data))
-                .collect(),
-        }
+                .map(|hash| to_hash(&hash.data, "run result hash"))
+                .collect::<Result<Vec<H256>>>()?,
+        })
     }
 }
 
-impl From<machine_manager::SessionRunResponse> for SessionRunResponse {
-    fn from(response: machine_manager::SessionRunResponse) -> Self {
-        SessionRunResponse {
-            one_of: response.run_oneof.unwrap().into(),
-        }
+impl TryFrom<machine_manager::SessionRunResponse> for SessionRunResponse {
+    type Error = Error;
+
+    fn try_from(response: machine_manager::SessionRunResponse) -> Result<Self> {
+        Ok(SessionRunResponse {
+            one_of: response
+                .run_oneof
+                .ok_or(Error::from("run_oneof not found"))?
+                .try_into()?,
+        })
     }
 }
 
@@ -190,11 +217,13 @@ pub struct NewSessionResponse {
     pub hash: H256,
 }
 
-impl From<cartesi_machine::Hash> for NewSessionResponse {
-    fn from(response: cartesi_machine::Hash) -> Self {
-        NewSessionResponse {
-            hash: H256::from_slice(&response.data),
-        }
+impl TryFrom<cartesi_machine::Hash> for NewSessionResponse {
+    type Error = Error;
+
+    fn try_from(response: cartesi_machine::Hash) -> Result<Self> {
+        Ok(NewSessionResponse {
+            hash: to_hash(&response.data, "session hash")?,
+        })
     }
 }
 
@@ -234,33 +263,37 @@ pub struct MerkleTreeProof {
     pub root_hash: H256,
 }
 
-impl From<cartesi_machine::MerkleTreeProof> for MerkleTreeProof {
-    fn from(proof: cartesi_machine::MerkleTreeProof) -> Self {
-        MerkleTreeProof {
+impl TryFrom<cartesi_machine::MerkleTreeProof> for MerkleTreeProof {
+    type Error = Error;
+
+    fn try_from(proof: cartesi_machine::MerkleTreeProof) -> Result<Self> {
+        Ok(MerkleTreeProof {
             address: proof.target_address,
             log2_target_size: proof.log2_target_size,
             log2_root_size: proof.log2_root_size,
-            target_hash: H256::from_slice(
+            target_hash: to_hash(
                 &proof
                     .target_hash
                     .into_option()
-                    .expect("target hash not found")
+                    .ok_or(Error::from("target hash not found"))?
                     .data,
-            ),
+                "target hash",
+            )?,
             sibling_hashes: proof
                 .sibling_hashes
                 .into_vec()
                 .into_iter()
-                .map(|hash| H256::from_slice(&hash.data))
-                .collect(),
-            root_hash: H256::from_slice(
+                .map(|hash| to_hash(&hash.data, "sibling hash"))
+                .collect::<Result<Vec<H256>>>()?,
+            root_hash: to_hash(
                 &proof
                     .root_hash
                     .into_option()
-                    .expect("root hash not found")
+                    .ok_or(Error::from("root hash not found"))?
                     .data,
-            ),
-        }
+                "root hash",
+            )?,
+        })
     }
 }
 
@@ -319,19 +352,26 @@ fn from_bytes(input: [u8; 8]) -> Vec<u8> {
     ]
 }
 
-impl From<cartesi_machine::Access> for Access {
-    fn from(access: cartesi_machine::Access) -> Self {
-        let proof: MerkleTreeProof =
-            access.proof.into_option().expect("proof not found").into();
-        Access {
+impl TryFrom<cartesi_machine::Access> for Access {
+    type Error = Error;
+
+    fn try_from(access: cartesi_machine::Access) -> Result<Self> {
+        let proof = MerkleTreeProof::try_from(
+            access
+                .proof
+                .into_option()
+                .ok_or(Error::from("proof not found"))?,
+        )
+        .chain_err(|| "invalid access proof")?;
+        Ok(Access {
             field_type: access.field_type.into(),
             address: proof.address,
             value_read: to_bytes(access.read)
-                .expect("read value has the wrong size"),
+                .ok_or(Error::from("read value has the wrong size"))?,
             value_written: to_bytes(access.written)
-                .expect("write value has the wrong size"),
+                .ok_or(Error::from("write value has the wrong size"))?,
             proof: proof,
-        }
+        })
     }
 }
 
@@ -361,19 +401,25 @@ pub struct SessionStepResponse {
     pub log: Vec<Access>,
 }
 
-impl From<machine_manager::SessionStepResponse> for SessionStepResponse {
-    fn from(response: machine_manager::SessionStepResponse) -> Self {
-        SessionStepResponse {
+impl TryFrom<machine_manager::SessionStepResponse> for SessionStepResponse {
+    type Error = Error;
+
+    fn try_from(response: machine_manager::SessionStepResponse) -> Result<Self> {
+        Ok(SessionStepResponse {
             log: response
                 .log
                 .into_option()
-                .expect("log not found")
+                .ok_or(Error::from("log not found"))?
                 .accesses
                 .into_vec()
                 .into_iter()
-                .map(|hash| hash.into())
-                .collect(),
-        }
+                .enumerate()
+                .map(|(i, access)| {
+                    Access::try_from(access)
+                        .chain_err(|| format!("invalid access {} of step log", i))
+                })
+                .collect::<Result<Vec<Access>>>()?,
+        })
     }
 }
 
@@ -413,17 +459,21 @@ pub struct SessionReadMemoryResponse {
     pub read_content: ReadMemoryResponse,
 }
 
-impl From<machine_manager::SessionReadMemoryResponse>
+impl TryFrom<machine_manager::SessionReadMemoryResponse>
     for SessionReadMemoryResponse
 {
-    fn from(response: machine_manager::SessionReadMemoryResponse) -> Self {
-        SessionReadMemoryResponse {
+    type Error = Error;
+
+    fn try_from(
+        response: machine_manager::SessionReadMemoryResponse,
+    ) -> Result<Self> {
+        Ok(SessionReadMemoryResponse {
             read_content: response
                 .read_content
                 .into_option()
-                .expect("read_content not found")
+                .ok_or(Error::from("read_content not found"))?
                 .into(),
-        }
+        })
     }
 }
 
@@ -457,11 +507,13 @@ pub struct SessionGetProofResponse {
     pub proof: MerkleTreeProof,
 }
 
-impl From<cartesi_machine::MerkleTreeProof> for SessionGetProofResponse {
-    fn from(proof: cartesi_machine::MerkleTreeProof) -> Self {
-        SessionGetProofResponse {
-            proof: proof.into(),
-        }
+impl TryFrom<cartesi_machine::MerkleTreeProof> for SessionGetProofResponse {
+    type Error = Error;
+
+    fn try_from(proof: cartesi_machine::MerkleTreeProof) -> Result<Self> {
+        Ok(SessionGetProofResponse {
+            proof: proof.try_into()?,
+        })
     }
 }
 
@@ -472,15 +524,111 @@ pub struct EndSessionRequest {
     pub silent: bool,
 }
 
-impl From<Vec<u8>> for SessionRunResponse {
-    fn from(response: Vec<u8>) -> Self {
-        let marshaller: Box<
-            dyn Marshaller<machine_manager::SessionRunResponse> + Sync + Send,
-        > = Box::new(grpc::protobuf::MarshallerProtobuf);
-        marshaller
-            .read(bytes::Bytes::from(response))
-            .unwrap()
-            .into()
+/// Representation of a request for the machine manager version
+#[derive(Debug, Clone)]
+pub struct GetVersionRequest {}
+
+/// Semantic version of the machine manager
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SemanticVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    pub pre_release: String,
+    pub build: String,
+}
+
+impl SemanticVersion {
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        SemanticVersion {
+            major,
+            minor,
+            patch,
+            pre_release: String::new(),
+            build: String::new(),
+        }
+    }
+}
+
+impl fmt::Display for SemanticVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.pre_release.is_empty() {
+            write!(f, "-{}", self.pre_release)?;
+        }
+        if !self.build.is_empty() {
+            write!(f, "+{}", self.build)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<versioning::SemanticVersion> for SemanticVersion {
+    fn from(version: versioning::SemanticVersion) -> Self {
+        SemanticVersion {
+            major: version.get_major(),
+            minor: version.get_minor(),
+            patch: version.get_patch(),
+            pre_release: version.get_pre_release().to_string(),
+            build: version.get_build().to_string(),
+        }
+    }
+}
+
+impl From<SemanticVersion> for versioning::SemanticVersion {
+    fn from(version: SemanticVersion) -> Self {
+        let mut v = versioning::SemanticVersion::new();
+        v.set_major(version.major);
+        v.set_minor(version.minor);
+        v.set_patch(version.patch);
+        v.set_pre_release(version.pre_release);
+        v.set_build(version.build);
+        v
+    }
+}
+
+/// Representation of the response to a version request
+#[derive(Debug, Clone)]
+pub struct GetVersionResponse {
+    pub version: SemanticVersion,
+}
+
+impl TryFrom<versioning::GetVersionResponse> for GetVersionResponse {
+    type Error = Error;
+
+    fn try_from(response: versioning::GetVersionResponse) -> Result<Self> {
+        if !response.has_version() {
+            return Err(Error::from("missing version"));
+        }
+        Ok(GetVersionResponse {
+            version: response.get_version().clone().into(),
+        })
+    }
+}
+
+impl From<GetVersionResponse> for versioning::GetVersionResponse {
+    fn from(response: GetVersionResponse) -> Self {
+        let mut r = versioning::GetVersionResponse::new();
+        r.set_version(response.version.into());
+        r
+    }
+}
+
+/// Read a protobuf message from the bytes archived for an emulator reply
+fn unmarshall<M: protobuf::Message>(response: Vec<u8>) -> Result<M> {
+    let marshaller: Box<dyn Marshaller<M> + Sync + Send> =
+        Box::new(grpc::protobuf::MarshallerProtobuf);
+    marshaller
+        .read(bytes::Bytes::from(response))
+        .map_err(|e| Error::from(format!("could not unmarshall response: {:?}", e)))
+}
+
+impl TryFrom<Vec<u8>> for SessionRunResponse {
+    type Error = Error;
+
+    fn try_from(response: Vec<u8>) -> Result<Self> {
+        let message: machine_manager::SessionRunResponse = unmarshall(response)?;
+        Self::try_from(message).chain_err(|| "invalid SessionRunResponse")
     }
 }
 
@@ -503,15 +651,12 @@ impl From<SessionRunResponse> for Vec<u8> {
     }
 }
 
-impl From<Vec<u8>> for SessionStepResponse {
-    fn from(response: Vec<u8>) -> Self {
-        let marshaller: Box<
-            dyn Marshaller<machine_manager::SessionStepResponse> + Sync + Send,
-        > = Box::new(grpc::protobuf::MarshallerProtobuf);
-        marshaller
-            .read(bytes::Bytes::from(response))
-            .unwrap()
-            .into()
+impl TryFrom<Vec<u8>> for SessionStepResponse {
+    type Error = Error;
+
+    fn try_from(response: Vec<u8>) -> Result<Self> {
+        let message: machine_manager::SessionStepResponse = unmarshall(response)?;
+        Self::try_from(message).chain_err(|| "invalid SessionStepResponse")
     }
 }
 
@@ -524,41 +669,48 @@ impl From<SessionStepResponse> for Vec<u8> {
     }
 }
 
-impl From<Vec<u8>> for NewSessionResponse {
-    fn from(response: Vec<u8>) -> Self {
-        let marshaller: Box<
-            dyn Marshaller<cartesi_machine::Hash> + Sync + Send,
-        > = Box::new(grpc::protobuf::MarshallerProtobuf);
-        marshaller
-            .read(bytes::Bytes::from(response))
-            .unwrap()
-            .into()
+impl TryFrom<Vec<u8>> for NewSessionResponse {
+    type Error = Error;
+
+    fn try_from(response: Vec<u8>) -> Result<Self> {
+        let message: cartesi_machine::Hash = unmarshall(response)?;
+        Self::try_from(message).chain_err(|| "invalid NewSessionResponse")
     }
 }
 
-impl From<Vec<u8>> for SessionReadMemoryResponse {
-    fn from(response: Vec<u8>) -> Self {
-        let marshaller: Box<
-            dyn Marshaller<machine_manager::SessionReadMemoryResponse>
-                + Sync
-                + Send,
-        > = Box::new(grpc::protobuf::MarshallerProtobuf);
-        marshaller
-            .read(bytes::Bytes::from(response))
-            .unwrap()
-            .into()
+impl TryFrom<Vec<u8>> for SessionReadMemoryResponse {
+    type Error = Error;
+
+    fn try_from(response: Vec<u8>) -> Result<Self> {
+        let message: machine_manager::SessionReadMemoryResponse = unmarshall(response)?;
+        Self::try_from(message).chain_err(|| "invalid SessionReadMemoryResponse")
     }
 }
 
-impl From<Vec<u8>> for SessionGetProofResponse {
-    fn from(response: Vec<u8>) -> Self {
-        let marshaller: Box<
-            dyn Marshaller<cartesi_machine::MerkleTreeProof> + Sync + Send,
-        > = Box::new(grpc::protobuf::MarshallerProtobuf);
-        marshaller
-            .read(bytes::Bytes::from(response))
-            .unwrap()
-            .into()
+impl TryFrom<Vec<u8>> for SessionGetProofResponse {
+    type Error = Error;
+
+    fn try_from(response: Vec<u8>) -> Result<Self> {
+        let message: cartesi_machine::MerkleTreeProof = unmarshall(response)?;
+        Self::try_from(message).chain_err(|| "invalid SessionGetProofResponse")
+    }
+}
+
+impl TryFrom<Vec<u8>> for GetVersionResponse {
+    type Error = Error;
+
+    fn try_from(response: Vec<u8>) -> Result<Self> {
+        let message: versioning::GetVersionResponse = unmarshall(response)?;
+        Self::try_from(message).chain_err(|| "invalid GetVersionResponse")
+    }
+}
+
+impl From<SessionRunRequest> for machine_manager::SessionRunRequest {
+    fn from(request: SessionRunRequest) -> Self {
+        let mut req = machine_manager::SessionRunRequest::new();
+        req.set_session_id(request.session_id);
+        req.set_final_cycles(request.times);
+        req
     }
 }
 
@@ -568,11 +720,16 @@ impl From<SessionRunRequest> for Vec<u8> {
             dyn Marshaller<machine_manager::SessionRunRequest> + Sync + Send,
         > = Box::new(grpc::protobuf::MarshallerProtobuf);
 
-        let mut req = machine_manager::SessionRunRequest::new();
-        req.set_session_id(request.session_id);
-        req.set_final_cycles(request.times);
+        marshaller.write(&request.into()).unwrap()
+    }
+}
 
-        marshaller.write(&req).unwrap()
+impl From<SessionStepRequest> for machine_manager::SessionStepRequest {
+    fn from(request: SessionStepRequest) -> Self {
+        let mut req = machine_manager::SessionStepRequest::new();
+        req.set_session_id(request.session_id);
+        req.set_initial_cycle(request.time);
+        req
     }
 }
 
@@ -582,11 +739,17 @@ impl From<SessionStepRequest> for Vec<u8> {
             dyn Marshaller<machine_manager::SessionStepRequest> + Sync + Send,
         > = Box::new(grpc::protobuf::MarshallerProtobuf);
 
-        let mut req = machine_manager::SessionStepRequest::new();
-        req.set_session_id(request.session_id);
-        req.set_initial_cycle(request.time);
+        marshaller.write(&request.into()).unwrap()
+    }
+}
 
-        marshaller.write(&req).unwrap()
+impl From<NewSessionRequest> for machine_manager::NewSessionRequest {
+    fn from(request: NewSessionRequest) -> Self {
+        let mut req = machine_manager::NewSessionRequest::new();
+        req.set_session_id(request.session_id);
+        req.set_machine(request.machine);
+        req.set_force(request.force);
+        req
     }
 }
 
@@ -596,80 +759,96 @@ impl From<NewSessionRequest> for Vec<u8> {
             dyn Marshaller<machine_manager::NewSessionRequest> + Sync + Send,
         > = Box::new(grpc::protobuf::MarshallerProtobuf);
 
-        let mut req = machine_manager::NewSessionRequest::new();
-        req.set_session_id(request.session_id);
-        req.set_machine(request.machine);
-        req.set_force(request.force);
+        marshaller.write(&request.into()).unwrap()
+    }
+}
 
-        marshaller.write(&req).unwrap()
+impl From<SessionReadMemoryRequest> for machine_manager::SessionReadMemoryRequest {
+    fn from(request: SessionReadMemoryRequest) -> Self {
+        let mut req = machine_manager::SessionReadMemoryRequest::new();
+        req.set_session_id(request.session_id);
+        req.set_cycle(request.time);
+        req.set_position(request.position);
+        req
     }
 }
 
 impl From<SessionReadMemoryRequest> for Vec<u8> {
     fn from(request: SessionReadMemoryRequest) -> Self {
         let marshaller: Box<
-            dyn Marshaller<machine_manager::SessionReadMemoryRequest>
-                + Sync
-                + Send,
+            dyn Marshaller<machine_manager::SessionReadMemoryRequest> + Sync + Send,
         > = Box::new(grpc::protobuf::MarshallerProtobuf);
 
-        let mut req = machine_manager::SessionReadMemoryRequest::new();
+        marshaller.write(&request.into()).unwrap()
+    }
+}
+
+impl From<SessionWriteMemoryRequest> for machine_manager::SessionWriteMemoryRequest {
+    fn from(request: SessionWriteMemoryRequest) -> Self {
+        let mut req = machine_manager::SessionWriteMemoryRequest::new();
         req.set_session_id(request.session_id);
         req.set_cycle(request.time);
         req.set_position(request.position);
-
-        marshaller.write(&req).unwrap()
+        req
     }
 }
 
 impl From<SessionWriteMemoryRequest> for Vec<u8> {
     fn from(request: SessionWriteMemoryRequest) -> Self {
         let marshaller: Box<
-            dyn Marshaller<machine_manager::SessionWriteMemoryRequest>
-                + Sync
-                + Send,
+            dyn Marshaller<machine_manager::SessionWriteMemoryRequest> + Sync + Send,
         > = Box::new(grpc::protobuf::MarshallerProtobuf);
 
-        let mut req = machine_manager::SessionWriteMemoryRequest::new();
+        marshaller.write(&request.into()).unwrap()
+    }
+}
+
+impl From<SessionReplaceMemoryRangeRequest> for machine_manager::SessionReplaceMemoryRangeRequest {
+    fn from(request: SessionReplaceMemoryRangeRequest) -> Self {
+        let mut req = machine_manager::SessionReplaceMemoryRangeRequest::new();
         req.set_session_id(request.session_id);
         req.set_cycle(request.time);
-        req.set_position(request.position);
-
-        marshaller.write(&req).unwrap()
+        req.set_range(request.range);
+        req
     }
 }
 
 impl From<SessionReplaceMemoryRangeRequest> for Vec<u8> {
     fn from(request: SessionReplaceMemoryRangeRequest) -> Self {
         let marshaller: Box<
-            dyn Marshaller<machine_manager::SessionReplaceMemoryRangeRequest>
-                + Sync
-                + Send,
+            dyn Marshaller<machine_manager::SessionReplaceMemoryRangeRequest> + Sync + Send,
         > = Box::new(grpc::protobuf::MarshallerProtobuf);
 
-        let mut req = machine_manager::SessionReplaceMemoryRangeRequest::new();
+        marshaller.write(&request.into()).unwrap()
+    }
+}
+
+impl From<SessionGetProofRequest> for machine_manager::SessionGetProofRequest {
+    fn from(request: SessionGetProofRequest) -> Self {
+        let mut req = machine_manager::SessionGetProofRequest::new();
         req.set_session_id(request.session_id);
         req.set_cycle(request.time);
-        req.set_range(request.range);
-
-        marshaller.write(&req).unwrap()
+        req.set_target(request.target);
+        req
     }
 }
 
 impl From<SessionGetProofRequest> for Vec<u8> {
     fn from(request: SessionGetProofRequest) -> Self {
         let marshaller: Box<
-            dyn Marshaller<machine_manager::SessionGetProofRequest>
-                + Sync
-                + Send,
+            dyn Marshaller<machine_manager::SessionGetProofRequest> + Sync + Send,
         > = Box::new(grpc::protobuf::MarshallerProtobuf);
 
-        let mut req = machine_manager::SessionGetProofRequest::new();
-        req.set_session_id(request.session_id);
-        req.set_cycle(request.time);
-        req.set_target(request.target);
+        marshaller.write(&request.into()).unwrap()
+    }
+}
 
-        marshaller.write(&req).unwrap()
+impl From<EndSessionRequest> for machine_manager::EndSessionRequest {
+    fn from(request: EndSessionRequest) -> Self {
+        let mut req = machine_manager::EndSessionRequest::new();
+        req.set_session_id(request.session_id);
+        req.set_silent(request.silent);
+        req
     }
 }
 
@@ -679,10 +858,84 @@ impl From<EndSessionRequest> for Vec<u8> {
             dyn Marshaller<machine_manager::EndSessionRequest> + Sync + Send,
         > = Box::new(grpc::protobuf::MarshallerProtobuf);
 
-        let mut req = machine_manager::EndSessionRequest::new();
-        req.set_session_id(request.session_id);
-        req.set_silent(request.silent);
+        marshaller.write(&request.into()).unwrap()
+    }
+}
+
+impl From<GetVersionRequest> for Vec<u8> {
+    fn from(_request: GetVersionRequest) -> Self {
+        let marshaller: Box<
+            dyn Marshaller<cartesi_machine::Void> + Sync + Send,
+        > = Box::new(grpc::protobuf::MarshallerProtobuf);
+
+        marshaller.write(&cartesi_machine::Void::new()).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dispatcher::Archive;
+    use {get_run_result, ArchiveKey};
+
+    fn marshall<M: protobuf::Message>(message: &M) -> Vec<u8> {
+        let marshaller: Box<dyn Marshaller<M> + Sync + Send> =
+            Box::new(grpc::protobuf::MarshallerProtobuf);
+        marshaller.write(message).unwrap()
+    }
+
+    #[test]
+    fn it_should_reject_malformed_responses() {
+        assert!(SessionRunResponse::try_from(vec![0xff; 7]).is_err());
+        assert!(SessionStepResponse::try_from(vec![0xff; 7]).is_err());
 
-        marshaller.write(&req).unwrap()
+        // missing oneof and log
+        let empty = marshall(&machine_manager::SessionRunResponse::new());
+        assert!(SessionRunResponse::try_from(empty).is_err());
+        let empty = marshall(&machine_manager::SessionStepResponse::new());
+        assert!(SessionStepResponse::try_from(empty).is_err());
+
+        // hash of the wrong size
+        let mut hash = cartesi_machine::Hash::new();
+        hash.data = vec![1, 2, 3];
+        let error = NewSessionResponse::try_from(marshall(&hash)).unwrap_err();
+        assert!(format!("{:?}", error).contains("has 3 bytes instead of 32"));
+    }
+
+    #[test]
+    fn it_should_round_trip_responses() {
+        let hashes = vec![H256::from_low_u64_be(1), H256::from_low_u64_be(2)];
+        let bin: Vec<u8> = SessionRunResponse {
+            one_of: SessionRunResponseOneOf::RunResult(SessionRunResult {
+                hashes: hashes.clone(),
+            }),
+        }
+        .into();
+        match SessionRunResponse::try_from(bin).unwrap().one_of {
+            SessionRunResponseOneOf::RunResult(result) => {
+                assert_eq!(result.hashes, hashes)
+            }
+            _ => panic!("expected a run result"),
+        }
+    }
+
+    #[test]
+    fn it_should_retry_malformed_archived_responses() {
+        let mut archive = Archive::new().unwrap();
+        let key = ArchiveKey::Run {
+            id: String::from("Machine000"),
+            times: vec![0, 1],
+        };
+        archive.insert_response(key.to_string(), Ok(vec![0xff; 7]));
+        let request: Vec<u8> = SessionRunRequest {
+            session_id: String::from("Machine000"),
+            times: vec![0, 1],
+        }
+        .into();
+        let error = get_run_result(&archive, "Test".to_string(), &key, request, None).unwrap_err();
+        match error.kind() {
+            ErrorKind::ServiceNeedsRetry(..) => {}
+            _ => panic!("expected a retry, got {:?}", error),
+        }
     }
 }
@@ -0,0 +1,160 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! Feasibility of the runs a Compute party must finish before the
+//! contract deadline. When the emulator speed observed so far projects
+//! the completion of the final time past the deadline, we would lose by
+//! `claimVictoryByTime` while still computing, so operators are alerted
+//! as soon as possible.
+
+use clock::Clock;
+use run_progress::RunEstimate;
+
+use std::fmt;
+
+/// The run of the final time is projected to complete after the deadline
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DeadlineAlert {
+    pub machine_id: String,
+    pub final_time: u64,
+    /// Deadline of the Compute contract, in seconds since the UNIX epoch
+    pub deadline: u64,
+    /// Uncertainty of our clock, taken off the deadline since the other
+    /// party may claim victory as soon as a block passes it
+    pub safety_margin: u64,
+    /// Cycle reached by the emulator so far
+    pub cycle: u64,
+    pub cycles_per_second: Option<f64>,
+    /// Projected completion time, none if the emulator is stalled
+    pub projected_completion: Option<u64>,
+}
+
+impl fmt::Display for DeadlineAlert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.projected_completion {
+            Some(completion) => write!(
+                f,
+                "machine {} is projected to reach cycle {} at {}, {}s past the deadline {} \
+                 less the safety margin of {}s",
+                self.machine_id,
+                self.final_time,
+                completion,
+                completion.saturating_sub(self.deadline.saturating_sub(self.safety_margin)),
+                self.deadline,
+                self.safety_margin
+            ),
+            None => write!(
+                f,
+                "machine {} is stalled at cycle {} of {}, deadline {}",
+                self.machine_id, self.cycle, self.final_time, self.deadline
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DeadlineAlert {}
+
+/// Alert if the run of `final_time`, as far as `estimate` goes, cannot
+/// complete by `deadline`. The cycles left are projected from the time of
+/// `clock`, the one deadlines are checked against, and its safety margin
+/// is taken off the deadline: the margin only delays our own victory
+/// claims, while the other party may claim as soon as a block passes the
+/// deadline. Without a measured speed or a reading of the clock there is
+/// nothing to project yet
+pub fn check(
+    machine_id: &str,
+    estimate: &RunEstimate,
+    final_time: u64,
+    deadline: u64,
+    clock: &dyn Clock,
+) -> Option<DeadlineAlert> {
+    let speed = estimate.cycles_per_second?;
+    let now = clock.now().ok()?;
+    let safety_margin = clock.safety_margin();
+    let projected_completion = if speed > 0.0 {
+        let left = final_time.saturating_sub(estimate.cycle) as f64;
+        Some(now.saturating_add((left / speed).ceil() as u64))
+    } else {
+        None
+    };
+    match projected_completion {
+        Some(completion) if completion <= deadline.saturating_sub(safety_margin) => None,
+        _ => Some(DeadlineAlert {
+            machine_id: machine_id.to_string(),
+            final_time,
+            deadline,
+            safety_margin,
+            cycle: estimate.cycle,
+            cycles_per_second: estimate.cycles_per_second,
+            projected_completion,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::BlockClock;
+
+    fn estimate(cycle: u64, cycles_per_second: Option<f64>) -> RunEstimate {
+        RunEstimate {
+            key: String::from("feasibility_run"),
+            cycle,
+            progress: 0,
+            application_progress: 0,
+            updated_at: 500,
+            cycles_per_second,
+            seconds_to_completion: None,
+            completion_at: None,
+        }
+    }
+
+    #[test]
+    fn it_should_alert_when_projected_past_deadline() {
+        let id = String::from("feasibility");
+        let clock = BlockClock::new(1_000, 0);
+        let running = estimate(1_000, Some(100.0));
+        // 9000 cycles left at 100 cycles/s end at 1090, whenever sampled
+        assert_eq!(check(&id, &running, 10_000, 1_090, &clock), None);
+        let alert = check(&id, &running, 10_000, 1_089, &clock).unwrap();
+        assert_eq!(alert.projected_completion, Some(1_090));
+        assert!(alert.to_string().contains("1s past the deadline"), "{}", alert);
+
+        // the other party claims right after the deadline, so our safety
+        // margin must be spared before it
+        let margin = BlockClock::new(1_000, 10);
+        assert_eq!(check(&id, &running, 10_000, 1_100, &margin), None);
+        let alert = check(&id, &running, 10_000, 1_099, &margin).unwrap();
+        assert_eq!(alert.safety_margin, 10);
+        assert!(alert.to_string().contains("1s past the deadline"), "{}", alert);
+
+        let stalled = estimate(1_000, Some(0.0));
+        let stalled = check(&id, &stalled, 10_000, u64::max_value(), &clock).unwrap();
+        assert_eq!(stalled.projected_completion, None);
+        assert!(stalled.to_string().contains("stalled"), "{}", stalled);
+
+        assert_eq!(check(&id, &estimate(1_000, None), 10_000, 0, &clock), None);
+    }
+}
@@ -0,0 +1,475 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! Forensic bundles of disputes that reached a state an honest party
+//! should never see, like a lost verification game or a partition where
+//! we agree with every claimed hash. A bundle is a self-contained JSON
+//! document with the instance tree, the archived emulator responses the
+//! DApps consulted and the transactions we sent for the dispute, and it
+//! replays offline through `Compute::react`.
+
+use super::configuration::Concern;
+use super::dispatcher::{Archive, DApp, Reaction};
+use super::error::*;
+use super::ethereum_types::{Address, U256};
+use super::ArchiveKey;
+use checked::{to_u64s, CheckedConversion};
+use compatibility::version_key;
+use compute::{Compute, ComputeCtx, ComputeCtxParsed, ComputeParams, DisputeParams};
+use emulator_service::{
+    SessionRunResponse, SessionRunResponseOneOf, SessionRunResult, EMULATOR_METHOD_PROOF,
+    EMULATOR_METHOD_READ, EMULATOR_METHOD_REPLACE, EMULATOR_METHOD_RUN, EMULATOR_METHOD_STEP,
+    EMULATOR_METHOD_VERSION, EMULATOR_SERVICE_NAME,
+};
+use input::InputDrive;
+use journal::SentTransaction;
+use output::OutputRange;
+use partition::{PartitionCtx, PartitionCtxParsed};
+use vg::{VGCtx, VGCtxParsed};
+
+use std::convert::TryFrom;
+use std::fs;
+use std::path::PathBuf;
+
+/// Serializable copy of a `state::Instance`; the service status is left
+/// out, as replaying never depends on it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct InstanceDump {
+    pub name: String,
+    pub contract_address: Address,
+    pub user_address: Address,
+    pub index: U256,
+    pub json_data: String,
+    pub sub_instances: Vec<InstanceDump>,
+}
+
+impl<'a> From<&'a state::Instance> for InstanceDump {
+    fn from(instance: &'a state::Instance) -> Self {
+        InstanceDump {
+            name: instance.name.clone(),
+            contract_address: instance.concern.contract_address,
+            user_address: instance.concern.user_address,
+            index: instance.index,
+            json_data: instance.json_data.clone(),
+            sub_instances: instance
+                .sub_instances
+                .iter()
+                .map(|sub| InstanceDump::from(sub.as_ref()))
+                .collect(),
+        }
+    }
+}
+
+impl From<InstanceDump> for state::Instance {
+    fn from(dump: InstanceDump) -> Self {
+        state::Instance {
+            name: dump.name,
+            concern: Concern {
+                contract_address: dump.contract_address,
+                user_address: dump.user_address,
+            },
+            index: dump.index,
+            service_status: state::ServiceStatus {
+                service_name: "".into(),
+                service_method: "".into(),
+                status: 0,
+                description: "".into(),
+                progress: 0,
+            },
+            json_data: dump.json_data,
+            sub_instances: dump
+                .sub_instances
+                .into_iter()
+                .map(|sub| Box::new(state::Instance::from(sub)))
+                .collect(),
+        }
+    }
+}
+
+/// Archived emulator response, hex encoded
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ArchivedResponse {
+    pub key: String,
+    pub method: String,
+    pub response: String,
+}
+
+/// Input drive of a bundled dispute, by the image the machine manager
+/// was given, so that the replay injects it under the same archive key
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BundledInput {
+    pub start: u64,
+    pub length: u64,
+    pub image: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ForensicBundle {
+    /// Why the bundle was taken
+    pub reason: String,
+    pub machine_id: String,
+    /// Time of the clock deadlines were checked against
+    pub timestamp: u64,
+    /// Compute instance, with its verification game below it
+    pub instance: InstanceDump,
+    pub responses: Vec<ArchivedResponse>,
+    pub sent_transactions: Vec<SentTransaction>,
+    /// Output range covered by the claim, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<OutputRange>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<BundledInput>,
+}
+
+/// Context of an instance, if its `json_data` is a valid `Parsed` state
+pub fn parse_ctx<Parsed, Ctx>(json_data: &str) -> Option<Ctx>
+where
+    Parsed: serde::de::DeserializeOwned,
+    Ctx: TryFrom<Parsed>,
+{
+    let parsed: Parsed = serde_json::from_str(json_data).ok()?;
+    Ctx::try_from(parsed).ok()
+}
+
+/// Emulator requests the DApps make for the Compute `instance`, as far as
+/// the contract states can be parsed
+fn consulted_keys(
+    instance: &state::Instance,
+    id: &str,
+    now: u64,
+    output: Option<OutputRange>,
+    input: Option<&BundledInput>,
+) -> Vec<(ArchiveKey, &'static str)> {
+    let mut keys = vec![];
+    let ctx: ComputeCtx = match parse_ctx::<ComputeCtxParsed, _>(&instance.json_data) {
+        Some(ctx) => ctx,
+        None => return keys,
+    };
+    keys.push((version_key(id, now), EMULATOR_METHOD_VERSION));
+    if let Some(input) = input {
+        keys.push((
+            ArchiveKey::Replace {
+                id: id.to_string(),
+                time: 0,
+                address: input.start,
+                path: input.image.to_string_lossy().into_owned(),
+            },
+            EMULATOR_METHOD_REPLACE,
+        ));
+    }
+    keys.push((
+        ArchiveKey::Run {
+            id: id.to_string(),
+            times: vec![0],
+        },
+        EMULATOR_METHOD_RUN,
+    ));
+    if let Ok(final_time) = ctx.final_time.to_u64("final time") {
+        keys.push((
+            ArchiveKey::Run {
+                id: id.to_string(),
+                times: vec![0, final_time],
+            },
+            EMULATOR_METHOD_RUN,
+        ));
+        if let Some(range) = output {
+            keys.push((
+                ArchiveKey::Read {
+                    id: id.to_string(),
+                    time: final_time,
+                    address: range.address,
+                    length: range.length(),
+                },
+                EMULATOR_METHOD_READ,
+            ));
+            keys.push((
+                ArchiveKey::Proof {
+                    id: id.to_string(),
+                    time: final_time,
+                    address: range.address,
+                    log2_size: range.log2_size,
+                },
+                EMULATOR_METHOD_PROOF,
+            ));
+        }
+    }
+
+    for vg_instance in &instance.sub_instances {
+        if let Some(vg_ctx) = parse_ctx::<VGCtxParsed, VGCtx>(&vg_instance.json_data) {
+            if let Ok(time) = vg_ctx.divergence_time.to_u64("divergence time") {
+                keys.push((
+                    ArchiveKey::Step {
+                        id: id.to_string(),
+                        time,
+                    },
+                    EMULATOR_METHOD_STEP,
+                ));
+            }
+        }
+        // MM instances fail to parse as partitions
+        for sub in &vg_instance.sub_instances {
+            if let Some(partition_ctx) =
+                parse_ctx::<PartitionCtxParsed, PartitionCtx>(&sub.json_data)
+            {
+                if let Ok(times) = to_u64s(&partition_ctx.query_array, "query time") {
+                    keys.push((
+                        ArchiveKey::Run {
+                            id: id.to_string(),
+                            times,
+                        },
+                        EMULATOR_METHOD_RUN,
+                    ));
+                }
+            }
+        }
+    }
+    keys
+}
+
+impl ForensicBundle {
+    /// Bundle the Compute `instance` with every archived response its
+    /// DApps consult. Replays never open a session, so they need a run of
+    /// cycle 0, which is not archived when the session was opened from
+    /// `DisputeParams::machine_request`; the initial hash of the contract
+    /// is bundled instead, as we only play once it matches ours
+    pub fn collect<P: DisputeParams>(
+        reason: &str,
+        instance: &state::Instance,
+        archive: &Archive,
+        params: &P,
+    ) -> Self {
+        let id = params.machine_id();
+        let timestamp = params.clock().now().unwrap_or(0);
+        let output = params.output_range();
+        let input = params.input_drive().and_then(|drive| {
+            drive.image_path().ok().map(|image| BundledInput {
+                start: drive.start,
+                length: drive.length,
+                image,
+            })
+        });
+        let mut responses = vec![];
+        for (key, method) in consulted_keys(instance, id, timestamp, output, input.as_ref()) {
+            let response = archive.get_response(
+                EMULATOR_SERVICE_NAME.to_string(),
+                key.to_string(),
+                method.to_string(),
+                vec![],
+            );
+            let response = match (response, &key) {
+                (Ok(response), _) => response,
+                (Err(_), ArchiveKey::Run { times, .. }) if times.as_slice() == [0] => {
+                    match initial_hash_response(instance) {
+                        Some(response) => response,
+                        None => continue,
+                    }
+                }
+                (Err(_), _) => continue,
+            };
+            let key = key.to_string();
+            if responses.iter().any(|r: &ArchivedResponse| r.key == key) {
+                continue;
+            }
+            responses.push(ArchivedResponse {
+                key,
+                method: method.to_string(),
+                response: hex::encode(response),
+            });
+        }
+
+        ForensicBundle {
+            reason: reason.to_string(),
+            machine_id: id.clone(),
+            timestamp,
+            instance: InstanceDump::from(instance),
+            responses,
+            sent_transactions: params
+                .journal()
+                .map(|journal| journal.sent_transactions(instance))
+                .unwrap_or_default(),
+            output,
+            input,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).chain_err(|| "Could not serialize forensic bundle")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).chain_err(|| "Could not parse forensic bundle")
+    }
+
+    /// Archive holding the bundled responses
+    pub fn archive(&self) -> Result<Archive> {
+        let mut archive = Archive::new()?;
+        for response in &self.responses {
+            let bytes = hex::decode(&response.response)
+                .chain_err(|| format!("Invalid bundled response {}", response.key))?;
+            archive.insert_response(response.key.clone(), Ok(bytes));
+        }
+        Ok(archive)
+    }
+
+    /// React to the bundled instance tree again, as of the bundle
+    /// timestamp. The replay keeps a journal of its own, so the journal
+    /// of a live node is left alone
+    pub fn replay(&self) -> Result<Reaction> {
+        let archive = self.archive()?;
+        let instance = state::Instance::from(self.instance.clone());
+        let mut params =
+            ComputeParams::with_block_timestamp(self.machine_id.clone(), self.timestamp, 0);
+        if let Some(range) = self.output {
+            params = params.with_output(range);
+        }
+        if let Some(input) = &self.input {
+            let drive = InputDrive::from_path(input.start, input.length, input.image.clone());
+            params = params.with_input_drive(drive);
+        }
+        Compute::react(&instance, &archive, &None, &params)
+    }
+
+    /// Name of the bundle file, unique per dispute and time
+    pub fn file_name(&self) -> String {
+        format!(
+            "{:x}_{:x}_{}_{}.json",
+            self.instance.contract_address,
+            self.instance.user_address,
+            self.instance.index,
+            self.timestamp
+        )
+    }
+}
+
+/// Run of cycle 0 answered with the initial hash of the contract
+fn initial_hash_response(instance: &state::Instance) -> Option<Vec<u8>> {
+    let ctx: ComputeCtx = parse_ctx::<ComputeCtxParsed, _>(&instance.json_data)?;
+    Some(
+        SessionRunResponse {
+            one_of: SessionRunResponseOneOf::RunResult(SessionRunResult {
+                hashes: vec![ctx.initial_hash],
+            }),
+        }
+        .into(),
+    )
+}
+
+/// Write the forensic bundle of the Compute `instance` to
+/// `DisputeParams::forensic_dir`, if there is one, once per dispute its
+/// `DisputeParams::journal` remembers. Only the reason and the path are
+/// logged as errors, the bundle itself goes to the debug log. Failing to
+/// write is only logged, as it must not keep us from playing
+pub fn report<P: DisputeParams>(
+    reason: &str,
+    instance: &state::Instance,
+    archive: &Archive,
+    params: &P,
+) {
+    let reported = params
+        .journal()
+        .map_or(false, |journal| journal.mark_reported(instance));
+    if reported {
+        return;
+    }
+    let bundle = ForensicBundle::collect(reason, instance, archive, params);
+    let json = match bundle.to_json() {
+        Ok(json) => json,
+        Err(e) => {
+            error!("Could not build forensic bundle: {}", e);
+            return;
+        }
+    };
+    debug!(
+        "Forensic bundle for Compute (index {}): {}",
+        instance.index, json
+    );
+    match params.forensic_dir() {
+        Some(dir) => {
+            let path = dir.join(bundle.file_name());
+            match fs::create_dir_all(dir).and_then(|_| fs::write(&path, &json)) {
+                Ok(()) => error!(
+                    "Compute (index {}): {}; forensic bundle written to {}",
+                    instance.index,
+                    reason,
+                    path.display()
+                ),
+                Err(e) => error!(
+                    "Compute (index {}): {}; could not write forensic bundle {}: {}",
+                    instance.index,
+                    reason,
+                    path.display(),
+                    e
+                ),
+            }
+        }
+        None => error!(
+            "Compute (index {}): {}; no forensic directory to write the bundle to",
+            instance.index, reason
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethabi::Token;
+    use tests::{build_concern, build_state, CLAIMERADDR};
+    use transaction::TransactionRequest;
+
+    #[test]
+    fn it_should_round_trip_instance_trees() {
+        let mut instance = build_state(build_concern(CLAIMERADDR), Some("[]".into()));
+        let mut sub = build_state(build_concern(CLAIMERADDR), Some("{}".into()));
+        sub.name = "VG".into();
+        sub.index = U256::from(3);
+        instance.name = "Compute".into();
+        instance.sub_instances = vec![Box::new(sub)];
+
+        let dump = InstanceDump::from(&instance);
+        assert_eq!(dump.sub_instances[0].name, "VG");
+        let bundle = ForensicBundle {
+            reason: "test".into(),
+            machine_id: "Machine000".into(),
+            timestamp: 1,
+            instance: dump.clone(),
+            responses: vec![ArchivedResponse {
+                key: "key".into(),
+                method: EMULATOR_METHOD_RUN.into(),
+                response: hex::encode(vec![1, 2, 3]),
+            }],
+            sent_transactions: vec![],
+            output: None,
+            input: None,
+        };
+        let parsed = ForensicBundle::from_json(&bundle.to_json().unwrap()).unwrap();
+        assert_eq!(parsed, bundle);
+        assert_eq!(
+            InstanceDump::from(&state::Instance::from(parsed.instance)),
+            dump
+        );
+        // unparsable contract states have nothing to consult
+        assert!(consulted_keys(&instance, "Machine000", 0, None, None).is_empty());
+    }
+}
@@ -0,0 +1,178 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+
+//! Input drives of computations whose input is only known when the
+//! Compute instance is created. A single machine template can then serve
+//! many inputs: the drive is injected into the emulator session at cycle
+//! 0, and the resulting root hash must be the `initialHash` the contract
+//! was instantiated with.
+
+use super::dispatcher::Archive;
+use super::error::*;
+use super::ethereum_types::H256;
+use super::keccak_hash::keccak;
+use super::{cartesi_machine, replace_memory_range};
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Where the contents of an input drive come from
+#[derive(Debug, Clone, PartialEq)]
+pub enum DriveSource {
+    /// Image file, as seen by the machine manager
+    Path(PathBuf),
+    /// Contents of the drive, written to an image file named after their
+    /// keccak and the drive length in `dir`, which the machine manager
+    /// must be able to read
+    Content { data: Vec<u8>, dir: PathBuf },
+}
+
+/// Memory range replaced with the input of the computation
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputDrive {
+    pub start: u64,
+    pub length: u64,
+    pub source: DriveSource,
+}
+
+impl InputDrive {
+    /// Drive of `length` bytes at `start`, loaded from the image at `path`
+    pub fn from_path(start: u64, length: u64, path: PathBuf) -> Self {
+        InputDrive {
+            start,
+            length,
+            source: DriveSource::Path(path),
+        }
+    }
+
+    /// Drive of `length` bytes at `start` holding `data`, padded with
+    /// zeros; the image file is written to the system temporary directory
+    pub fn from_content(start: u64, length: u64, data: Vec<u8>) -> Result<Self> {
+        if data.len() as u64 > length {
+            return Err(Error::from(format!(
+                "Input of {} bytes does not fit a drive of {} bytes",
+                data.len(),
+                length
+            )));
+        }
+        Ok(InputDrive {
+            start,
+            length,
+            source: DriveSource::Content {
+                data,
+                dir: std::env::temp_dir(),
+            },
+        })
+    }
+
+    /// Image file of the drive, writing it first if it is given by content
+    /// and not written yet. The name only depends on the content and the
+    /// length, so that the replace request, and thus its archive key, stays
+    /// the same across reactions
+    pub fn image_path(&self) -> Result<PathBuf> {
+        match &self.source {
+            DriveSource::Path(path) => Ok(path.clone()),
+            DriveSource::Content { data, dir } => {
+                let path = dir.join(format!("input-{:x}-{}.bin", keccak(data), self.length));
+                // a partially written image is shorter than the drive
+                let written = fs::metadata(&path)
+                    .map(|metadata| metadata.len() == self.length)
+                    .unwrap_or(false);
+                if !written {
+                    let mut image = data.clone();
+                    image.resize(self.length as usize, 0);
+                    fs::write(&path, image)
+                        .chain_err(|| format!("Could not write input drive {:?}", path))?;
+                }
+                Ok(path)
+            }
+        }
+    }
+
+    /// Keccak of the drive range and of where its contents come from, so
+    /// that sessions of different inputs are told apart
+    pub fn fingerprint(&self) -> H256 {
+        let mut seed = Vec::new();
+        seed.extend_from_slice(&self.start.to_be_bytes());
+        seed.extend_from_slice(&self.length.to_be_bytes());
+        match &self.source {
+            DriveSource::Path(path) => {
+                seed.push(0);
+                seed.extend_from_slice(path.to_string_lossy().as_bytes());
+            }
+            DriveSource::Content { data, .. } => {
+                seed.push(1);
+                seed.extend_from_slice(keccak(data).as_bytes());
+            }
+        }
+        keccak(&seed)
+    }
+
+    /// Replace the drive range of session `id` at cycle 0
+    pub fn inject(&self, archive: &Archive, id: &str) -> Result<()> {
+        let path = self.image_path()?;
+        let mut range = cartesi_machine::MemoryRangeConfig::new();
+        range.set_start(self.start);
+        range.set_length(self.length);
+        range.set_image_filename(path.to_string_lossy().into_owned());
+        trace!("Injecting input drive {:?} into machine {}", path, id);
+        replace_memory_range(archive, id.to_string(), range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_name_images_after_their_content() {
+        let drive = InputDrive::from_content(0x2000, 16, vec![1, 2, 3]).unwrap();
+        let path = drive.image_path().unwrap();
+        let mut image = vec![1, 2, 3];
+        image.resize(16, 0);
+        assert_eq!(fs::read(&path).unwrap(), image);
+        assert_eq!(drive.image_path().unwrap(), path);
+
+        // an image cut short is written again
+        fs::write(&path, vec![1, 2, 3]).unwrap();
+        assert_eq!(drive.image_path().unwrap(), path);
+        assert_eq!(fs::read(&path).unwrap(), image);
+
+        let other = InputDrive::from_content(0x2000, 16, vec![3, 2, 1]).unwrap();
+        assert_ne!(other.image_path().unwrap(), path);
+        assert_ne!(other.fingerprint(), drive.fingerprint());
+
+        let file = InputDrive::from_path(0x2000, 16, path.clone());
+        assert_eq!(file.image_path().unwrap(), path);
+        assert_ne!(file.fingerprint(), drive.fingerprint());
+    }
+
+    #[test]
+    fn it_should_reject_content_larger_than_drive() {
+        assert!(InputDrive::from_content(0x2000, 8, vec![0; 9]).is_err());
+        assert!(InputDrive::from_content(0x2000, 8, vec![0; 8]).is_ok());
+    }
+}
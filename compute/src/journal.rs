@@ -0,0 +1,254 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! Journal of the disputes we play: the transactions we sent, the last
+//! reaction taken and whether the dispute was dumped into a forensic
+//! bundle. The journal belongs to whoever passes the DApp its parameters,
+//! see `DisputeParams::journal`. `Compute::react` records its reactions
+//! there, and the journal of a dispute is dropped when it is over.
+
+use super::dispatcher::Reaction;
+use super::ethereum_types::{Address, U256};
+use tracker::{self, Tracker};
+
+use std::sync::Mutex;
+
+/// Transactions kept per dispute, the oldest being dropped first
+pub const MAX_JOURNAL_ENTRIES: usize = 256;
+
+/// Disputes journaled at once, the least recently played being dropped
+/// first
+pub const MAX_JOURNALED_DISPUTES: usize = 1024;
+
+/// Transaction we sent for a dispute
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SentTransaction {
+    pub contract_address: Address,
+    pub function: String,
+    /// Arguments, as printed by `Debug`
+    pub data: Vec<String>,
+}
+
+/// Last reaction taken for a dispute
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub enum LastReaction {
+    Idle,
+    Transaction {
+        contract_address: Address,
+        function: String,
+    },
+}
+
+/// Compute contract, user and index of a dispute
+type DisputeKey = (Address, Address, U256);
+
+/// What we did in a dispute so far
+#[derive(Debug, Default)]
+struct DisputeJournal {
+    transactions: Vec<SentTransaction>,
+    last_reaction: Option<LastReaction>,
+    /// Whether the dispute was already dumped, as it is seen on every
+    /// reaction until it is over
+    reported: bool,
+}
+
+fn dispute_key(instance: &state::Instance) -> DisputeKey {
+    (
+        instance.concern.contract_address,
+        instance.concern.user_address,
+        instance.index,
+    )
+}
+
+/// Journal of every dispute played with the same parameters
+#[derive(Debug)]
+pub struct Journal {
+    disputes: Mutex<Tracker<DisputeKey, DisputeJournal>>,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Journal {
+            disputes: Mutex::new(Tracker::new(MAX_JOURNALED_DISPUTES)),
+        }
+    }
+
+    /// Record `reaction` as the last one taken for the Compute `instance`,
+    /// along with the transaction it sends, if any. A transaction is
+    /// reacted again on every poll until it is mined, so one identical to
+    /// the last recorded is not recorded twice
+    pub fn record(&self, instance: &state::Instance, reaction: &Reaction) {
+        tracker::with(&self.disputes, |disputes| {
+            let dispute = disputes.entry(dispute_key(instance), DisputeJournal::default);
+            match reaction {
+                Reaction::Transaction(request) => {
+                    let sent = SentTransaction {
+                        contract_address: request.concern.contract_address,
+                        function: request.function.clone(),
+                        data: request.data.iter().map(|t| format!("{:?}", t)).collect(),
+                    };
+                    if dispute.transactions.last() != Some(&sent) {
+                        if dispute.transactions.len() == MAX_JOURNAL_ENTRIES {
+                            dispute.transactions.remove(0);
+                        }
+                        dispute.transactions.push(sent);
+                    }
+                    dispute.last_reaction = Some(LastReaction::Transaction {
+                        contract_address: request.concern.contract_address,
+                        function: request.function.clone(),
+                    });
+                }
+                _ => dispute.last_reaction = Some(LastReaction::Idle),
+            }
+        });
+    }
+
+    /// Drop the journal of a finished Compute `instance`
+    pub fn forget(&self, instance: &state::Instance) {
+        tracker::with(&self.disputes, |disputes| {
+            disputes.remove(&dispute_key(instance));
+        });
+    }
+
+    /// Transactions sent so far for the Compute `instance`
+    pub fn sent_transactions(&self, instance: &state::Instance) -> Vec<SentTransaction> {
+        tracker::with(&self.disputes, |disputes| {
+            disputes
+                .get(&dispute_key(instance))
+                .map(|dispute| dispute.transactions.clone())
+                .unwrap_or_default()
+        })
+    }
+
+    /// Last reaction taken for the Compute `instance`, if any
+    pub fn last_reaction(&self, instance: &state::Instance) -> Option<LastReaction> {
+        tracker::with(&self.disputes, |disputes| {
+            disputes
+                .get(&dispute_key(instance))
+                .and_then(|dispute| dispute.last_reaction.clone())
+        })
+    }
+
+    /// Mark the Compute `instance` as reported, telling whether it already
+    /// was
+    pub fn mark_reported(&self, instance: &state::Instance) -> bool {
+        tracker::with(&self.disputes, |disputes| {
+            let dispute = disputes.entry(dispute_key(instance), DisputeJournal::default);
+            let reported = dispute.reported;
+            dispute.reported = true;
+            reported
+        })
+    }
+}
+
+impl Default for Journal {
+    fn default() -> Self {
+        Journal::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethabi::Token;
+    use tests::{build_concern, build_state, CLAIMERADDR};
+    use transaction::TransactionRequest;
+
+    #[test]
+    fn it_should_journal_reactions_until_forgotten() {
+        let journal = Journal::new();
+        let instance = build_state(build_concern(CLAIMERADDR), None);
+        let request = |i: usize| TransactionRequest {
+            contract_name: None,
+            concern: instance.concern.clone(),
+            value: U256::from(0),
+            function: "submitClaim".into(),
+            data: vec![Token::Uint(U256::from(i))],
+            gas: None,
+            strategy: transaction::Strategy::Simplest,
+        };
+
+        assert_eq!(journal.last_reaction(&instance), None);
+        journal.record(&instance, &Reaction::Idle);
+        assert_eq!(journal.last_reaction(&instance), Some(LastReaction::Idle));
+        assert!(journal.sent_transactions(&instance).is_empty());
+        for i in 0..MAX_JOURNAL_ENTRIES + 1 {
+            journal.record(&instance, &Reaction::Transaction(request(i)));
+        }
+        let sent = journal.sent_transactions(&instance);
+        assert_eq!(sent.len(), MAX_JOURNAL_ENTRIES);
+        assert_eq!(sent[0].function, "submitClaim");
+        assert_eq!(
+            sent[0].data,
+            vec![format!("{:?}", Token::Uint(U256::from(1)))]
+        );
+        assert_eq!(
+            journal.last_reaction(&instance),
+            Some(LastReaction::Transaction {
+                contract_address: instance.concern.contract_address,
+                function: "submitClaim".into(),
+            })
+        );
+        assert!(!journal.mark_reported(&instance));
+        assert!(journal.mark_reported(&instance));
+
+        journal.forget(&instance);
+        assert!(journal.sent_transactions(&instance).is_empty());
+        assert_eq!(journal.last_reaction(&instance), None);
+        assert!(!journal.mark_reported(&instance));
+    }
+
+    #[test]
+    fn it_should_journal_a_pending_transaction_once() {
+        let journal = Journal::new();
+        let instance = build_state(build_concern(CLAIMERADDR), None);
+        let request = |function: &str| {
+            Reaction::Transaction(TransactionRequest {
+                contract_name: None,
+                concern: instance.concern.clone(),
+                value: U256::from(0),
+                function: function.into(),
+                data: vec![Token::Uint(instance.index)],
+                gas: None,
+                strategy: transaction::Strategy::Simplest,
+            })
+        };
+
+        // polled again while the claim is pending
+        journal.record(&instance, &request("submitClaim"));
+        journal.record(&instance, &request("submitClaim"));
+        journal.record(&instance, &Reaction::Idle);
+        journal.record(&instance, &request("confirm"));
+        journal.record(&instance, &request("submitClaim"));
+        let functions: Vec<String> = journal
+            .sent_transactions(&instance)
+            .into_iter()
+            .map(|sent| sent.function)
+            .collect();
+        assert_eq!(functions, vec!["submitClaim", "confirm", "submitClaim"]);
+        // another journal knows nothing of the dispute
+        assert!(Journal::new().sent_transactions(&instance).is_empty());
+    }
+}
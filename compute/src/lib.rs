@@ -26,10 +26,31 @@
 // Apache v2 license.
 
 #![warn(unused_extern_crates)]
+pub mod archive_key;
+pub mod checked;
+pub mod clock;
+pub mod compatibility;
 pub mod compute;
 pub mod emulator_service;
+pub mod feasibility;
+pub mod forensic;
+pub mod input;
+pub mod journal;
+pub mod merkle;
 pub mod mm;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock_manager;
+pub mod output;
 pub mod partition;
+pub mod pretty;
+pub mod query_strategy;
+pub mod replay;
+pub mod run_progress;
+#[cfg(test)]
+mod simulator;
+pub mod states;
+pub mod tracker;
+pub mod urgency;
 pub mod vg;
 
 extern crate configuration;
@@ -39,19 +60,31 @@ extern crate grpc;
 #[macro_use]
 extern crate serde_derive;
 #[macro_use]
+extern crate ctx_derive;
+#[macro_use]
 extern crate log;
 extern crate dispatcher;
 extern crate emulator;
 extern crate ethabi;
 extern crate ethereum_types;
+extern crate keccak_hash;
 extern crate transaction;
 
+pub use archive_key::ArchiveKey;
+pub use checked::CheckedConversion;
+pub use clock::{BlockClock, Clock, SystemClock, DEFAULT_SAFETY_MARGIN};
+pub use compatibility::{
+    check_emulator_version, IncompatibleEmulator, VersionRange, SUPPORTED_EMULATOR_VERSIONS,
+    VERSION_RECHECK_SECONDS,
+};
 pub use compute::{
     win_by_deadline_or_idle, Compute, ComputeCtx, ComputeCtxParsed,
+    ComputeParams, DisputeParams, InitialHashMismatch, InstanceParams,
 };
-pub use emulator::{cartesi_machine, machine_manager};
+pub use emulator::{cartesi_machine, machine_manager, versioning};
 pub use emulator_service::{
-    AccessType, EndSessionRequest, NewSessionRequest, NewSessionResponse,
+    AccessType, EndSessionRequest, GetVersionRequest, GetVersionResponse,
+    NewSessionRequest, NewSessionResponse, SemanticVersion,
     SessionGetProofRequest, SessionGetProofResponse, SessionReadMemoryRequest,
     SessionReadMemoryResponse, SessionRunRequest, SessionRunResponse,
     SessionRunResponseOneOf, SessionRunResult, SessionStepRequest,
@@ -59,15 +92,35 @@ pub use emulator_service::{
     EMULATOR_METHOD_END,
     EMULATOR_METHOD_NEW, EMULATOR_METHOD_PROOF, EMULATOR_METHOD_READ,
     EMULATOR_METHOD_RUN, EMULATOR_METHOD_STEP, EMULATOR_METHOD_WRITE,
-    EMULATOR_METHOD_REPLACE,
+    EMULATOR_METHOD_REPLACE, EMULATOR_METHOD_VERSION,
     EMULATOR_SERVICE_NAME,
 };
+pub use feasibility::DeadlineAlert;
+pub use forensic::{ArchivedResponse, BundledInput, ForensicBundle, InstanceDump};
+pub use journal::{Journal, LastReaction, SentTransaction};
+pub use input::{DriveSource, InputDrive};
+pub use merkle::ProofError;
 pub use mm::MM;
-pub use partition::Partition;
+#[cfg(any(test, feature = "mock"))]
+pub use mock_manager::{MockMachineManager, ToyMachine};
+pub use output::{get_output, Output, OutputRange};
+pub use partition::{NoDisagreement, Partition};
+pub use pretty::Progress;
+pub use query_strategy::{Bisection, CheapestGas, QueryInterval, QueryStrategy};
+pub use replay::{
+    predict_settlement, replay_access_log, ReplayError, SettlementOutcome,
+};
+pub use run_progress::{RunEstimate, RunProgress};
+pub use states::{ComputeState, MMState, PartitionState, VGState};
+pub use urgency::{DefaultPolicy, TransactionPolicy, Urgency};
 pub use vg::{VGCtx, VGCtxParsed, VG};
 
-#[derive(Debug)]
-enum Role {
+use ethereum_types::H256;
+use std::convert::TryFrom;
+
+/// Side of a dispute
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
     Claimer,
     Challenger,
 }
@@ -79,82 +132,152 @@ enum Role {
 // blockchain.
 // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
 
-pub fn build_session_run_key(id: String, times: Vec<u64>) -> String {
-    return format!("{}_run_{:?}", id, times);
-}
-
-pub fn build_session_step_key(id: String, divergence_time: String) -> String {
-    return format!("{}_step_{}", id, divergence_time);
-}
-
-pub fn build_session_end_key(id: String) -> String {
-    return format!("{}_end", id);
-}
+/// Fetch an emulator response from the archive and decode it. A reply
+/// that cannot be decoded, for instance from an emulator of another
+/// version, is reported as a service failure to be retried
+pub fn get_emulator_response<T>(
+    archive: &dispatcher::Archive,
+    contract: String,
+    key: String,
+    method: &str,
+    request: Vec<u8>,
+) -> error::Result<T>
+where
+    T: TryFrom<Vec<u8>, Error = error::Error>,
+{
+    let response = archive.get_response(
+        EMULATOR_SERVICE_NAME.to_string(),
+        key.clone(),
+        method.to_string(),
+        request.clone(),
+    )?;
 
-pub fn build_session_read_key(
-    id: String,
-    time: u64,
-    address: u64,
-    length: u64,
-) -> String {
-    return format!("{}_read_{}_{}_{}", id, time, address, length);
+    T::try_from(response).map_err(|e| {
+        error!("Invalid emulator response to {} ({}): {}", method, key, e);
+        error::Error::from(error::ErrorKind::ServiceNeedsRetry(
+            EMULATOR_SERVICE_NAME.to_string(),
+            key,
+            method.to_string(),
+            request,
+            contract,
+            1,
+            0,
+            format!("invalid emulator response: {}", e),
+        ))
+    })
 }
 
-pub fn build_session_write_key(
+/// Open the emulator session `id` running `machine`, returning the hash
+/// of its initial state
+pub fn new_session(
+    archive: &dispatcher::Archive,
+    contract: String,
     id: String,
-    time: u64,
-    address: u64,
-    data: Vec<u8>,
-) -> String {
-    return format!("{}_write_{}_{}_{:?}", id, time, address, data);
+    machine: cartesi_machine::MachineRequest,
+) -> error::Result<H256> {
+    let request = NewSessionRequest {
+        machine,
+        session_id: id.clone(),
+        force: false,
+    };
+    let response: NewSessionResponse = get_emulator_response(
+        archive,
+        contract,
+        ArchiveKey::New { id }.to_string(),
+        EMULATOR_METHOD_NEW,
+        request.into(),
+    )?;
+    Ok(response.hash)
 }
 
-pub fn build_session_replace_key(
-    id: String,
-    time: u64,
-    address: u64,
-    path: String,
-) -> String {
-    return format!("{}_write_{}_{}_{:?}", id, time, address, path);
+/// End the emulator session `id`; ending a session that is already gone
+/// is not an error
+pub fn end_session(archive: &dispatcher::Archive, id: String) -> error::Result<()> {
+    let request = EndSessionRequest {
+        session_id: id.clone(),
+        silent: true,
+    };
+    archive.get_response(
+        EMULATOR_SERVICE_NAME.to_string(),
+        ArchiveKey::End { id }.to_string(),
+        EMULATOR_METHOD_END.to_string(),
+        request.into(),
+    )?;
+    Ok(())
 }
 
-pub fn build_session_proof_key(
+/// Replace the memory range of session `id` described by `range` with the
+/// contents of its image file, starting from cycle 0
+pub fn replace_memory_range(
+    archive: &dispatcher::Archive,
     id: String,
-    time: u64,
-    address: u64,
-    log2_size: u64,
-) -> String {
-    return format!("{}_proof_{}_{}_{}", id, time, address, log2_size);
+    range: cartesi_machine::MemoryRangeConfig,
+) -> error::Result<()> {
+    let archive_key = ArchiveKey::Replace {
+        id: id.clone(),
+        time: 0,
+        address: range.get_start(),
+        path: range.get_image_filename().to_string(),
+    }
+    .to_string();
+    let request = SessionReplaceMemoryRangeRequest {
+        session_id: id,
+        time: 0,
+        range,
+    };
+    archive.get_response(
+        EMULATOR_SERVICE_NAME.to_string(),
+        archive_key,
+        EMULATOR_METHOD_REPLACE.to_string(),
+        request.into(),
+    )?;
+    Ok(())
 }
 
+/// Result of the run archived under `key`. While the machine is still
+/// running, its progress is sampled into `runs` to estimate when it
+/// completes
 pub fn get_run_result(
     archive: &dispatcher::Archive,
     contract: String,
-    key: String,
+    key: &ArchiveKey,
     request: Vec<u8>,
+    runs: Option<&RunProgress>,
 ) -> error::Result<SessionRunResult> {
-    let processed_response: SessionRunResponse = archive
-        .get_response(
-            EMULATOR_SERVICE_NAME.to_string(),
-            key.clone(),
-            EMULATOR_METHOD_RUN.to_string(),
-            request.clone(),
-        )?
-        .into();
+    let archive_key = key.to_string();
+    let processed_response: SessionRunResponse = get_emulator_response(
+        archive,
+        contract.clone(),
+        archive_key.clone(),
+        EMULATOR_METHOD_RUN,
+        request.clone(),
+    )?;
 
     match processed_response.one_of {
-        SessionRunResponseOneOf::RunResult(s) => Ok(s),
+        SessionRunResponseOneOf::RunResult(s) => {
+            if let Some(runs) = runs {
+                runs.forget(key);
+            }
+            Ok(s)
+        }
         SessionRunResponseOneOf::RunProgress(p) => {
-            error!("Fail to get machine run result, progress: {}", p.progress);
+            let description = match runs.and_then(|runs| runs.record(key, &p)) {
+                Some(estimate) => format!("machine still running: {}", estimate),
+                None => "machine still running".to_string(),
+            };
+            error!(
+                "Fail to get machine run result ({}): {}",
+                archive_key, description
+            );
             Err(error::Error::from(error::ErrorKind::ServiceNeedsRetry(
                 EMULATOR_SERVICE_NAME.to_string(),
-                key,
+                archive_key,
                 EMULATOR_METHOD_RUN.to_string(),
                 request,
                 contract,
                 1,
                 p.progress,
-                "machine stil running".to_string(),
+                description,
             )))
         }
     }
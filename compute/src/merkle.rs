@@ -0,0 +1,390 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! Off-chain verification of the Merkle tree proofs returned by the
+//! emulator, mirroring the `Merkle` library used by the contracts.
+
+use super::ethereum_types::H256;
+use super::keccak_hash::keccak;
+use emulator_service::MerkleTreeProof;
+
+use std::fmt;
+
+/// Log2 of the size of a machine word, the smallest provable target
+pub const LOG2_WORD_SIZE: u64 = 3;
+/// Log2 of the size of the whole machine address space
+pub const LOG2_MACHINE_SIZE: u64 = 64;
+
+/// Reasons for which a `MerkleTreeProof` can be rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProofError {
+    /// Target or root sizes are outside the machine address space
+    InvalidSize {
+        log2_target_size: u64,
+        log2_root_size: u64,
+    },
+    /// Target address is not aligned to the target size
+    MisalignedAddress { address: u64, log2_target_size: u64 },
+    /// Number of sibling hashes does not match the tree height
+    WrongSiblingCount { expected: usize, found: usize },
+    /// Target hash does not match the hash of the proven value
+    TargetMismatch { expected: H256, found: H256 },
+    /// Siblings do not lead from the target hash to the root hash
+    RootMismatch { expected: H256, computed: H256 },
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProofError::InvalidSize {
+                log2_target_size,
+                log2_root_size,
+            } => write!(
+                f,
+                "invalid proof sizes (log2 target: {}, log2 root: {})",
+                log2_target_size, log2_root_size
+            ),
+            ProofError::MisalignedAddress {
+                address,
+                log2_target_size,
+            } => write!(
+                f,
+                "address {} is not aligned to log2 size {}",
+                address, log2_target_size
+            ),
+            ProofError::WrongSiblingCount { expected, found } => write!(
+                f,
+                "expected {} sibling hashes, found {}",
+                expected, found
+            ),
+            ProofError::TargetMismatch { expected, found } => write!(
+                f,
+                "target hash {:?} does not match value hash {:?}",
+                found, expected
+            ),
+            ProofError::RootMismatch { expected, computed } => write!(
+                f,
+                "computed root hash {:?} does not match {:?}",
+                computed, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProofError {}
+
+/// Hash of a single machine word, as stored in the leaves of the tree
+pub fn word_hash(word: &[u8; 8]) -> H256 {
+    keccak(&word[..])
+}
+
+/// Hash of the concatenation of two sibling nodes
+pub fn concat_hash(left: &H256, right: &H256) -> H256 {
+    let mut data = [0u8; 64];
+    data[..32].copy_from_slice(left.as_bytes());
+    data[32..].copy_from_slice(right.as_bytes());
+    keccak(&data[..])
+}
+
+/// Root hash of a tree of the given size in which every word is zero
+pub fn pristine_hash(log2_size: u64) -> H256 {
+    let mut hash = word_hash(&[0u8; 8]);
+    for _ in LOG2_WORD_SIZE..log2_size {
+        hash = concat_hash(&hash, &hash);
+    }
+    hash
+}
+
+/// Root hash of the tree whose leaves are the words of `data`, which must
+/// span a power of two number of words
+pub fn range_hash(data: &[u8]) -> Option<H256> {
+    let words = data.len() >> LOG2_WORD_SIZE;
+    if data.len() % 8 != 0 || !words.is_power_of_two() {
+        return None;
+    }
+    let mut level: Vec<H256> = data
+        .chunks(8)
+        .map(|chunk| {
+            let mut word = [0u8; 8];
+            word.copy_from_slice(chunk);
+            word_hash(&word)
+        })
+        .collect();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| concat_hash(&pair[0], &pair[1]))
+            .collect();
+    }
+    level.pop()
+}
+
+/// Recompute the root hash of a tree from a target hash and its siblings.
+///
+/// Siblings are expected in the emulator order, i.e. the first entry is
+/// the sibling closest to the root.
+pub fn compute_root(
+    address: u64,
+    log2_target_size: u64,
+    log2_root_size: u64,
+    target_hash: &H256,
+    sibling_hashes: &[H256],
+) -> Result<H256, ProofError> {
+    if log2_target_size < LOG2_WORD_SIZE
+        || log2_root_size > LOG2_MACHINE_SIZE
+        || log2_target_size > log2_root_size
+    {
+        return Err(ProofError::InvalidSize {
+            log2_target_size,
+            log2_root_size,
+        });
+    }
+    if log2_target_size < LOG2_MACHINE_SIZE
+        && address & ((1u64 << log2_target_size) - 1) != 0
+    {
+        return Err(ProofError::MisalignedAddress {
+            address,
+            log2_target_size,
+        });
+    }
+    let height = (log2_root_size - log2_target_size) as usize;
+    if sibling_hashes.len() != height {
+        return Err(ProofError::WrongSiblingCount {
+            expected: height,
+            found: sibling_hashes.len(),
+        });
+    }
+
+    let mut hash = *target_hash;
+    for log2_size in log2_target_size..log2_root_size {
+        let sibling = &sibling_hashes[(log2_root_size - 1 - log2_size) as usize];
+        if (address >> log2_size) & 1 == 0 {
+            hash = concat_hash(&hash, sibling);
+        } else {
+            hash = concat_hash(sibling, &hash);
+        }
+    }
+    Ok(hash)
+}
+
+impl MerkleTreeProof {
+    /// Root hash obtained by replacing the target with `target_hash`
+    pub fn root_with_target(&self, target_hash: &H256) -> Result<H256, ProofError> {
+        compute_root(
+            self.address,
+            self.log2_target_size,
+            self.log2_root_size,
+            target_hash,
+            &self.sibling_hashes,
+        )
+    }
+
+    /// Check that the siblings lead from `target_hash` to `root_hash`
+    pub fn verify(&self) -> Result<(), ProofError> {
+        let computed = self.root_with_target(&self.target_hash)?;
+        if computed != self.root_hash {
+            return Err(ProofError::RootMismatch {
+                expected: self.root_hash,
+                computed,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check that this is a valid proof of `word` at the proof address
+    pub fn verify_word(&self, word: &[u8; 8]) -> Result<(), ProofError> {
+        if self.log2_target_size != LOG2_WORD_SIZE {
+            return Err(ProofError::InvalidSize {
+                log2_target_size: self.log2_target_size,
+                log2_root_size: self.log2_root_size,
+            });
+        }
+        let expected = word_hash(word);
+        if expected != self.target_hash {
+            return Err(ProofError::TargetMismatch {
+                expected,
+                found: self.target_hash,
+            });
+        }
+        self.verify()
+    }
+
+    /// Check that this is a valid proof of the range holding `data` at the
+    /// proof address
+    pub fn verify_range(&self, data: &[u8]) -> Result<(), ProofError> {
+        let expected = match range_hash(data) {
+            Some(hash)
+                if self.log2_target_size < LOG2_MACHINE_SIZE
+                    && data.len() as u64 == 1u64 << self.log2_target_size =>
+            {
+                hash
+            }
+            _ => {
+                return Err(ProofError::InvalidSize {
+                    log2_target_size: self.log2_target_size,
+                    log2_root_size: self.log2_root_size,
+                })
+            }
+        };
+        if expected != self.target_hash {
+            return Err(ProofError::TargetMismatch {
+                expected,
+                found: self.target_hash,
+            });
+        }
+        self.verify()
+    }
+
+    /// Sibling hashes ordered from the leaf up, as expected by the
+    /// `Merkle.getRoot` function of the contracts
+    pub fn contract_siblings(&self) -> Vec<H256> {
+        self.sibling_hashes.iter().rev().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Proof of `word` at `address` in an otherwise pristine machine
+    pub fn build_word_proof(address: u64, word: [u8; 8]) -> MerkleTreeProof {
+        let sibling_hashes: Vec<H256> = (LOG2_WORD_SIZE..LOG2_MACHINE_SIZE)
+            .rev()
+            .map(|log2_size| pristine_hash(log2_size))
+            .collect();
+        let target_hash = word_hash(&word);
+        let root_hash = compute_root(
+            address,
+            LOG2_WORD_SIZE,
+            LOG2_MACHINE_SIZE,
+            &target_hash,
+            &sibling_hashes,
+        )
+        .unwrap();
+        MerkleTreeProof {
+            address,
+            log2_target_size: LOG2_WORD_SIZE,
+            log2_root_size: LOG2_MACHINE_SIZE,
+            target_hash,
+            sibling_hashes,
+            root_hash,
+        }
+    }
+
+    #[test]
+    fn it_should_match_pristine_root() {
+        let proof = build_word_proof(0x1000, [0u8; 8]);
+        assert_eq!(proof.root_hash, pristine_hash(LOG2_MACHINE_SIZE));
+        assert_eq!(proof.verify(), Ok(()));
+        assert_eq!(proof.verify_word(&[0u8; 8]), Ok(()));
+    }
+
+    #[test]
+    fn it_should_verify_word_proof() {
+        let word = [0, 1, 2, 3, 4, 5, 6, 7];
+        let proof = build_word_proof(0x8000_0000_0000_0008, word);
+        assert_ne!(proof.root_hash, pristine_hash(LOG2_MACHINE_SIZE));
+        assert_eq!(proof.verify(), Ok(()));
+        assert_eq!(proof.verify_word(&word), Ok(()));
+        assert!(matches!(
+            proof.verify_word(&[0u8; 8]),
+            Err(ProofError::TargetMismatch { .. })
+        ));
+        assert_eq!(
+            proof.contract_siblings().first(),
+            proof.sibling_hashes.last()
+        );
+    }
+
+    #[test]
+    fn it_should_reject_malformed_proofs() {
+        let word = [7u8; 8];
+        let proof = build_word_proof(0x100, word);
+        {
+            let mut bad = proof.clone();
+            bad.root_hash = H256::zero();
+            assert!(matches!(
+                bad.verify(),
+                Err(ProofError::RootMismatch { .. })
+            ));
+        }
+        {
+            let mut bad = proof.clone();
+            bad.address = 0x108;
+            assert!(matches!(
+                bad.verify(),
+                Err(ProofError::RootMismatch { .. })
+            ));
+        }
+        {
+            let mut bad = proof.clone();
+            bad.address = 100;
+            assert!(matches!(
+                bad.verify(),
+                Err(ProofError::MisalignedAddress { .. })
+            ));
+        }
+        {
+            let mut bad = proof.clone();
+            bad.sibling_hashes.pop();
+            assert_eq!(
+                bad.verify(),
+                Err(ProofError::WrongSiblingCount {
+                    expected: 61,
+                    found: 60
+                })
+            );
+        }
+        {
+            let mut bad = proof.clone();
+            bad.log2_root_size = 65;
+            assert!(matches!(
+                bad.verify(),
+                Err(ProofError::InvalidSize { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn it_should_verify_range_proof() {
+        // a pristine range hashes like the pristine tree of its size
+        assert_eq!(range_hash(&[0u8; 32]), Some(pristine_hash(5)));
+        assert_eq!(range_hash(&[0u8; 24]), None);
+        assert_eq!(range_hash(&[0u8; 7]), None);
+
+        let word = [7u8; 8];
+        let proof = build_word_proof(0x2000, word);
+        assert_eq!(range_hash(&word), Some(proof.target_hash));
+        assert_eq!(proof.verify_range(&word), Ok(()));
+        assert!(matches!(
+            proof.verify_range(&[0u8; 8]),
+            Err(ProofError::TargetMismatch { .. })
+        ));
+        assert!(matches!(
+            proof.verify_range(&[7u8; 16]),
+            Err(ProofError::InvalidSize { .. })
+        ));
+    }
+}
@@ -23,7 +23,7 @@
 // be used independently under the Apache v2 license. After this component is
 // rewritten, the entire component will be released under the Apache v2 license.
 
-use super::build_session_step_key;
+use super::ArchiveKey;
 use super::dispatcher::{AddressField, Bytes32Field, String32Field, U256Field};
 use super::dispatcher::{Archive, DApp, Reaction};
 use super::error::Result;
@@ -31,59 +31,108 @@ use super::error::*;
 use super::ethabi::Token;
 use super::ethereum_types::{Address, H256, U256};
 use super::transaction::TransactionRequest;
+use emulator_service::Access;
+use pretty::{Pretty, Progress};
+use replay::{predict_settlement, replay_access_log};
 use super::{
-    AccessType, SessionStepRequest, SessionStepResponse, EMULATOR_METHOD_STEP,
-    EMULATOR_SERVICE_NAME,
+    get_emulator_response, AccessType, Role, SessionStepRequest, SessionStepResponse,
+    EMULATOR_METHOD_STEP,
 };
+use checked::CheckedConversion;
+use states::MMState;
+use urgency::{DefaultPolicy, TransactionPolicy, Urgency};
+
+use std::convert::TryFrom;
 
 pub struct MM();
 
-// !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
-// these two structs and the From trait below shuld be
-// obtained from a simple derive
-// !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
-#[derive(Serialize, Deserialize)]
-pub struct MMCtxParsed(
-    pub AddressField,  // provider
-    pub Bytes32Field,  // initialHash
-    pub Bytes32Field,  // newHash
-    pub U256Field,     // historyLength
-    pub String32Field, // currentState
-);
-
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, ContractCtx)]
+#[ctx(parsed = "MMCtxParsed")]
 pub struct MMCtx {
+    #[ctx(field = "AddressField")]
     pub provider: Address,
+    #[ctx(field = "Bytes32Field")]
     pub initial_hash: H256,
+    /// `newHash`
+    #[ctx(field = "Bytes32Field")]
     pub final_hash: H256,
+    #[ctx(field = "U256Field")]
     pub history_length: U256,
-    pub current_state: String,
+    #[ctx(field = "String32Field", parse)]
+    pub current_state: MMState,
 }
 
-#[derive(Default)]
-pub struct MMParams {
+pub struct MMParams<'a> {
     pub machine_id: String,
     pub divergence_time: U256,
+    pub hash_after_divergence: H256,
+    /// Urgency of the enclosing verification game deadline
+    pub urgency: Urgency,
+    pub policy: &'a dyn TransactionPolicy,
 }
 
-impl From<MMCtxParsed> for MMCtx {
-    fn from(parsed: MMCtxParsed) -> MMCtx {
-        MMCtx {
-            provider: parsed.0.value,
-            initial_hash: parsed.1.value,
-            final_hash: parsed.2.value,
-            history_length: parsed.3.value,
-            current_state: parsed.4.value,
+impl<'a> Default for MMParams<'a> {
+    fn default() -> Self {
+        MMParams {
+            machine_id: String::default(),
+            divergence_time: U256::default(),
+            hash_after_divergence: H256::default(),
+            urgency: Urgency::default(),
+            policy: &DefaultPolicy,
         }
     }
 }
 
-impl DApp<MMParams> for MM {
+/// Access log of the step of machine `id` at `divergence_time`
+pub fn get_step_log(
+    archive: &Archive,
+    contract: &str,
+    id: &str,
+    divergence_time: U256,
+) -> Result<Vec<Access>> {
+    trace!("Calculating step of machine {}", id);
+    let time = divergence_time.to_u64("divergence time")?;
+    let request = SessionStepRequest {
+        session_id: id.to_string(),
+        time,
+    };
+    let archive_key = ArchiveKey::Step {
+        id: id.to_string(),
+        time,
+    }
+    .to_string();
+
+    // have we sampled the divergence time?
+    let processed_response: SessionStepResponse = get_emulator_response(
+        archive,
+        contract.to_string(),
+        archive_key,
+        EMULATOR_METHOD_STEP,
+        request.into(),
+    )?;
+    Ok(processed_response.log)
+}
+
+/// Whether the values proven so far in the MM instance agree with
+/// `step_log`. Reads leave the memory untouched, so only the proven
+/// writes can be checked, through the hash they lead to
+pub fn proofs_agree_with(ctx: &MMCtx, step_log: &[Access]) -> bool {
+    let proven = match ctx.history_length.to_usize("MM history length") {
+        Ok(proven) if proven <= step_log.len() => proven,
+        _ => return false,
+    };
+    match replay_access_log(ctx.initial_hash, &step_log[..proven]) {
+        Ok(hash) => hash == ctx.final_hash,
+        Err(_) => false,
+    }
+}
+
+impl<'a> DApp<MMParams<'a>> for MM {
     fn react(
         instance: &state::Instance,
         archive: &Archive,
         _post_payload: &Option<String>,
-        params: &MMParams,
+        params: &MMParams<'a>,
     ) -> Result<Reaction> {
         let parsed: MMCtxParsed = serde_json::from_str(&instance.json_data).chain_err(|| {
             format!(
@@ -91,45 +140,46 @@ impl DApp<MMParams> for MM {
                 &instance.json_data
             )
         })?;
-        let ctx: MMCtx = parsed.into();
+        let ctx = MMCtx::try_from(parsed)?;
 
         trace!("Context for mm {:?}", ctx);
 
         // should not happen as it indicates an innactive instance,
         // but it is possible that the blockchain state changed between queries
-        match ctx.current_state.as_ref() {
-            "FinishedReplay" => {
+        match ctx.current_state {
+            MMState::FinishedReplay => {
                 return Ok(Reaction::Idle);
             }
             _ => {}
         };
 
-        match ctx.current_state.as_ref() {
-            "WaitingProofs" => {
+        match ctx.current_state {
+            MMState::WaitingProofs => {
                 // machine id
                 let id = params.machine_id.clone();
-                trace!("Calculating step of machine {}", id);
-                let request = SessionStepRequest {
-                    session_id: id.clone(),
-                    time: params.divergence_time.as_u64(),
-                };
-                let archive_key =
-                    build_session_step_key(id.clone(), params.divergence_time.to_string());
-
-                // have we sampled the divergence time?
-                let processed_response: SessionStepResponse = archive
-                    .get_response(
-                        EMULATOR_SERVICE_NAME.to_string(),
-                        archive_key.clone(),
-                        EMULATOR_METHOD_STEP.to_string(),
-                        request.into(),
-                    )?
-                    .into();
-
-                let step_log = processed_response.log;
+                let step_log = get_step_log(archive, "MM", &id, params.divergence_time)?;
+                // replay the whole log locally, so that proofs the contract
+                // would reject are caught before sending any transaction
+                let outcome = predict_settlement(
+                    ctx.initial_hash,
+                    params.hash_after_divergence,
+                    &step_log,
+                )
+                .chain_err(|| {
+                    format!(
+                        "Invalid proof from emulator in step log of machine {} at time {}",
+                        id, params.divergence_time
+                    )
+                })?;
+                trace!("Predicted settlement for MM (index: {}): {:?}", instance.index, outcome);
+
                 // if all proofs have been inserted, finish proof phase
-                if ctx.history_length.as_usize() >= step_log.len() {
-                    info!("Finishing Proof phase for MM (index: {})", instance.index);
+                let history_length = ctx.history_length.to_usize("MM history length")?;
+                if history_length >= step_log.len() {
+                    info!(
+                        "Finishing Proof phase for MM (index: {}, predicted: {:?})",
+                        instance.index, outcome
+                    );
                     let request = TransactionRequest {
                         contract_name: None, // Name not needed, is concern
                         concern: instance.concern.clone(),
@@ -141,24 +191,21 @@ impl DApp<MMParams> for MM {
                         // them inside the transaction manager
                         // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
                         data: vec![Token::Uint(instance.index)],
-                        gas: None,
-                        strategy: transaction::Strategy::Simplest,
+                        gas: params.policy.gas(params.urgency),
+                        strategy: params.policy.strategy(params.urgency),
                     };
                     return Ok(Reaction::Transaction(request));
                 }
 
                 // otherwise, submit one more proof step
-                let access = (&step_log[ctx.history_length.as_usize()]).clone();
-                let mut siblings: Vec<_> = access
+                let access = (&step_log[history_length]).clone();
+                let siblings: Vec<_> = access
                     .proof
-                    .sibling_hashes
+                    .contract_siblings()
                     .into_iter()
                     .map(|hash| Token::FixedBytes(hash.0.to_vec()))
                     .collect();
                 trace!("Size of siblings: {}", siblings.len());
-                // !!!!! This should not be necessary, !!!!!!!
-                // !!!!! the emulator should do it     !!!!!!!
-                siblings.reverse();
                 match access.field_type {
                     AccessType::Read => {
                         let request = TransactionRequest {
@@ -177,8 +224,8 @@ impl DApp<MMParams> for MM {
                                 Token::FixedBytes(access.value_read.to_vec()),
                                 Token::Array(siblings),
                             ],
-                            gas: None,
-                            strategy: transaction::Strategy::Simplest,
+                            gas: params.policy.gas(params.urgency),
+                            strategy: params.policy.strategy(params.urgency),
                         };
                         return Ok(Reaction::Transaction(request));
                     }
@@ -200,14 +247,14 @@ impl DApp<MMParams> for MM {
                                 Token::FixedBytes(access.value_written.to_vec()),
                                 Token::Array(siblings),
                             ],
-                            gas: None,
-                            strategy: transaction::Strategy::Simplest,
+                            gas: params.policy.gas(params.urgency),
+                            strategy: params.policy.strategy(params.urgency),
                         };
                         return Ok(Reaction::Transaction(request));
                     }
                 }
             }
-            _ => {}
+            MMState::WaitingReplay | MMState::FinishedReplay => {}
         }
 
         return Ok(Reaction::Idle);
@@ -216,7 +263,7 @@ impl DApp<MMParams> for MM {
     fn get_pretty_instance(
         instance: &state::Instance,
         archive: &Archive,
-        _params: &MMParams,
+        params: &MMParams<'a>,
     ) -> Result<state::Instance> {
         // get context (state) of the mm instance
         let parsed: MMCtxParsed = serde_json::from_str(&instance.json_data).chain_err(|| {
@@ -225,8 +272,23 @@ impl DApp<MMParams> for MM {
                 &instance.json_data
             )
         })?;
-        let ctx: MMCtx = parsed.into();
-        let json_data = serde_json::to_string(&ctx).unwrap();
+        let ctx = MMCtx::try_from(parsed)?;
+        // the challenger provides the memory values and settles the game
+        let role = match instance.concern.user_address {
+            provider if provider == ctx.provider => Some(Role::Challenger),
+            _ => None,
+        };
+        let mut progress = Progress::new(role, Some(Role::Challenger));
+        progress.urgency = Some(params.urgency);
+        progress.proofs_submitted = ctx.history_length.to_u64("MM history length").ok();
+        progress.proofs_total = get_step_log(archive, "MM", &params.machine_id, params.divergence_time)
+            .ok()
+            .map(|log| log.len() as u64);
+        let json_data = serde_json::to_string(&Pretty {
+            ctx: &ctx,
+            progress,
+        })
+        .unwrap();
 
         // get context (state) of the sub instances
 
@@ -249,11 +311,24 @@ pub mod tests {
 
     use super::*;
     use emulator_service;
+    use merkle;
     use tests::{
         build_concern, build_state, encode, CONTRACTADDR, MACHINEID, UNKNOWNSTATE,
     };
 
     pub fn build_mm_state_json_data(current_state: &str, history_length: Option<&str>) -> String {
+        build_mm_state_json_data_with_hash(
+            current_state,
+            history_length,
+            "0xa70817cd86277772e8f71cfe28d32da866b05f981d80e4d17eae915321930000",
+        )
+    }
+
+    pub fn build_mm_state_json_data_with_hash(
+        current_state: &str,
+        history_length: Option<&str>,
+        initial_hash: &str,
+    ) -> String {
         let _history_length = history_length.unwrap_or("0x0");
         let data = serde_json::json!([
         {"name": "provider",
@@ -261,7 +336,7 @@ pub mod tests {
         "type": "address"},
 
         {"name": "initial_hash",
-        "value": "0xa70817cd86277772e8f71cfe28d32da866b05f981d80e4d17eae915321930000",
+        "value": initial_hash,
         "type": "bytes32"},
 
         {"name": "new_hash",
@@ -286,6 +361,7 @@ pub mod tests {
         let mm_params = MMParams {
             machine_id: String::from(MACHINEID),
             divergence_time,
+            ..Default::default()
         };
         let current_state = encode("FinishedReplay"); // FinishedReplay,
         let archive = Archive::new().unwrap();
@@ -306,31 +382,82 @@ pub mod tests {
             let current_state = encode(UNKNOWNSTATE);
             state_instance.json_data = build_mm_state_json_data(current_state.as_str(), None);
             let result = MM::react(&state_instance, &archive, &None, &mm_params);
-            assert!(matches!(result.unwrap(), Reaction::Idle));
+            match result {
+                Err(e) => assert!(format!("{:?}", e).contains("Unknown current state Unknown State")),
+                Ok(_) => panic!("Unknown state should be an error"),
+            }
         }
     }
 
+    #[test]
+    fn it_should_check_proofs_against_step_log() {
+        let old = [0, 1, 2, 3, 4, 5, 6, 7];
+        let new = [7; 8];
+        let proof = merkle::tests::build_word_proof(0x100, old);
+        let initial_hash = proof.root_hash;
+        let written_hash = proof.root_with_target(&merkle::word_hash(&new)).unwrap();
+        let step_log = vec![emulator_service::Access {
+            field_type: emulator_service::AccessType::Write,
+            address: 0x100,
+            value_read: old,
+            value_written: new,
+            proof,
+        }];
+        let ctx = |history_length: u64, final_hash: H256| MMCtx {
+            provider: Address::zero(),
+            initial_hash,
+            final_hash,
+            history_length: U256::from(history_length),
+            current_state: MMState::WaitingProofs,
+        };
+        assert!(proofs_agree_with(&ctx(0, initial_hash), &step_log));
+        assert!(proofs_agree_with(&ctx(1, written_hash), &step_log));
+        // the challenger proved another written value
+        assert!(!proofs_agree_with(&ctx(1, initial_hash), &step_log));
+        assert!(!proofs_agree_with(&ctx(2, written_hash), &step_log));
+        // a history beyond any log never agrees, nor panics
+        let mut hostile = ctx(0, initial_hash);
+        hostile.history_length = U256::max_value();
+        assert!(!proofs_agree_with(&hostile, &step_log));
+    }
+
+    #[test]
+    fn it_should_reject_divergence_time_beyond_64_bits() {
+        let mm_params = MMParams {
+            machine_id: String::from(MACHINEID),
+            divergence_time: U256::from(u64::max_value()) + 1,
+            ..Default::default()
+        };
+        let mut state_instance = build_state(build_concern(CONTRACTADDR), None);
+        state_instance.json_data =
+            build_mm_state_json_data(encode("WaitingProofs").as_str(), None);
+
+        let error = MM::react(&state_instance, &Archive::new().unwrap(), &None, &mm_params)
+            .unwrap_err();
+        let error = format!("{:?}", error);
+        assert!(
+            error.contains("divergence time 18446744073709551616 is out of range"),
+            "{}",
+            error
+        );
+    }
+
     #[test]
     fn it_should_work_waiting_proofs_correclty() {
         let divergence_time = U256::from("200");
         let mm_params = MMParams {
             machine_id: String::from(MACHINEID),
             divergence_time,
+            ..Default::default()
         };
         let current_state = encode("WaitingProofs");
 
         let mut archive = Archive::new().unwrap();
-        let proof = emulator_service::MerkleTreeProof {
-            address: 100,
-            log2_target_size: 2,
-            log2_root_size: 63,
-            target_hash: H256::zero(),
-            root_hash: H256::zero(),
-            sibling_hashes: vec![H256::zero(), H256::zero(), H256::zero(), H256::zero()],
-        };
+        let proof = merkle::tests::build_word_proof(0x100, [0, 1, 2, 3, 4, 5, 6, 7]);
+        let initial_hash = format!("{:?}", proof.root_hash);
         let mut access = emulator_service::Access {
             field_type: emulator_service::AccessType::Read,
-            address: 100,
+            address: 0x100,
             value_read: [0, 1, 2, 3, 4, 5, 6, 7],
             value_written: [0, 1, 2, 3, 4, 5, 6, 7],
             proof,
@@ -339,14 +466,16 @@ pub mod tests {
             log: vec![access.clone()],
         }
         .into();
-        let archive_key = build_session_step_key(
-            String::from(MACHINEID),
-            mm_params.divergence_time.to_string(),
-        );
+        let archive_key = ArchiveKey::Step {
+            id: String::from(MACHINEID),
+            time: mm_params.divergence_time.as_u64(),
+        }
+        .to_string();
         let concern = build_concern(CONTRACTADDR);
 
         let mut state_instance = build_state(concern, None);
-        state_instance.json_data = build_mm_state_json_data(current_state.as_str(), None);
+        state_instance.json_data =
+            build_mm_state_json_data_with_hash(current_state.as_str(), None, &initial_hash);
 
         {
             //proveRead
@@ -389,8 +518,11 @@ pub mod tests {
         }
         {
             // Finish Proof
-            state_instance.json_data =
-                build_mm_state_json_data(current_state.as_str(), Option::from("0x10"));
+            state_instance.json_data = build_mm_state_json_data_with_hash(
+                current_state.as_str(),
+                Option::from("0x10"),
+                &initial_hash,
+            );
             let result = MM::react(&state_instance, &archive, &None, &mm_params);
             let mut reaction = result.unwrap();
             assert!(matches!(
@@ -407,14 +539,53 @@ pub mod tests {
         }
     }
 
+    #[test]
+    #[should_panic(expected = "Invalid proof from emulator")]
+    fn it_should_reject_invalid_proofs() {
+        let divergence_time = U256::from("200");
+        let mm_params = MMParams {
+            machine_id: String::from(MACHINEID),
+            divergence_time,
+            ..Default::default()
+        };
+        let current_state = encode("WaitingProofs");
+
+        let mut archive = Archive::new().unwrap();
+        let mut proof = merkle::tests::build_word_proof(0x100, [0, 1, 2, 3, 4, 5, 6, 7]);
+        let initial_hash = format!("{:?}", proof.root_hash);
+        proof.sibling_hashes[0] = H256::zero();
+        let access = emulator_service::Access {
+            field_type: emulator_service::AccessType::Read,
+            address: 0x100,
+            value_read: [0, 1, 2, 3, 4, 5, 6, 7],
+            value_written: [0, 1, 2, 3, 4, 5, 6, 7],
+            proof,
+        };
+        let bin: Vec<u8> = SessionStepResponse { log: vec![access] }.into();
+        let archive_key = ArchiveKey::Step {
+            id: String::from(MACHINEID),
+            time: mm_params.divergence_time.as_u64(),
+        }
+        .to_string();
+        archive.insert_response(archive_key, Ok(bin));
+
+        let concern = build_concern(CONTRACTADDR);
+        let mut state_instance = build_state(concern, None);
+        state_instance.json_data =
+            build_mm_state_json_data_with_hash(current_state.as_str(), None, &initial_hash);
+
+        MM::react(&state_instance, &archive, &None, &mm_params).unwrap();
+    }
+
     #[test]
     fn it_should_get_pretty_instance_correctly() {
         let divergence_time = U256::from("200");
         let mm_params = MMParams {
             machine_id: String::from(MACHINEID),
             divergence_time,
+            ..Default::default()
         };
-        let current_state = encode("ChallengerWon"); // ChallengerWon,
+        let current_state = encode("WaitingReplay"); // WaitingReplay,
         let archive = Archive::new().unwrap();
         let concern = build_concern(CONTRACTADDR);
 
@@ -435,8 +606,14 @@ pub mod tests {
         );
         assert_eq!(serde_json::json!("0x0"), pretty_json["history_length"]);
         assert_eq!(
-            serde_json::json!("ChallengerWon"),
+            serde_json::json!("WaitingReplay"),
             pretty_json["current_state"]
         );
+        // the step log is not archived yet
+        let progress = &pretty_json["progress"];
+        assert_eq!(serde_json::json!("Challenger"), progress["role"]);
+        assert_eq!(serde_json::json!(true), progress["our_move"]);
+        assert_eq!(serde_json::json!(0), progress["proofs_submitted"]);
+        assert!(progress.get("proofs_total").is_none());
     }
 }
@@ -0,0 +1,831 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! A deterministic, in-process machine manager.
+//!
+//! Instead of a Cartesi machine, every session runs a toy machine whose
+//! only activity is to increment a counter word once per cycle. Hashes,
+//! step logs and proofs are computed over a real Merkle tree of the
+//! machine memory, so they can be checked by the contracts' algorithm.
+
+use super::dispatcher::{Archive, Reaction};
+use super::error::Result;
+use super::error::*;
+use super::ethereum_types::H256;
+use super::grpc::marshall::Marshaller;
+use super::{cartesi_machine, machine_manager};
+use emulator::machine_manager_grpc;
+use emulator_service::{
+    Access, AccessType, MerkleTreeProof, SessionRunResponse, SessionRunResponseOneOf,
+    SessionRunResult, SessionStepResponse, EMULATOR_METHOD_END, EMULATOR_METHOD_NEW,
+    EMULATOR_METHOD_PROOF, EMULATOR_METHOD_READ, EMULATOR_METHOD_REPLACE,
+    EMULATOR_METHOD_RUN, EMULATOR_METHOD_STEP, EMULATOR_METHOD_VERSION,
+    EMULATOR_METHOD_WRITE,
+};
+use emulator_service::{GetVersionResponse, SemanticVersion};
+use merkle::{concat_hash, pristine_hash, word_hash, LOG2_MACHINE_SIZE, LOG2_WORD_SIZE};
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// Address of the word incremented by the toy machine at every cycle
+pub const COUNTER_ADDRESS: u64 = 0x1000;
+/// Address of the word that tells toy machine templates apart
+pub const TEMPLATE_ADDRESS: u64 = 0x1008;
+
+/// Maximum number of emulator requests served for a single reaction
+const MAX_SERVED_REQUESTS: usize = 16;
+
+/// A machine that increments the word at `COUNTER_ADDRESS` once per cycle
+#[derive(Debug, Clone)]
+pub struct ToyMachine {
+    /// Memory contents at cycle 0, indexed by word address
+    pub memory: BTreeMap<u64, [u8; 8]>,
+    /// Cycle whose step writes a wrong value, making every later hash
+    /// differ from the ones of a correct machine
+    pub diverge_at: Option<u64>,
+}
+
+impl ToyMachine {
+    pub fn new(template: u64) -> Self {
+        let mut memory = BTreeMap::new();
+        memory.insert(TEMPLATE_ADDRESS, template.to_le_bytes());
+        memory.insert(COUNTER_ADDRESS, 0u64.to_le_bytes());
+        ToyMachine {
+            memory,
+            diverge_at: None,
+        }
+    }
+
+    /// Same machine, misbehaving from the step at `cycle` on
+    pub fn diverging_at(mut self, cycle: u64) -> Self {
+        self.diverge_at = Some(cycle);
+        self
+    }
+
+    /// Value of the counter word at `cycle`
+    pub fn counter(&self, cycle: u64) -> u64 {
+        match self.diverge_at {
+            Some(d) if cycle > d => cycle + 1,
+            _ => cycle,
+        }
+    }
+
+    /// Memory contents at `cycle`
+    pub fn memory_at(&self, cycle: u64) -> BTreeMap<u64, [u8; 8]> {
+        let mut memory = self.memory.clone();
+        memory.insert(COUNTER_ADDRESS, self.counter(cycle).to_le_bytes());
+        memory
+    }
+
+    /// Write `data` at `address`, starting from cycle 0
+    pub fn write(&mut self, address: u64, data: &[u8]) -> std::result::Result<(), String> {
+        if address % 8 != 0 {
+            return Err(format!("unaligned write address {}", address));
+        }
+        for (i, chunk) in data.chunks(8).enumerate() {
+            let word_address = address + 8 * i as u64;
+            let mut word = self.memory.get(&word_address).cloned().unwrap_or([0u8; 8]);
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.memory.insert(word_address, word);
+        }
+        Ok(())
+    }
+}
+
+/// Sparse view of a machine memory as a Merkle tree
+struct MemoryTree<'a> {
+    words: &'a BTreeMap<u64, [u8; 8]>,
+    pristine: &'a [H256],
+}
+
+impl<'a> MemoryTree<'a> {
+    fn node_hash(&self, address: u64, log2_size: u64) -> H256 {
+        let end = address as u128 + (1u128 << log2_size);
+        let occupied = match self.words.range(address..).next() {
+            Some((a, _)) => (*a as u128) < end,
+            None => false,
+        };
+        if !occupied {
+            return self.pristine[log2_size as usize];
+        }
+        if log2_size == LOG2_WORD_SIZE {
+            return word_hash(&self.words[&address]);
+        }
+        let half = 1u64 << (log2_size - 1);
+        concat_hash(
+            &self.node_hash(address, log2_size - 1),
+            &self.node_hash(address + half, log2_size - 1),
+        )
+    }
+
+    fn root_hash(&self) -> H256 {
+        self.node_hash(0, LOG2_MACHINE_SIZE)
+    }
+
+    fn proof(&self, address: u64, log2_size: u64) -> MerkleTreeProof {
+        let sibling_hashes = (log2_size..LOG2_MACHINE_SIZE)
+            .rev()
+            .map(|level| self.node_hash(((address >> level) ^ 1) << level, level))
+            .collect();
+        MerkleTreeProof {
+            address,
+            log2_target_size: log2_size,
+            log2_root_size: LOG2_MACHINE_SIZE,
+            target_hash: self.node_hash(address, log2_size),
+            sibling_hashes,
+            root_hash: self.root_hash(),
+        }
+    }
+}
+
+/// Machine manager that serves every session from a `ToyMachine`
+pub struct MockMachineManager {
+    template: ToyMachine,
+    sessions: Mutex<HashMap<String, ToyMachine>>,
+    pristine: Vec<H256>,
+    version: SemanticVersion,
+}
+
+impl MockMachineManager {
+    /// New sessions will run a copy of `template`
+    pub fn new(template: ToyMachine) -> Self {
+        let pristine = (0..=LOG2_MACHINE_SIZE)
+            .map(|log2_size| {
+                if log2_size < LOG2_WORD_SIZE {
+                    H256::zero()
+                } else {
+                    pristine_hash(log2_size)
+                }
+            })
+            .collect();
+        MockMachineManager {
+            template,
+            sessions: Mutex::new(HashMap::new()),
+            pristine,
+            version: SemanticVersion::new(0, 8, 0),
+        }
+    }
+
+    /// Report `version` as the machine manager version
+    pub fn with_version(mut self, version: SemanticVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    fn version_response(&self) -> emulator::versioning::GetVersionResponse {
+        GetVersionResponse {
+            version: self.version.clone(),
+        }
+        .into()
+    }
+
+    /// Register a session that is already running `machine`
+    pub fn with_session(self, session_id: &str, machine: ToyMachine) -> Self {
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session_id.to_string(), machine);
+        self
+    }
+
+    /// Whether the session `session_id` is open
+    pub fn has_session(&self, session_id: &str) -> bool {
+        self.sessions.lock().unwrap().contains_key(session_id)
+    }
+
+    fn session(&self, session_id: &str) -> std::result::Result<ToyMachine, String> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .cloned()
+            .ok_or_else(|| format!("session {} not found", session_id))
+    }
+
+    fn update_session<F>(&self, session_id: &str, f: F) -> std::result::Result<(), String>
+    where
+        F: FnOnce(&mut ToyMachine) -> std::result::Result<(), String>,
+    {
+        let mut sessions = self.sessions.lock().unwrap();
+        let machine = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| format!("session {} not found", session_id))?;
+        f(machine)
+    }
+
+    /// Root hash of `machine` at `cycle`
+    pub fn hash_at(&self, machine: &ToyMachine, cycle: u64) -> H256 {
+        let memory = machine.memory_at(cycle);
+        MemoryTree {
+            words: &memory,
+            pristine: &self.pristine,
+        }
+        .root_hash()
+    }
+
+    fn proof_at(
+        &self,
+        machine: &ToyMachine,
+        cycle: u64,
+        address: u64,
+        log2_size: u64,
+    ) -> std::result::Result<MerkleTreeProof, String> {
+        if log2_size < LOG2_WORD_SIZE
+            || log2_size > LOG2_MACHINE_SIZE
+            || (log2_size < LOG2_MACHINE_SIZE && address % (1u64 << log2_size) != 0)
+        {
+            return Err(format!(
+                "invalid proof target {} (log2 size {})",
+                address, log2_size
+            ));
+        }
+        let memory = machine.memory_at(cycle);
+        Ok(MemoryTree {
+            words: &memory,
+            pristine: &self.pristine,
+        }
+        .proof(address, log2_size))
+    }
+
+    fn new_session(
+        &self,
+        session_id: &str,
+        force: bool,
+    ) -> std::result::Result<H256, String> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.contains_key(session_id) && !force {
+            return Err(format!("session {} already exists", session_id));
+        }
+        sessions.insert(session_id.to_string(), self.template.clone());
+        Ok(self.hash_at(&self.template, 0))
+    }
+
+    fn run(&self, session_id: &str, times: &[u64]) -> std::result::Result<SessionRunResult, String> {
+        let machine = self.session(session_id)?;
+        Ok(SessionRunResult {
+            hashes: times.iter().map(|t| self.hash_at(&machine, *t)).collect(),
+        })
+    }
+
+    fn step(&self, session_id: &str, cycle: u64) -> std::result::Result<SessionStepResponse, String> {
+        let machine = self.session(session_id)?;
+        Ok(self.step_at(&machine, cycle))
+    }
+
+    /// Access log of the step of `machine` at `cycle`
+    pub fn step_at(&self, machine: &ToyMachine, cycle: u64) -> SessionStepResponse {
+        let memory = machine.memory_at(cycle);
+        let proof = MemoryTree {
+            words: &memory,
+            pristine: &self.pristine,
+        }
+        .proof(COUNTER_ADDRESS, LOG2_WORD_SIZE);
+        let before = machine.counter(cycle).to_le_bytes();
+        let after = machine.counter(cycle + 1).to_le_bytes();
+        SessionStepResponse {
+            log: vec![
+                Access {
+                    field_type: AccessType::Read,
+                    address: COUNTER_ADDRESS,
+                    value_read: before,
+                    value_written: before,
+                    proof: proof.clone(),
+                },
+                Access {
+                    field_type: AccessType::Write,
+                    address: COUNTER_ADDRESS,
+                    value_read: before,
+                    value_written: after,
+                    proof,
+                },
+            ],
+        }
+    }
+
+    fn read_memory(
+        &self,
+        session_id: &str,
+        cycle: u64,
+        address: u64,
+        length: u64,
+    ) -> std::result::Result<Vec<u8>, String> {
+        let machine = self.session(session_id)?;
+        let memory = machine.memory_at(cycle);
+        Ok((address..address.saturating_add(length))
+            .map(|a| {
+                let word = memory.get(&(a & !7)).cloned().unwrap_or([0u8; 8]);
+                word[(a & 7) as usize]
+            })
+            .collect())
+    }
+
+    fn replace_memory_range(
+        &self,
+        session_id: &str,
+        range: &cartesi_machine::MemoryRangeConfig,
+    ) -> std::result::Result<(), String> {
+        let mut data = if range.get_image_filename().is_empty() {
+            vec![]
+        } else {
+            std::fs::read(range.get_image_filename())
+                .map_err(|e| format!("could not read {}: {}", range.get_image_filename(), e))?
+        };
+        if data.len() as u64 > range.get_length() {
+            return Err(format!(
+                "image {} is larger than range length {}",
+                range.get_image_filename(),
+                range.get_length()
+            ));
+        }
+        data.resize(range.get_length() as usize, 0);
+        self.update_session(session_id, |machine| machine.write(range.get_start(), &data))
+    }
+
+    fn end_session(&self, session_id: &str, silent: bool) -> std::result::Result<(), String> {
+        let removed = self.sessions.lock().unwrap().remove(session_id);
+        if removed.is_none() && !silent {
+            return Err(format!("session {} not found", session_id));
+        }
+        Ok(())
+    }
+
+    /// Answer a marshalled emulator request, as the dispatcher would do
+    /// after sending it to a real machine manager
+    pub fn respond(&self, method: &str, request: Vec<u8>) -> Result<Vec<u8>> {
+        let bytes = bytes::Bytes::from(request);
+        match method {
+            m if m == EMULATOR_METHOD_NEW => {
+                let req: machine_manager::NewSessionRequest = unmarshall(bytes)?;
+                let hash = self.new_session(req.get_session_id(), req.get_force());
+                marshall(&to_hash(hash.map_err(Error::from)?))
+            }
+            m if m == EMULATOR_METHOD_RUN => {
+                let req: machine_manager::SessionRunRequest = unmarshall(bytes)?;
+                let result = self
+                    .run(req.get_session_id(), req.get_final_cycles())
+                    .map_err(Error::from)?;
+                let response: machine_manager::SessionRunResponse = SessionRunResponse {
+                    one_of: SessionRunResponseOneOf::RunResult(result),
+                }
+                .into();
+                marshall(&response)
+            }
+            m if m == EMULATOR_METHOD_STEP => {
+                let req: machine_manager::SessionStepRequest = unmarshall(bytes)?;
+                let response: machine_manager::SessionStepResponse = self
+                    .step(req.get_session_id(), req.get_initial_cycle())
+                    .map_err(Error::from)?
+                    .into();
+                marshall(&response)
+            }
+            m if m == EMULATOR_METHOD_READ => {
+                let req: machine_manager::SessionReadMemoryRequest = unmarshall(bytes)?;
+                let position = req.get_position();
+                let data = self
+                    .read_memory(
+                        req.get_session_id(),
+                        req.get_cycle(),
+                        position.get_address(),
+                        position.get_length(),
+                    )
+                    .map_err(Error::from)?;
+                let mut content = cartesi_machine::ReadMemoryResponse::new();
+                content.set_data(data);
+                let mut response = machine_manager::SessionReadMemoryResponse::new();
+                response.set_read_content(content);
+                marshall(&response)
+            }
+            m if m == EMULATOR_METHOD_WRITE => {
+                let req: machine_manager::SessionWriteMemoryRequest = unmarshall(bytes)?;
+                let position = req.get_position();
+                self.update_session(req.get_session_id(), |machine| {
+                    machine.write(position.get_address(), position.get_data())
+                })
+                .map_err(Error::from)?;
+                marshall(&cartesi_machine::Void::new())
+            }
+            m if m == EMULATOR_METHOD_REPLACE => {
+                let req: machine_manager::SessionReplaceMemoryRangeRequest = unmarshall(bytes)?;
+                self.replace_memory_range(req.get_session_id(), req.get_range())
+                    .map_err(Error::from)?;
+                marshall(&cartesi_machine::Void::new())
+            }
+            m if m == EMULATOR_METHOD_PROOF => {
+                let req: machine_manager::SessionGetProofRequest = unmarshall(bytes)?;
+                let target = req.get_target();
+                let machine = self.session(req.get_session_id()).map_err(Error::from)?;
+                let proof: cartesi_machine::MerkleTreeProof = self
+                    .proof_at(
+                        &machine,
+                        req.get_cycle(),
+                        target.get_address(),
+                        target.get_log2_size(),
+                    )
+                    .map_err(Error::from)?
+                    .into();
+                marshall(&proof)
+            }
+            m if m == EMULATOR_METHOD_VERSION => marshall(&self.version_response()),
+            m if m == EMULATOR_METHOD_END => {
+                let req: machine_manager::EndSessionRequest = unmarshall(bytes)?;
+                self.end_session(req.get_session_id(), req.get_silent())
+                    .map_err(Error::from)?;
+                marshall(&cartesi_machine::Void::new())
+            }
+            _ => Err(Error::from(format!("Unknown emulator method {}", method))),
+        }
+    }
+
+    /// Call `react` until it returns, answering every emulator request it
+    /// is missing and storing the answer in `archive`
+    pub fn react_with<F>(&self, archive: &mut Archive, react: F) -> Result<Reaction>
+    where
+        F: Fn(&Archive) -> Result<Reaction>,
+    {
+        for _ in 0..MAX_SERVED_REQUESTS {
+            let (key, method, request) = match react(archive) {
+                Err(e) => match e.kind() {
+                    ErrorKind::ResponseMissError(_, key, method, request) => {
+                        (key.clone(), method.clone(), request.clone())
+                    }
+                    ErrorKind::ServiceNeedsRetry(_, key, method, request, _, _, _, _) => {
+                        (key.clone(), method.clone(), request.clone())
+                    }
+                    _ => return Err(e),
+                },
+                reaction => return reaction,
+            };
+            let response = self.respond(&method, request)?;
+            archive.insert_response(key, Ok(response));
+        }
+        Err(Error::from(format!(
+            "Reaction still missing emulator responses after {} requests",
+            MAX_SERVED_REQUESTS
+        )))
+    }
+}
+
+fn to_hash(hash: H256) -> cartesi_machine::Hash {
+    let mut h = cartesi_machine::Hash::new();
+    h.data = hash.as_bytes().into();
+    h
+}
+
+fn to_grpc_error(message: String) -> grpc::Error {
+    grpc::Error::GrpcMessage(grpc::GrpcMessageError {
+        grpc_status: grpc::GrpcStatus::Internal as i32,
+        grpc_message: message,
+    })
+}
+
+fn marshall<M: protobuf::Message>(message: &M) -> Result<Vec<u8>> {
+    let marshaller: Box<dyn Marshaller<M> + Sync + Send> =
+        Box::new(grpc::protobuf::MarshallerProtobuf);
+    marshaller
+        .write(message)
+        .map_err(|e| Error::from(format!("Could not marshall response: {:?}", e)))
+}
+
+fn unmarshall<M: protobuf::Message>(bytes: bytes::Bytes) -> Result<M> {
+    let marshaller: Box<dyn Marshaller<M> + Sync + Send> =
+        Box::new(grpc::protobuf::MarshallerProtobuf);
+    marshaller
+        .read(bytes)
+        .map_err(|e| Error::from(format!("Could not unmarshall request: {:?}", e)))
+}
+
+fn single<T: Send + 'static>(result: std::result::Result<T, String>) -> grpc::SingleResponse<T> {
+    match result {
+        Ok(t) => grpc::SingleResponse::completed(t),
+        Err(e) => grpc::SingleResponse::err(to_grpc_error(e)),
+    }
+}
+
+impl machine_manager_grpc::MachineManager for MockMachineManager {
+    fn get_version(
+        &self,
+        _o: grpc::RequestOptions,
+        _p: cartesi_machine::Void,
+    ) -> grpc::SingleResponse<emulator::versioning::GetVersionResponse> {
+        grpc::SingleResponse::completed(self.version_response())
+    }
+
+    fn new_session(
+        &self,
+        _o: grpc::RequestOptions,
+        p: machine_manager::NewSessionRequest,
+    ) -> grpc::SingleResponse<cartesi_machine::Hash> {
+        single(self.new_session(p.get_session_id(), p.get_force()).map(to_hash))
+    }
+
+    fn session_run(
+        &self,
+        _o: grpc::RequestOptions,
+        p: machine_manager::SessionRunRequest,
+    ) -> grpc::SingleResponse<machine_manager::SessionRunResponse> {
+        single(
+            self.run(p.get_session_id(), p.get_final_cycles())
+                .map(|result| {
+                    SessionRunResponse {
+                        one_of: SessionRunResponseOneOf::RunResult(result),
+                    }
+                    .into()
+                }),
+        )
+    }
+
+    fn session_step(
+        &self,
+        _o: grpc::RequestOptions,
+        p: machine_manager::SessionStepRequest,
+    ) -> grpc::SingleResponse<machine_manager::SessionStepResponse> {
+        single(
+            self.step(p.get_session_id(), p.get_initial_cycle())
+                .map(|response| response.into()),
+        )
+    }
+
+    fn session_store(
+        &self,
+        _o: grpc::RequestOptions,
+        p: machine_manager::SessionStoreRequest,
+    ) -> grpc::SingleResponse<cartesi_machine::Void> {
+        single(
+            self.session(p.get_session_id())
+                .map(|_| cartesi_machine::Void::new()),
+        )
+    }
+
+    fn session_read_memory(
+        &self,
+        _o: grpc::RequestOptions,
+        p: machine_manager::SessionReadMemoryRequest,
+    ) -> grpc::SingleResponse<machine_manager::SessionReadMemoryResponse> {
+        let position = p.get_position();
+        single(
+            self.read_memory(
+                p.get_session_id(),
+                p.get_cycle(),
+                position.get_address(),
+                position.get_length(),
+            )
+            .map(|data| {
+                let mut content = cartesi_machine::ReadMemoryResponse::new();
+                content.set_data(data);
+                let mut response = machine_manager::SessionReadMemoryResponse::new();
+                response.set_read_content(content);
+                response
+            }),
+        )
+    }
+
+    fn session_write_memory(
+        &self,
+        _o: grpc::RequestOptions,
+        p: machine_manager::SessionWriteMemoryRequest,
+    ) -> grpc::SingleResponse<cartesi_machine::Void> {
+        let position = p.get_position();
+        single(
+            self.update_session(p.get_session_id(), |machine| {
+                machine.write(position.get_address(), position.get_data())
+            })
+            .map(|_| cartesi_machine::Void::new()),
+        )
+    }
+
+    fn session_replace_memory_range(
+        &self,
+        _o: grpc::RequestOptions,
+        p: machine_manager::SessionReplaceMemoryRangeRequest,
+    ) -> grpc::SingleResponse<cartesi_machine::Void> {
+        single(
+            self.replace_memory_range(p.get_session_id(), p.get_range())
+                .map(|_| cartesi_machine::Void::new()),
+        )
+    }
+
+    fn session_get_proof(
+        &self,
+        _o: grpc::RequestOptions,
+        p: machine_manager::SessionGetProofRequest,
+    ) -> grpc::SingleResponse<cartesi_machine::MerkleTreeProof> {
+        let target = p.get_target();
+        single(
+            self.session(p.get_session_id())
+                .and_then(|machine| {
+                    self.proof_at(
+                        &machine,
+                        p.get_cycle(),
+                        target.get_address(),
+                        target.get_log2_size(),
+                    )
+                })
+                .map(|proof| proof.into()),
+        )
+    }
+
+    fn end_session(
+        &self,
+        _o: grpc::RequestOptions,
+        p: machine_manager::EndSessionRequest,
+    ) -> grpc::SingleResponse<cartesi_machine::Void> {
+        single(
+            self.end_session(p.get_session_id(), p.get_silent())
+                .map(|_| cartesi_machine::Void::new()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dispatcher::DApp;
+    use emulator::{ClientError, MachineManagerClient};
+    use emulator_service::{
+        EndSessionRequest, NewSessionRequest, NewSessionResponse, SessionReadMemoryRequest,
+        SessionReadMemoryResponse, SessionRunRequest, SessionStepRequest,
+    };
+    use partition::tests::build_partition_state_json_data;
+    use replay::replay_access_log;
+    use tests::{build_concern, build_state, encode, CLAIMERADDR, MACHINEID};
+    use transaction::TransactionRequest;
+    use {get_run_result, ArchiveKey, Partition};
+
+    use std::convert::TryFrom;
+    use std::time::Duration;
+
+    fn build_mock() -> MockMachineManager {
+        MockMachineManager::new(ToyMachine::new(1)).with_session(MACHINEID, ToyMachine::new(1))
+    }
+
+    #[test]
+    fn it_should_chain_steps_and_run_hashes() {
+        let mock = build_mock();
+        let machine = ToyMachine::new(1);
+        for cycle in 0..4 {
+            let before = mock.hash_at(&machine, cycle);
+            let after = mock.hash_at(&machine, cycle + 1);
+            let step = mock.step(MACHINEID, cycle).unwrap();
+            assert_eq!(step.log[0].proof.root_hash, before);
+            assert_eq!(step.log[0].proof.verify_word(&step.log[0].value_read), Ok(()));
+            assert_eq!(replay_access_log(before, &step.log), Ok(after));
+        }
+        let diverging = ToyMachine::new(1).diverging_at(2);
+        assert_eq!(mock.hash_at(&diverging, 2), mock.hash_at(&machine, 2));
+        assert_ne!(mock.hash_at(&diverging, 3), mock.hash_at(&machine, 3));
+        assert_ne!(mock.hash_at(&ToyMachine::new(2), 0), mock.hash_at(&machine, 0));
+    }
+
+    #[test]
+    fn it_should_answer_marshalled_requests() {
+        let mock = build_mock();
+        let mut archive = Archive::new().unwrap();
+        let times = vec![0, 5, 10];
+        let request = SessionRunRequest {
+            session_id: MACHINEID.to_string(),
+            times: times.clone(),
+        };
+        let key = ArchiveKey::Run {
+            id: MACHINEID.to_string(),
+            times: times.clone(),
+        };
+        let response = mock.respond(EMULATOR_METHOD_RUN, request.clone().into()).unwrap();
+        archive.insert_response(key.to_string(), Ok(response));
+        let result =
+            get_run_result(&archive, "Test".to_string(), &key, request.into(), None).unwrap();
+        assert_eq!(result.hashes[1], mock.hash_at(&ToyMachine::new(1), 5));
+
+        let request = SessionStepRequest {
+            session_id: MACHINEID.to_string(),
+            time: 5,
+        };
+        let response = SessionStepResponse::try_from(
+            mock.respond(EMULATOR_METHOD_STEP, request.into()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(response.log.len(), 2);
+        assert_eq!(response.log[1].value_written, 6u64.to_le_bytes());
+
+        assert!(mock.respond("unknown", vec![]).is_err());
+    }
+
+    #[test]
+    fn it_should_serve_typed_clients() {
+        let machine = ToyMachine::new(1);
+        let expected = MockMachineManager::new(machine.clone());
+        let client = MachineManagerClient::with_stub(
+            MockMachineManager::new(machine.clone()),
+            Duration::from_secs(10),
+        );
+        let version: GetVersionResponse = client.get_version().unwrap();
+        assert_eq!(version.version, SemanticVersion::new(0, 8, 0));
+
+        let created: NewSessionResponse = client
+            .new_session(NewSessionRequest {
+                session_id: MACHINEID.to_string(),
+                machine: cartesi_machine::MachineRequest::new(),
+                force: false,
+            })
+            .unwrap();
+        assert_eq!(created.hash, expected.hash_at(&machine, 0));
+
+        let response: SessionRunResponse = client
+            .session_run(SessionRunRequest {
+                session_id: MACHINEID.to_string(),
+                times: vec![0, 7],
+            })
+            .unwrap();
+        match response.one_of {
+            SessionRunResponseOneOf::RunResult(result) => {
+                assert_eq!(result.hashes[1], expected.hash_at(&machine, 7))
+            }
+            _ => panic!("expected a run result"),
+        }
+
+        let step: SessionStepResponse = client
+            .session_step(SessionStepRequest {
+                session_id: MACHINEID.to_string(),
+                time: 7,
+            })
+            .unwrap();
+        assert_eq!(step.log[1].value_written, 8u64.to_le_bytes());
+
+        let mut position = cartesi_machine::ReadMemoryRequest::new();
+        position.set_address(COUNTER_ADDRESS);
+        position.set_length(8);
+        let read: SessionReadMemoryResponse = client
+            .session_read_memory(SessionReadMemoryRequest {
+                session_id: MACHINEID.to_string(),
+                time: 7,
+                position,
+            })
+            .unwrap();
+        assert_eq!(read.read_content.data, 7u64.to_le_bytes());
+
+        let end = EndSessionRequest {
+            session_id: MACHINEID.to_string(),
+            silent: false,
+        };
+        client.end_session(end.clone()).unwrap();
+        match client.end_session(end).unwrap_err() {
+            ClientError::Status {
+                method, message, ..
+            } => {
+                assert_eq!(method, "EndSession");
+                assert!(message.contains("not found"), "{}", message);
+            }
+            e => panic!("expected an error status, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn it_should_serve_dapp_requests() {
+        let mock = build_mock();
+        let mut archive = Archive::new().unwrap();
+        let concern = build_concern(CLAIMERADDR);
+        let state_instance = build_state(
+            concern,
+            Option::from(build_partition_state_json_data(
+                encode("WaitingHashes").as_str(),
+                Option::from("0x1fffffffffffff"),
+                None,
+                Option::from(vec!["0x0", "0x4", "0x8"]),
+                Option::from("0x3"),
+            )),
+        );
+
+        let reaction = mock
+            .react_with(&mut archive, |archive| {
+                Partition::react(&state_instance, archive, &None, &String::from(MACHINEID))
+            })
+            .unwrap();
+        if let Reaction::Transaction(TransactionRequest { function, .. }) = reaction {
+            assert_eq!(function, "replyQuery");
+        } else {
+            panic!("Only transaction");
+        }
+    }
+}
@@ -0,0 +1,221 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+
+//! Output ranges of the machine under dispute. In output mode, the claim
+//! covers not only the final hash but also the contents of a memory range
+//! at the final time, which are read from the emulator together with a
+//! proof that they are part of the final state.
+
+use super::dispatcher::Archive;
+use super::error::*;
+use super::ethereum_types::H256;
+use super::{cartesi_machine, get_emulator_response, ArchiveKey};
+use emulator_service::{
+    SessionGetProofRequest, SessionGetProofResponse, SessionReadMemoryRequest,
+    SessionReadMemoryResponse, EMULATOR_METHOD_PROOF, EMULATOR_METHOD_READ,
+};
+use merkle::LOG2_WORD_SIZE;
+
+/// Largest output range, as it is read in a single request
+pub const MAX_LOG2_OUTPUT_SIZE: u64 = 20;
+
+/// Aligned memory range holding the output of the machine
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputRange {
+    pub address: u64,
+    pub log2_size: u64,
+}
+
+impl OutputRange {
+    /// Range of `1 << log2_size` bytes at `address`, which must be
+    /// aligned to its size
+    pub fn new(address: u64, log2_size: u64) -> Result<Self> {
+        if log2_size < LOG2_WORD_SIZE || log2_size > MAX_LOG2_OUTPUT_SIZE {
+            return Err(Error::from(format!(
+                "Output log2 size {} is not between {} and {}",
+                log2_size, LOG2_WORD_SIZE, MAX_LOG2_OUTPUT_SIZE
+            )));
+        }
+        if address % (1u64 << log2_size) != 0 {
+            return Err(Error::from(format!(
+                "Output address {:#x} is not aligned to log2 size {}",
+                address, log2_size
+            )));
+        }
+        Ok(OutputRange { address, log2_size })
+    }
+
+    pub fn length(&self) -> u64 {
+        1 << self.log2_size
+    }
+}
+
+/// Contents of an output range at `time`, proven to be part of the
+/// machine state whose hash is `root_hash`
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Output {
+    pub address: u64,
+    pub log2_size: u64,
+    pub time: u64,
+    #[serde(serialize_with = "serialize_hex")]
+    pub data: Vec<u8>,
+    pub root_hash: H256,
+}
+
+fn serialize_hex<S: serde::Serializer>(
+    data: &[u8],
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("0x{}", hex::encode(data)))
+}
+
+/// Read `range` from session `id` at `time`, and check its proof against
+/// `root_hash`, the machine hash at that time
+pub fn get_output(
+    archive: &Archive,
+    contract: &str,
+    id: &str,
+    time: u64,
+    range: OutputRange,
+    root_hash: H256,
+) -> Result<Output> {
+    let mut position = cartesi_machine::ReadMemoryRequest::new();
+    position.set_address(range.address);
+    position.set_length(range.length());
+    let request = SessionReadMemoryRequest {
+        session_id: id.to_string(),
+        time,
+        position,
+    };
+    let archive_key = ArchiveKey::Read {
+        id: id.to_string(),
+        time,
+        address: range.address,
+        length: range.length(),
+    }
+    .to_string();
+    let read: SessionReadMemoryResponse = get_emulator_response(
+        archive,
+        contract.to_string(),
+        archive_key,
+        EMULATOR_METHOD_READ,
+        request.into(),
+    )?;
+
+    let mut target = cartesi_machine::GetProofRequest::new();
+    target.set_address(range.address);
+    target.set_log2_size(range.log2_size);
+    let request = SessionGetProofRequest {
+        session_id: id.to_string(),
+        time,
+        target,
+    };
+    let archive_key = ArchiveKey::Proof {
+        id: id.to_string(),
+        time,
+        address: range.address,
+        log2_size: range.log2_size,
+    }
+    .to_string();
+    let proof: SessionGetProofResponse = get_emulator_response(
+        archive,
+        contract.to_string(),
+        archive_key,
+        EMULATOR_METHOD_PROOF,
+        request.into(),
+    )?;
+    let proof = proof.proof;
+
+    let data = read.read_content.data;
+    if proof.address != range.address || proof.log2_target_size != range.log2_size {
+        return Err(Error::from(format!(
+            "Proof of machine {} targets {:#x} (log2 size {}) instead of the output range",
+            id, proof.address, proof.log2_target_size
+        )));
+    }
+    proof
+        .verify_range(&data)
+        .chain_err(|| format!("Invalid output proof of machine {} at {}", id, time))?;
+    if proof.root_hash != root_hash {
+        return Err(Error::from(format!(
+            "Output of machine {} at {} is proven against {:?}, not {:?}",
+            id, time, proof.root_hash, root_hash
+        )));
+    }
+
+    Ok(Output {
+        address: range.address,
+        log2_size: range.log2_size,
+        time,
+        data,
+        root_hash,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dispatcher::Reaction;
+    use mock_manager::{MockMachineManager, ToyMachine, COUNTER_ADDRESS};
+    use tests::MACHINEID;
+
+    fn output_of(mock: &MockMachineManager, root_hash: H256) -> Result<Output> {
+        let mut archive = Archive::new().unwrap();
+        let range = OutputRange::new(COUNTER_ADDRESS, 4).unwrap();
+        let id = String::from(MACHINEID);
+        // archive the responses, then read the output from the archive
+        mock.react_with(&mut archive, |archive| {
+            get_output(archive, "Compute", &id, 7, range, root_hash).map(|_| Reaction::Idle)
+        })?;
+        get_output(&archive, "Compute", &id, 7, range, root_hash)
+    }
+
+    #[test]
+    fn it_should_prove_output_against_machine_hash() {
+        let machine = ToyMachine::new(3);
+        let mock = MockMachineManager::new(machine.clone()).with_session(MACHINEID, machine.clone());
+        let root_hash = mock.hash_at(&machine, 7);
+
+        let output = output_of(&mock, root_hash).unwrap();
+        let mut data = machine.counter(7).to_le_bytes().to_vec();
+        data.extend_from_slice(&3u64.to_le_bytes());
+        assert_eq!(output.data, data);
+        assert_eq!(output.root_hash, root_hash);
+        let json = serde_json::to_value(&output).unwrap();
+        assert_eq!(json["data"], format!("0x{}", hex::encode(&data)));
+
+        let error = output_of(&mock, mock.hash_at(&machine, 8)).unwrap_err();
+        assert!(error.to_string().contains("is proven against"));
+    }
+
+    #[test]
+    fn it_should_reject_invalid_output_ranges() {
+        assert!(OutputRange::new(COUNTER_ADDRESS, 4).is_ok());
+        assert!(OutputRange::new(COUNTER_ADDRESS, 2).is_err());
+        assert!(OutputRange::new(COUNTER_ADDRESS, MAX_LOG2_OUTPUT_SIZE + 1).is_err());
+        assert!(OutputRange::new(COUNTER_ADDRESS + 8, 4).is_err());
+    }
+}
@@ -23,7 +23,7 @@
 // be used independently under the Apache v2 license. After this component is
 // rewritten, the entire component will be released under the Apache v2 license.
 
-use super::build_session_run_key;
+use super::ArchiveKey;
 use super::dispatcher::{AddressField, BoolArray, Bytes32Array, String32Field, U256Array};
 use super::dispatcher::{Archive, DApp, Reaction};
 use super::error::Result;
@@ -32,67 +32,171 @@ use super::ethabi::Token;
 use super::ethereum_types::{Address, H256, U256};
 use super::transaction::TransactionRequest;
 use super::win_by_deadline_or_idle;
+use compute::DisputeParams;
 use super::{get_run_result, Role};
-use emulator_service::SessionRunRequest;
+use checked::{to_u64s, CheckedConversion};
+use emulator_service::{SessionRunRequest, EMULATOR_METHOD_RUN, EMULATOR_SERVICE_NAME};
+use pretty::{partition_next, role_of, Pretty, Progress};
+use query_strategy::{next_query_arrays, Bisection, QueryInterval, QueryStrategy};
+use states::PartitionState;
+use urgency::Urgency;
+
+use std::convert::TryFrom;
+use std::fmt;
 
 pub struct Partition();
 
-// !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
-// these two structs and the From trait below shuld be
-// obtained from a simple derive
-// !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
-#[derive(Serialize, Deserialize)]
-pub struct PartitionCtxParsed(
-    pub AddressField,  // challenger
-    pub AddressField,  // claimer
-    pub U256Array,     // queryArray
-    pub BoolArray,     // submittedArray
-    pub Bytes32Array,  // hashArray
-    pub String32Field, // currentState
-    pub U256Array,     // uint values: finalTime
-                       // querySize
-                       // deadline
-                       // divergenceTime
-);
-
-#[derive(Serialize, Debug)]
+/// The challenger agrees with every hash the claimer submitted, even
+/// though it disputed the final one: one of the parties has a bug
+#[derive(Debug, Clone)]
+pub struct NoDisagreement {
+    pub query_array: Vec<U256>,
+    pub hash_array: Vec<H256>,
+}
+
+impl fmt::Display for NoDisagreement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "local hashes agree with the claimed hashes {:?} at times {:?}",
+            self.hash_array, self.query_array
+        )
+    }
+}
+
+impl std::error::Error for NoDisagreement {}
+
+#[derive(Serialize, Debug, ContractCtx)]
+#[ctx(parsed = "PartitionCtxParsed")]
 pub struct PartitionCtx {
+    #[ctx(field = "AddressField")]
     pub challenger: Address,
+    #[ctx(field = "AddressField")]
     pub claimer: Address,
+    #[ctx(field = "U256Array")]
     pub query_array: Vec<U256>,
+    #[ctx(field = "BoolArray")]
     pub submitted_array: Vec<bool>,
+    #[ctx(field = "Bytes32Array")]
     pub hash_array: Vec<H256>,
-    pub current_state: String,
+    #[ctx(field = "String32Field", parse)]
+    pub current_state: PartitionState,
+    #[ctx(packed = "U256Array", index = 0)]
     pub final_time: U256,
+    #[ctx(packed = "U256Array", index = 1)]
     pub query_size: U256,
+    #[ctx(packed = "U256Array", index = 2)]
     pub deadline: U256,
+    #[ctx(packed = "U256Array", index = 3)]
     pub divergence_time: U256,
 }
 
-impl From<PartitionCtxParsed> for PartitionCtx {
-    fn from(parsed: PartitionCtxParsed) -> PartitionCtx {
-        PartitionCtx {
-            challenger: parsed.0.value,
-            claimer: parsed.1.value,
-            query_array: parsed.2.value,
-            submitted_array: parsed.3.value,
-            hash_array: parsed.4.value,
-            current_state: parsed.5.value,
-            final_time: parsed.6.value[0],
-            query_size: parsed.6.value[1],
-            deadline: parsed.6.value[2],
-            divergence_time: parsed.6.value[3],
+/// Start the runs in `runs` while the other party moves, so that the
+/// emulator is ahead when the next round comes. The first run never
+/// requested is returned as a missing response for the dispatcher to
+/// send, while runs already in progress are left to finish. Prefetching
+/// is only a head start, so any other failure is logged and the party
+/// keeps waiting
+fn prefetch<P: DisputeParams>(archive: &Archive, params: &P, runs: Vec<Vec<u64>>) -> Result<()> {
+    let id = params.machine_id();
+    if let Err(e) = params.prepare_session(archive) {
+        return skip_prefetch(e, &format!("runs of {}", id));
+    }
+    for times in runs {
+        let request = SessionRunRequest {
+            session_id: id.to_string(),
+            times: times.clone(),
+        };
+        let archive_key = ArchiveKey::Run {
+            id: id.to_string(),
+            times,
+        };
+        match get_run_result(
+            archive,
+            "Partition".to_string(),
+            &archive_key,
+            request.into(),
+            params.run_progress(),
+        ) {
+            Ok(_) => {}
+            Err(e) => match e.kind() {
+                ErrorKind::ServiceNeedsRetry(..) => {}
+                _ => return skip_prefetch(e, &format!("run {}", archive_key)),
+            },
         }
     }
+    Ok(())
 }
 
-impl DApp<String> for Partition {
-    fn react(
+/// Stop prefetching `what` on `e`, only passing on missing responses
+fn skip_prefetch(e: Error, what: &str) -> Result<()> {
+    match e.kind() {
+        ErrorKind::ResponseMissError(..) => Err(e),
+        ErrorKind::ServiceNeedsRetry(..) => Ok(()),
+        _ => {
+            warn!("Failed to prefetch {}: {}", what, e);
+            Ok(())
+        }
+    }
+}
+
+/// Hashes of the machine at every time of the query array. An emulator
+/// answering fewer hashes than times is asked again, as for any invalid
+/// response
+fn queried_hashes<P: DisputeParams>(
+    archive: &Archive,
+    params: &P,
+    ctx: &PartitionCtx,
+) -> Result<Vec<H256>> {
+    let id = params.machine_id();
+    let sample_points = to_u64s(&ctx.query_array, "Partition query time")?;
+    let request: Vec<u8> = SessionRunRequest {
+        session_id: id.clone(),
+        times: sample_points.clone(),
+    }
+    .into();
+    let archive_key = ArchiveKey::Run {
+        id: id.clone(),
+        times: sample_points.clone(),
+    };
+    // have we sampled the times?
+    let processed_result = get_run_result(
+        archive,
+        "Partition".to_string(),
+        &archive_key,
+        request.clone(),
+        params.run_progress(),
+    )?;
+    if processed_result.hashes.len() < sample_points.len() {
+        return Err(Error::from(ErrorKind::ServiceNeedsRetry(
+            EMULATOR_SERVICE_NAME.to_string(),
+            archive_key.to_string(),
+            EMULATOR_METHOD_RUN.to_string(),
+            request,
+            "Partition".to_string(),
+            1,
+            0,
+            format!(
+                "{} hashes for {} query times",
+                processed_result.hashes.len(),
+                sample_points.len()
+            ),
+        )));
+    }
+    Ok(processed_result.hashes)
+}
+
+impl Partition {
+    /// Same as `react`, with the challenger choosing which interval to
+    /// query next according to `strategy`
+    pub fn react_with_strategy<P: DisputeParams, S: QueryStrategy + ?Sized>(
         instance: &state::Instance,
         archive: &Archive,
         _post_payload: &Option<String>,
-        machine_id: &String,
+        params: &P,
+        strategy: &S,
     ) -> Result<Reaction> {
+        let machine_id = params.machine_id();
         let parsed: PartitionCtxParsed =
             serde_json::from_str(&instance.json_data).chain_err(|| {
                 format!(
@@ -100,13 +204,15 @@ impl DApp<String> for Partition {
                     &instance.json_data
                 )
             })?;
-        let ctx: PartitionCtx = parsed.into();
+        let ctx = PartitionCtx::try_from(parsed)?;
         trace!("Context for parition {:?}", ctx);
 
         // should not happen as it indicates an innactive instance,
         // but it is possible that the blockchain state changed between queries
-        match ctx.current_state.as_ref() {
-            "ChallengerWon" | "ClaimerWon" | "DivergenceFound" => {
+        match ctx.current_state {
+            PartitionState::ChallengerWon
+            | PartitionState::ClaimerWon
+            | PartitionState::DivergenceFound => {
                 return Ok(Reaction::Idle);
             }
             _ => {}
@@ -124,50 +230,64 @@ impl DApp<String> for Partition {
         };
         trace!("Role played (index {}) is: {:?}", instance.index, role);
 
+        // the closer the deadline, the more aggressive our transactions
+        let deadline = ctx.deadline.to_u64("Partition deadline")?;
+        let query_size = ctx.query_size.to_usize("Partition query size")?;
+        // the query size bounds what predicting the next query arrays
+        // allocates, so it must not exceed the query times we are given
+        if query_size > ctx.query_array.len() {
+            return Err(Error::from(ErrorKind::InvalidContractState(format!(
+                "Partition query size {} exceeds the {} query times",
+                query_size,
+                ctx.query_array.len()
+            ))));
+        }
+        let urgency = Urgency::of(deadline, params.clock())?;
+        let policy = params.transaction_policy();
+        if urgency != Urgency::Normal {
+            debug!(
+                "{:?} deadline for Partition (index {}): {}",
+                urgency, instance.index, ctx.deadline
+            );
+        }
+
         match role {
-            Role::Claimer => match ctx.current_state.as_ref() {
-                "WaitingQuery" => {
-                    return win_by_deadline_or_idle(
+            Role::Claimer => match ctx.current_state {
+                PartitionState::WaitingQuery => {
+                    let reaction = win_by_deadline_or_idle(
                         &instance.concern,
                         instance.index,
-                        ctx.deadline.as_u64(),
-                    );
+                        deadline,
+                        params,
+                    )?;
+                    if let Reaction::Idle = reaction {
+                        if params.prefetch_runs() {
+                            // whichever interval gets queried, we reply
+                            // with the hashes of its slice
+                            let query_array = to_u64s(&ctx.query_array, "Partition query time")?;
+                            prefetch(archive, params, next_query_arrays(&query_array, query_size))?;
+                        }
+                    }
+                    return Ok(reaction);
                 }
-                "WaitingHashes" => {
+                PartitionState::WaitingHashes => {
+                    params.prepare_session(archive)?;
                     // machine id
                     let id = machine_id.clone();
 
                     trace!("Calculating queried hashes of machine {}", id);
-                    let sample_points: Vec<u64> = ctx
-                        .query_array
-                        .clone()
-                        .into_iter()
-                        .map(|u| u.as_u64())
-                        .collect();
-                    let request = SessionRunRequest {
-                        session_id: id.clone(),
-                        times: sample_points.clone(),
-                    };
-                    let archive_key = build_session_run_key(id.clone(), sample_points.clone());
-                    // have we sampled the times?
-                    let processed_result = get_run_result(
-                        archive,
-                        "Partition".to_string(),
-                        archive_key,
-                        request.into(),
-                    )?;
+                    let local_hashes = queried_hashes(archive, params, &ctx)?;
 
                     let mut hashes = Vec::new();
 
-                    for i in 0..ctx.query_size.as_usize() {
+                    for i in 0..query_size {
                         // get the i'th time in query array
                         let _time = &ctx.query_array.get(i).ok_or(Error::from(
                             ErrorKind::InvalidContractState(String::from(
                                 "could not find element in query array",
                             )),
                         ))?;
-                        let hash = processed_result.hashes.get(i).unwrap();
-                        hashes.push(hash);
+                        hashes.push(&local_hashes[i]);
                     }
                     // submit the required hashes
                     info!("Replying Query for Partition (index: {})", instance.index);
@@ -199,45 +319,36 @@ impl DApp<String> for Partition {
                                     .collect(),
                             ),
                         ],
-                        gas: None,
-                        strategy: transaction::Strategy::Simplest,
+                        gas: policy.gas(urgency),
+                        strategy: policy.strategy(urgency),
                     };
                     return Ok(Reaction::Transaction(request));
                 }
-                _ => {
-                    return Err(Error::from(ErrorKind::InvalidContractState(format!(
-                        "Unknown current state {}",
-                        ctx.current_state
-                    ))));
+                PartitionState::ChallengerWon
+                | PartitionState::ClaimerWon
+                | PartitionState::DivergenceFound => {
+                    return Ok(Reaction::Idle);
                 }
             },
-            Role::Challenger => match ctx.current_state.as_ref() {
-                "WaitingQuery" => {
+            Role::Challenger => match ctx.current_state {
+                PartitionState::WaitingQuery => {
+                    params.prepare_session(archive)?;
                     // machine id
                     let id = machine_id.clone();
 
                     trace!("Calculating posted hashes of machine {}", id);
-                    let sample_points: Vec<u64> = ctx
-                        .query_array
-                        .clone()
-                        .into_iter()
-                        .map(|u| u.as_u64())
-                        .collect();
-                    let request = SessionRunRequest {
-                        session_id: id.clone(),
-                        times: sample_points.clone(),
-                    };
-                    let archive_key = build_session_run_key(id.clone(), sample_points.clone());
-
-                    // have we sampled the times?
-                    let processed_result = get_run_result(
-                        archive,
-                        "Partition".to_string(),
-                        archive_key,
-                        request.into(),
-                    )?;
+                    let local_hashes = queried_hashes(archive, params, &ctx)?;
 
-                    for i in 0..(ctx.query_size.as_usize() - 1) {
+                    // intervals whose left end we agree with and whose right
+                    // end we do not, the first left end is agreed upon
+                    let mut candidates = Vec::new();
+                    let mut left_agreed = true;
+                    let intervals = query_size.checked_sub(1).ok_or(Error::from(
+                        ErrorKind::InvalidContractState(String::from(
+                            "Partition query size is 0",
+                        )),
+                    ))?;
+                    for i in 0..intervals {
                         // get the i'th time in query array
                         let time = ctx.query_array.get(i).ok_or(Error::from(
                             ErrorKind::InvalidContractState(format!(
@@ -260,83 +371,145 @@ impl DApp<String> for Partition {
                             )),
                         ))?;
 
-                        // have we sampled that specific time?
-                        let hash = processed_result.hashes.get(i + 1).unwrap();
-
-                        if hash != *claimed_hash {
-                            // do we need another partition?
-                            if next_time.as_u64() - time.as_u64() > 1 {
-                                // submit the relevant query
-                                info!("Making Query for Partition (index: {})", instance.index);
-                                let request = TransactionRequest {
-                                    contract_name: None, // Name not needed, is concern
-                                    concern: instance.concern.clone(),
-                                    value: U256::from(0),
-                                    function: "makeQuery".into(),
-                                    // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
-                                    // improve these types by letting the
-                                    // dapp submit ethereum_types and convert
-                                    // them inside the transaction manager
-                                    // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
-                                    data: vec![
-                                        Token::Uint(instance.index),
-                                        Token::Uint(U256::from(i)),
-                                        Token::Uint(*time),
-                                        Token::Uint(*next_time),
-                                    ],
-                                    gas: None,
-                                    strategy: transaction::Strategy::Simplest,
-                                };
-                                return Ok(Reaction::Transaction(request));
-                            } else {
-                                // submit divergence time
-                                info!(
-                                    "Divergence found for Partition (index: {}, time: {})",
-                                    instance.index, *time
-                                );
-                                let request = TransactionRequest {
-                                    contract_name: None, // Name not needed, is concern
-                                    concern: instance.concern.clone(),
-                                    value: U256::from(0),
-                                    function: "presentDivergence".into(),
-                                    // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
-                                    // improve these types by letting the
-                                    // dapp submit ethereum_types and convert
-                                    // them inside the transaction manager
-                                    // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
-                                    data: vec![Token::Uint(instance.index), Token::Uint(*time)],
-                                    gas: None,
-                                    strategy: transaction::Strategy::Simplest,
-                                };
-                                return Ok(Reaction::Transaction(request));
-                            }
+                        let hash = &local_hashes[i + 1];
+
+                        let right_agreed = hash == *claimed_hash;
+                        if left_agreed && !right_agreed {
+                            candidates.push(QueryInterval {
+                                piece: i,
+                                left: time.to_u64("Partition query time")?,
+                                right: next_time.to_u64("Partition query time")?,
+                            });
                         }
+                        left_agreed = right_agreed;
+                    }
+
+                    if candidates.is_empty() {
+                        // no disagreement found. important bug!!!!
+                        error!("bug found: no disagreement in dispute {:?}!!!", instance);
+                        return Err(NoDisagreement {
+                            query_array: ctx.query_array.clone(),
+                            hash_array: ctx.hash_array.clone(),
+                        })
+                        .chain_err(|| {
+                            format!(
+                                "Bug found: no disagreement in dispute (Partition index {})",
+                                instance.index
+                            )
+                        });
+                    }
+
+                    let choice = strategy.choose(
+                        &candidates,
+                        query_size,
+                        archive,
+                        machine_id,
+                    );
+                    let interval = candidates.get(choice).ok_or(Error::from(format!(
+                        "query strategy chose interval {} out of {}",
+                        choice,
+                        candidates.len()
+                    )))?;
+                    trace!(
+                        "Querying interval {:?} out of {} candidates",
+                        interval,
+                        candidates.len()
+                    );
+
+                    // do we need another partition?
+                    if !interval.is_divergence() {
+                        // submit the relevant query
+                        info!("Making Query for Partition (index: {})", instance.index);
+                        let request = TransactionRequest {
+                            contract_name: None, // Name not needed, is concern
+                            concern: instance.concern.clone(),
+                            value: U256::from(0),
+                            function: "makeQuery".into(),
+                            // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
+                            // improve these types by letting the
+                            // dapp submit ethereum_types and convert
+                            // them inside the transaction manager
+                            // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
+                            data: vec![
+                                Token::Uint(instance.index),
+                                Token::Uint(U256::from(interval.piece)),
+                                Token::Uint(U256::from(interval.left)),
+                                Token::Uint(U256::from(interval.right)),
+                            ],
+                            gas: policy.gas(urgency),
+                            strategy: policy.strategy(urgency),
+                        };
+                        return Ok(Reaction::Transaction(request));
+                    } else {
+                        // submit divergence time
+                        info!(
+                            "Divergence found for Partition (index: {}, time: {})",
+                            instance.index, interval.left
+                        );
+                        let request = TransactionRequest {
+                            contract_name: None, // Name not needed, is concern
+                            concern: instance.concern.clone(),
+                            value: U256::from(0),
+                            function: "presentDivergence".into(),
+                            // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
+                            // improve these types by letting the
+                            // dapp submit ethereum_types and convert
+                            // them inside the transaction manager
+                            // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
+                            data: vec![
+                                Token::Uint(instance.index),
+                                Token::Uint(U256::from(interval.left)),
+                            ],
+                            gas: policy.gas(urgency),
+                            strategy: policy.strategy(urgency),
+                        };
+                        return Ok(Reaction::Transaction(request));
                     }
-                    // no disagreement found. important bug!!!!
-                    error!("bug found: no disagreement in dispute {:?}!!!", instance);
-                    return Err(Error::from(format!("no disagreement in dispute")));
                 }
-                "WaitingHashes" => {
-                    return win_by_deadline_or_idle(
+                PartitionState::WaitingHashes => {
+                    let reaction = win_by_deadline_or_idle(
                         &instance.concern,
                         instance.index,
-                        ctx.deadline.as_u64(),
-                    );
+                        deadline,
+                        params,
+                    )?;
+                    if let Reaction::Idle = reaction {
+                        if params.prefetch_runs() {
+                            // the hashes of our query are checked against
+                            // the reply, then any interval may be queried
+                            let query_array = to_u64s(&ctx.query_array, "Partition query time")?;
+                            let mut runs = vec![query_array.clone()];
+                            runs.extend(next_query_arrays(&query_array, query_size));
+                            prefetch(archive, params, runs)?;
+                        }
+                    }
+                    return Ok(reaction);
                 }
-                _ => {
-                    return Err(Error::from(ErrorKind::InvalidContractState(format!(
-                        "Unknown current state {}",
-                        ctx.current_state
-                    ))));
+                PartitionState::ChallengerWon
+                | PartitionState::ClaimerWon
+                | PartitionState::DivergenceFound => {
+                    return Ok(Reaction::Idle);
                 }
             },
         }
     }
 
+}
+
+impl<P: DisputeParams> DApp<P> for Partition {
+    fn react(
+        instance: &state::Instance,
+        archive: &Archive,
+        post_payload: &Option<String>,
+        params: &P,
+    ) -> Result<Reaction> {
+        Partition::react_with_strategy(instance, archive, post_payload, params, &Bisection)
+    }
+
     fn get_pretty_instance(
         instance: &state::Instance,
         archive: &Archive,
-        _machine_id: &String,
+        params: &P,
     ) -> Result<state::Instance> {
         // get context (state) of the partition instance
         let parsed: PartitionCtxParsed =
@@ -346,8 +519,52 @@ impl DApp<String> for Partition {
                     &instance.json_data
                 )
             })?;
-        let ctx: PartitionCtx = parsed.into();
-        let json_data = serde_json::to_string(&ctx).unwrap();
+        let ctx = PartitionCtx::try_from(parsed)?;
+        let mut progress = Progress::new(
+            role_of(instance.concern.user_address, ctx.claimer, ctx.challenger),
+            partition_next(ctx.current_state),
+        )
+        .with_deadline(ctx.deadline, params.clock());
+        match ctx.current_state {
+            PartitionState::WaitingQuery | PartitionState::WaitingHashes => {
+                // the query points span the interval being bisected
+                let time = |t: &U256| t.to_u64("Partition query time").ok();
+                let first = ctx.query_array.first().and_then(time);
+                let last = ctx.query_array.last().and_then(time);
+                let interval = match (first, last) {
+                    (Some(left), Some(right)) if left <= right => Some(QueryInterval {
+                        piece: 0,
+                        left,
+                        right,
+                    }),
+                    _ => None,
+                };
+                progress.interval_width = interval.map(|i| i.right - i.left);
+                // the query size only counts up to the query times set
+                let query_size = ctx
+                    .query_size
+                    .to_usize("Partition query size")
+                    .ok()
+                    .filter(|&size| size <= ctx.query_array.len());
+                progress.remaining_rounds = match (interval, query_size) {
+                    // slices that never shrink the interval have no end
+                    (Some(interval), Some(query_size)) => {
+                        Some(interval.rounds(query_size)).filter(|&r| r != u64::max_value())
+                    }
+                    _ => None,
+                };
+            }
+            PartitionState::DivergenceFound => {
+                progress.interval_width = Some(1);
+                progress.remaining_rounds = Some(0);
+            }
+            PartitionState::ChallengerWon | PartitionState::ClaimerWon => {}
+        }
+        let json_data = serde_json::to_string(&Pretty {
+            ctx: &ctx,
+            progress,
+        })
+        .unwrap();
         // get context (state) of the sub instances
 
         let pretty_sub_instances: Vec<Box<state::Instance>> = vec![];
@@ -368,8 +585,12 @@ impl DApp<String> for Partition {
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use compute::ComputeParams;
     use dispatcher::dapp::Reaction;
-    use emulator_service::{SessionRunResponse, SessionRunResponseOneOf, SessionRunResult};
+    use urgency::{DefaultPolicy, TransactionPolicy};
+    use emulator_service::{
+        SessionRunProgress, SessionRunResponse, SessionRunResponseOneOf, SessionRunResult,
+    };
     use ethereum_types::H160;
     use tests::{
         build_concern, build_service_status, build_state, encode, hash_from_string, CHALLENGERADDR,
@@ -421,10 +642,10 @@ pub mod tests {
     #[test]
     fn it_should_create_pctx_from_pctx_parsed() {
         let data =
-            build_partition_state_json_data(encode(UNKNOWNSTATE).as_str(), None, None, None, None);
+            build_partition_state_json_data(encode("WaitingQuery").as_str(), None, None, None, None);
         let p: PartitionCtxParsed = serde_json::from_str(&data).unwrap();
 
-        let parsed = PartitionCtx::from(p);
+        let parsed = PartitionCtx::try_from(p).unwrap();
         assert_eq!(parsed.challenger, hash_from_string::<H160>(CHALLENGERADDR));
         assert_eq!(parsed.claimer, hash_from_string::<H160>(CLAIMERADDR));
         assert_eq!(
@@ -433,14 +654,19 @@ pub mod tests {
         );
         assert_eq!(parsed.submitted_array, [true, false]);
         assert_eq!(parsed.hash_array, [hash_from_string::<H256>(HASH1)]);
-        assert_eq!(parsed.current_state, UNKNOWNSTATE);
+        assert_eq!(parsed.current_state, PartitionState::WaitingQuery);
         assert_eq!(parsed.final_time, U256::from(0));
         assert_eq!(parsed.query_size, U256::from(0));
         assert_eq!(parsed.deadline, U256::from(0));
         assert_eq!(parsed.divergence_time, U256::from(0));
+
+        let data =
+            build_partition_state_json_data(encode(UNKNOWNSTATE).as_str(), None, None, None, None);
+        let p: PartitionCtxParsed = serde_json::from_str(&data).unwrap();
+        assert!(PartitionCtx::try_from(p).is_err());
     }
     #[test]
-    #[should_panic(expected = "User is neither claimer nor challenger")]
+    #[should_panic(expected = "Unknown current state Unknown State")]
     fn it_should_be_idle() {
         let current_state = encode("ChallengerWon"); // ChallengerWon
         let archive = Archive::new().unwrap();
@@ -562,6 +788,139 @@ pub mod tests {
         }
     }
 
+    #[test]
+    fn it_should_check_deadline_against_block_timestamp() {
+        let current_state = encode("WaitingQuery");
+        let archive = Archive::new().unwrap();
+        let concern = build_concern(CLAIMERADDR);
+        let state_instance = build_state(
+            concern,
+            Option::from(build_partition_state_json_data(
+                current_state.as_str(),
+                Option::from("0x3e8"),
+                None,
+                None,
+                None,
+            )),
+        );
+        let react = |timestamp, safety_margin| {
+            let params =
+                ComputeParams::with_block_timestamp(String::from(MACHINEID), timestamp, safety_margin);
+            Partition::react(&state_instance, &archive, &None, &params).unwrap()
+        };
+
+        // just before and right at the deadline
+        assert!(matches!(react(999, 0), Reaction::Idle));
+        assert!(matches!(react(1000, 0), Reaction::Idle));
+        // within the safety margin
+        assert!(matches!(react(1010, 10), Reaction::Idle));
+        // just after the deadline
+        for (timestamp, safety_margin) in vec![(1001, 0), (1011, 10)] {
+            if let Reaction::Transaction(transaction) = react(timestamp, safety_margin) {
+                assert_eq!(transaction.function, "claimVictoryByTime");
+                // a missed deadline leaves no time to lose
+                assert_eq!(transaction.gas, DefaultPolicy.gas(Urgency::Critical));
+            } else {
+                panic!("Only transaction");
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_escalate_transactions_near_deadline() {
+        struct Escalating;
+        impl TransactionPolicy for Escalating {
+            fn gas(&self, urgency: Urgency) -> Option<U256> {
+                match urgency {
+                    Urgency::Critical => Some(U256::from(1_000_000)),
+                    _ => None,
+                }
+            }
+        }
+
+        let current_state = encode("WaitingHashes");
+        let mut archive = Archive::new().unwrap();
+        let bin: Vec<u8> = SessionRunResponse {
+            one_of: SessionRunResponseOneOf::RunResult(SessionRunResult {
+                hashes: vec![H256::zero(), H256::zero(), H256::zero()],
+            }),
+        }
+        .into();
+        let key = ArchiveKey::Run {
+            id: String::from(MACHINEID),
+            times: vec![1, 512, 12288],
+        }
+        .to_string();
+        archive.insert_response(key, Ok(bin));
+
+        let state_instance = build_state(
+            build_concern(CLAIMERADDR),
+            Option::from(build_partition_state_json_data(
+                current_state.as_str(),
+                Option::from("0x2710"),
+                Option::from(vec![HASH1, HASH2, HASH3]),
+                Option::from(vec!["0x1", "0x200", "0x3000"]),
+                Option::from("0x3"),
+            )),
+        );
+        let react = |timestamp| {
+            let mut params = ComputeParams::with_block_timestamp(String::from(MACHINEID), timestamp, 0);
+            params.policy = Box::new(Escalating);
+            match Partition::react(&state_instance, &archive, &None, &params).unwrap() {
+                Reaction::Transaction(transaction) => transaction,
+                _ => panic!("Only transaction"),
+            }
+        };
+
+        assert_eq!(react(0).gas, None);
+        let transaction = react(10_000 - 60);
+        assert_eq!(transaction.function, "replyQuery");
+        assert_eq!(transaction.gas, Some(U256::from(1_000_000)));
+    }
+
+    #[test]
+    fn it_should_retry_runs_missing_query_hashes() {
+        let mut archive = Archive::new().unwrap();
+        let bin: Vec<u8> = SessionRunResponse {
+            one_of: SessionRunResponseOneOf::RunResult(SessionRunResult {
+                hashes: vec![H256::zero(), H256::zero()],
+            }),
+        }
+        .into();
+        let key = ArchiveKey::Run {
+            id: String::from(MACHINEID),
+            times: vec![1, 512, 12288],
+        }
+        .to_string();
+        archive.insert_response(key, Ok(bin));
+
+        let parties = vec![
+            (CLAIMERADDR, "WaitingHashes"),
+            (CHALLENGERADDR, "WaitingQuery"),
+        ];
+        for (user, state) in parties {
+            let state_instance = build_state(
+                build_concern(user),
+                Option::from(build_partition_state_json_data(
+                    encode(state).as_str(),
+                    Option::from("0x1fffffffffffff"),
+                    Option::from(vec![HASH1, HASH2, HASH3]),
+                    Option::from(vec!["0x1", "0x200", "0x3000"]),
+                    Option::from("0x3"),
+                )),
+            );
+            let error =
+                Partition::react(&state_instance, &archive, &None, &String::from(MACHINEID))
+                    .unwrap_err();
+            match error.kind() {
+                ErrorKind::ServiceNeedsRetry(_, _, _, _, _, _, _, description) => {
+                    assert_eq!(description, "2 hashes for 3 query times");
+                }
+                _ => panic!("Only retries: {:?}", error),
+            }
+        }
+    }
+
     #[test]
     fn it_should_call_win_by_deadline_as_challenger() {
         let current_state = encode("WaitingHashes"); // WaitingHashes
@@ -623,7 +982,11 @@ pub mod tests {
             let query_array = vec!["0x1", "0x200", "0x3000"];
             let query_array_as_u64: Vec<u64> = vec![1, 512, 12288];
 
-            let key = build_session_run_key(String::from(MACHINEID), query_array_as_u64);
+            let key = ArchiveKey::Run {
+                id: String::from(MACHINEID),
+                times: query_array_as_u64,
+            }
+            .to_string();
             archive.insert_response(key, Ok(bin.clone()));
 
             state_instance.json_data = build_partition_state_json_data(
@@ -653,7 +1016,11 @@ pub mod tests {
             // Re-create input to update values for query_array            let mut archive = Archive::new().unwrap();
             let query_array = vec!["0x1", "0x2", "0x3000"];
             let query_array_as_u64: Vec<u64> = vec![1, 2, 12288];
-            let key = build_session_run_key(String::from(MACHINEID), query_array_as_u64);
+            let key = ArchiveKey::Run {
+                id: String::from(MACHINEID),
+                times: query_array_as_u64,
+            }
+            .to_string();
             archive.insert_response(key, Ok(bin));
             state_instance.json_data = build_partition_state_json_data(
                 current_state.as_str(),
@@ -697,6 +1064,114 @@ pub mod tests {
             panic!("Test should have failed already");
         }
     }
+    #[test]
+    fn it_should_query_interval_chosen_by_strategy() {
+        let current_state = encode("WaitingQuery");
+        let mut archive = Archive::new().unwrap();
+        let bin: Vec<u8> = SessionRunResponse {
+            one_of: SessionRunResponseOneOf::RunResult(SessionRunResult {
+                hashes: vec![H256::zero(); 4],
+            }),
+        }
+        .into();
+        let key = ArchiveKey::Run {
+            id: String::from(MACHINEID),
+            times: vec![0, 100, 101, 102],
+        }
+        .to_string();
+        archive.insert_response(key, Ok(bin));
+
+        // disagreements on [0, 100] and [101, 102]
+        let zero_hash = "0x0000000000000000000000000000000000000000000000000000000000000000";
+        let concern = build_concern(CHALLENGERADDR);
+        let state_instance = build_state(
+            concern,
+            Option::from(build_partition_state_json_data(
+                current_state.as_str(),
+                Option::from("0x1fffffffffffff"),
+                Option::from(vec![zero_hash, HASH1, zero_hash, HASH2]),
+                Option::from(vec!["0x0", "0x64", "0x65", "0x66"]),
+                Option::from("0x4"),
+            )),
+        );
+
+        let reaction =
+            Partition::react(&state_instance, &archive, &None, &String::from(MACHINEID)).unwrap();
+        if let Reaction::Transaction(transaction) = reaction {
+            assert_eq!(transaction.function, "presentDivergence");
+            assert_eq!(transaction.data[1], Token::Uint(U256::from(101)));
+        } else {
+            panic!("Only transaction");
+        }
+
+        struct First;
+        impl QueryStrategy for First {
+            fn choose(&self, _: &[QueryInterval], _: usize, _: &Archive, _: &str) -> usize {
+                0
+            }
+        }
+        let reaction = Partition::react_with_strategy(
+            &state_instance,
+            &archive,
+            &None,
+            &String::from(MACHINEID),
+            &First,
+        )
+        .unwrap();
+        if let Reaction::Transaction(transaction) = reaction {
+            assert_eq!(transaction.function, "makeQuery");
+            assert_eq!(transaction.data[3], Token::Uint(U256::from(100)));
+        } else {
+            panic!("Only transaction");
+        }
+    }
+    #[test]
+    fn it_should_reject_adversarial_contract_values() {
+        let current_state = encode("WaitingQuery");
+        let mut archive = Archive::new().unwrap();
+        let bin: Vec<u8> = SessionRunResponse {
+            one_of: SessionRunResponseOneOf::RunResult(SessionRunResult {
+                hashes: vec![H256::zero(), H256::zero(), H256::zero()],
+            }),
+        }
+        .into();
+        let key = ArchiveKey::Run {
+            id: String::from(MACHINEID),
+            times: vec![1, 2, 3],
+        }
+        .to_string();
+        archive.insert_response(key, Ok(bin));
+
+        let too_large = "0x10000000000000000";
+        let mut state_instance = build_state(build_concern(CHALLENGERADDR), None);
+        for (deadline, query_array, query_size, expected) in vec![
+            (too_large, vec!["0x1", "0x2", "0x3"], "0x3", "Partition deadline"),
+            ("0x1fffffffffffff", vec!["0x1", too_large, "0x3"], "0x3", "Partition query time"),
+            ("0x1fffffffffffff", vec!["0x1", "0x2", "0x3"], too_large, "Partition query size"),
+            ("0x1fffffffffffff", vec!["0x1", "0x2", "0x3"], "0x0", "Partition query size is 0"),
+            (
+                "0x1fffffffffffff",
+                vec!["0x1", "0x2", "0x3"],
+                "0x10000000000",
+                "Partition query size 1099511627776 exceeds the 3 query times",
+            ),
+        ] {
+            state_instance.json_data = build_partition_state_json_data(
+                current_state.as_str(),
+                Option::from(deadline),
+                Option::from(vec![HASH1, HASH2, HASH3]),
+                Option::from(query_array),
+                Option::from(query_size),
+            );
+            let error =
+                Partition::react(&state_instance, &archive, &None, &String::from(MACHINEID))
+                    .unwrap_err();
+            let error = format!("{:?}", error);
+            assert!(error.contains("InvalidContractState"), "{}", error);
+            assert!(error.contains(expected), "{}", error);
+        }
+    }
+
     #[test]
     fn it_should_make_tx_as_claimer() {
         let current_state = encode("WaitingHashes");
@@ -712,7 +1187,11 @@ pub mod tests {
         let query_size = "0x3";
         let query_array = vec!["0x1", "0x200", "0x3000"];
         let query_array_as_u64: Vec<u64> = vec![1, 512, 12288];
-        let key = build_session_run_key(String::from(MACHINEID), query_array_as_u64);
+        let key = ArchiveKey::Run {
+            id: String::from(MACHINEID),
+            times: query_array_as_u64,
+        }
+        .to_string();
         archive.insert_response(key, Ok(bin));
 
         let hash_array = vec![
@@ -749,6 +1228,74 @@ pub mod tests {
             panic!("Only transaction");
         }
     }
+    #[test]
+    fn it_should_prefetch_runs_of_next_round() {
+        let mut archive = Archive::new().unwrap();
+        let state_instance = build_state(
+            build_concern(CLAIMERADDR),
+            Option::from(build_partition_state_json_data(
+                encode("WaitingQuery").as_str(),
+                Option::from("0x1fffffffffffff"),
+                None,
+                Option::from(vec!["0x0", "0x14", "0x28"]),
+                Option::from("0x3"),
+            )),
+        );
+        let params = ComputeParams::with_block_timestamp(String::from(MACHINEID), 0, 0);
+        let missing_times = |archive: &Archive, params: &ComputeParams| {
+            let error = Partition::react(&state_instance, archive, &None, params).unwrap_err();
+            match error.kind() {
+                ErrorKind::ResponseMissError(_, key, _, _) => key.clone(),
+                _ => panic!("Only missing responses: {:?}", error),
+            }
+        };
+        let key = |times: Vec<u64>| {
+            ArchiveKey::Run {
+                id: String::from(MACHINEID),
+                times,
+            }
+            .to_string()
+        };
+        let response = |one_of| -> Vec<u8> { SessionRunResponse { one_of }.into() };
+
+        // without prefetch, the claimer waits for the query
+        assert!(matches!(
+            Partition::react(&state_instance, &archive, &None, &params).unwrap(),
+            Reaction::Idle
+        ));
+
+        let params = params.with_prefetch();
+        assert_eq!(missing_times(&archive, &params), key(vec![0, 10, 20]));
+        // a run in progress does not hold the next one back
+        let progress = SessionRunProgress {
+            progress: 50,
+            application_progress: 0,
+            updated_at: 0,
+            cycle: 10,
+        };
+        archive.insert_response(
+            key(vec![0, 10, 20]),
+            Ok(response(SessionRunResponseOneOf::RunProgress(progress))),
+        );
+        assert_eq!(missing_times(&archive, &params), key(vec![20, 30, 40]));
+        archive.insert_response(
+            key(vec![20, 30, 40]),
+            Ok(response(SessionRunResponseOneOf::RunResult(SessionRunResult {
+                hashes: vec![H256::zero(); 3],
+            }))),
+        );
+        assert!(matches!(
+            Partition::react(&state_instance, &archive, &None, &params).unwrap(),
+            Reaction::Idle
+        ));
+        // a failed prefetch never fails the reaction
+        archive.insert_response(key(vec![0, 10, 20]), Ok(vec![0xff]));
+        assert!(matches!(
+            Partition::react(&state_instance, &archive, &None, &params).unwrap(),
+            Reaction::Idle
+        ));
+    }
+
     #[test]
     fn it_should_get_pretty_instance_correctly() {
         let current_state = encode("ChallengerWon");
@@ -780,5 +1327,10 @@ pub mod tests {
             pretty_json["challenger"]
         );
         assert_eq!(serde_json::json!("0x0"), pretty_json["query_size"]);
+        let progress = &pretty_json["progress"];
+        assert!(progress["role"].is_null());
+        assert_eq!(serde_json::json!("Challenger"), progress["next_to_act"]);
+        assert_eq!(serde_json::json!(false), progress["our_move"]);
+        assert!(progress.get("interval_width").is_none());
     }
 }
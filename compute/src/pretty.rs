@@ -0,0 +1,185 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+
+//! Dispute progress added to the pretty instances of the DApps, so that
+//! operators watching many disputes see at a glance who has to act and
+//! how urgent it is.
+
+use super::ethereum_types::{Address, U256};
+use super::Role;
+use checked::CheckedConversion;
+use clock::Clock;
+use journal::LastReaction;
+use run_progress::RunEstimate;
+use states::{ComputeState, PartitionState, VGState};
+use urgency::Urgency;
+
+use std::convert::TryFrom;
+
+/// Progress of an instance, serialized next to its context
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Progress {
+    /// Role we play, none if we are not a party of the instance
+    pub role: Option<Role>,
+    /// Party the dispute waits on, none once it is over or when either
+    /// party may move
+    pub next_to_act: Option<Role>,
+    /// Whether the dispute waits on us
+    pub our_move: bool,
+    /// Seconds left before the deadline, negative once it is over
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seconds_to_deadline: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub urgency: Option<Urgency>,
+    /// Cycles in the interval being bisected by the partition
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval_width: Option<u64>,
+    /// Partition rounds left before the divergence is found, in the worst
+    /// case, see `QueryInterval::rounds`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remaining_rounds: Option<u64>,
+    /// Accesses proven in the memory manager so far
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proofs_submitted: Option<u64>,
+    /// Accesses in the step log of the divergence time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proofs_total: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_reaction: Option<LastReaction>,
+    /// Emulator runs still in progress for the machine under dispute
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub runs: Vec<RunEstimate>,
+}
+
+impl Progress {
+    pub fn new(role: Option<Role>, next_to_act: Option<Role>) -> Self {
+        Progress {
+            role,
+            next_to_act,
+            our_move: role.is_some() && role == next_to_act,
+            ..Default::default()
+        }
+    }
+
+    /// Time left before `deadline` according to `clock`; left out when the
+    /// clock fails or the deadline does not fit 64 bits
+    pub fn with_deadline(mut self, deadline: U256, clock: &dyn Clock) -> Self {
+        if let (Ok(deadline), Ok(now)) = (deadline.to_u64("deadline"), clock.now()) {
+            let left = i128::from(deadline) - i128::from(now);
+            let saturated = if left < 0 { i64::min_value() } else { i64::max_value() };
+            self.seconds_to_deadline = Some(i64::try_from(left).unwrap_or(saturated));
+            self.urgency = Urgency::of(deadline, clock).ok();
+        }
+        self
+    }
+}
+
+/// Context of an instance followed by its progress
+#[derive(Serialize)]
+pub struct Pretty<'a, C: 'a> {
+    #[serde(flatten)]
+    pub ctx: &'a C,
+    pub progress: Progress,
+}
+
+/// Role of `user` in an instance between `claimer` and `challenger`
+pub fn role_of(user: Address, claimer: Address, challenger: Address) -> Option<Role> {
+    match user {
+        cl if cl == claimer => Some(Role::Claimer),
+        ch if ch == challenger => Some(Role::Challenger),
+        _ => None,
+    }
+}
+
+/// Party the Compute contract waits on; while challenged, the one the
+/// verification game waits on
+pub fn compute_next(state: ComputeState, vg_next: Option<Role>) -> Option<Role> {
+    match state {
+        ComputeState::WaitingClaim => Some(Role::Claimer),
+        ComputeState::WaitingConfirmation => Some(Role::Challenger),
+        ComputeState::WaitingChallenge => vg_next,
+        ComputeState::ClaimerMissedDeadline
+        | ComputeState::ChallengerWon
+        | ComputeState::ClaimerWon
+        | ComputeState::ConsensusResult => None,
+    }
+}
+
+/// Party the verification game waits on. Once it is over, the winner
+/// still has to claim victory in the Compute contract
+pub fn vg_next(state: VGState, partition: Option<PartitionState>) -> Option<Role> {
+    match state {
+        VGState::WaitPartition => partition.and_then(partition_next),
+        // the challenger proves the step and settles the game
+        VGState::WaitMemoryProveValues => Some(Role::Challenger),
+        VGState::FinishedClaimerWon => Some(Role::Claimer),
+        VGState::FinishedChallengerWon => Some(Role::Challenger),
+    }
+}
+
+/// Party the partition waits on. Once it is over, the winner claims
+/// victory in the verification game, and either party starts the
+/// machine run challenge of a divergence
+pub fn partition_next(state: PartitionState) -> Option<Role> {
+    match state {
+        PartitionState::WaitingQuery => Some(Role::Challenger),
+        PartitionState::WaitingHashes => Some(Role::Claimer),
+        PartitionState::ClaimerWon => Some(Role::Claimer),
+        PartitionState::ChallengerWon => Some(Role::Challenger),
+        PartitionState::DivergenceFound => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::BlockClock;
+
+    #[test]
+    fn it_should_tell_who_has_to_act_and_when() {
+        let progress = Progress::new(Some(Role::Claimer), Some(Role::Claimer))
+            .with_deadline(U256::from(1_000), &BlockClock::new(1_100, 0));
+        assert!(progress.our_move);
+        assert_eq!(progress.seconds_to_deadline, Some(-100));
+        assert_eq!(progress.urgency, Some(Urgency::Critical));
+        let json = serde_json::to_value(&progress).unwrap();
+        assert_eq!(json["next_to_act"], "Claimer");
+        assert!(json.get("interval_width").is_none());
+
+        let progress = Progress::new(None, Some(Role::Claimer))
+            .with_deadline(U256::max_value(), &BlockClock::new(0, 0));
+        assert!(!progress.our_move);
+        assert_eq!(progress.seconds_to_deadline, None);
+
+        assert_eq!(
+            compute_next(
+                ComputeState::WaitingChallenge,
+                vg_next(VGState::WaitPartition, Some(PartitionState::WaitingQuery))
+            ),
+            Some(Role::Challenger)
+        );
+    }
+}
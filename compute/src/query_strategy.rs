@@ -0,0 +1,338 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! Strategies used by the challenger to choose which interval of the
+//! partition to query next.
+
+use super::ArchiveKey;
+use super::dispatcher::Archive;
+use emulator_service::{
+    SessionRunRequest, SessionRunResponse, SessionRunResponseOneOf,
+    EMULATOR_METHOD_RUN, EMULATOR_SERVICE_NAME,
+};
+
+use std::convert::TryFrom;
+
+/// Execution gas of `makeQuery`, excluding calldata
+const MAKE_QUERY_GAS: u64 = 60000;
+/// Execution gas of `presentDivergence`, excluding calldata
+const PRESENT_DIVERGENCE_GAS: u64 = 40000;
+/// Base gas of any transaction
+const TRANSACTION_GAS: u64 = 21000;
+/// Calldata gas per zero and non-zero byte (EIP-2028)
+const ZERO_BYTE_GAS: u64 = 4;
+const NONZERO_BYTE_GAS: u64 = 16;
+
+/// Interval of the query array whose left end is agreed upon by both
+/// parties and whose right end is not
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueryInterval {
+    /// Index of the left end in the query array
+    pub piece: usize,
+    pub left: u64,
+    pub right: u64,
+}
+
+impl QueryInterval {
+    /// Whether the divergence can be presented right away
+    pub fn is_divergence(&self) -> bool {
+        self.right - self.left <= 1
+    }
+
+    /// Worst case number of `makeQuery` transactions before the divergence
+    /// can be presented inside this interval
+    pub fn rounds(&self, query_size: usize) -> u64 {
+        // slices of fewer than three points never shrink the interval
+        if query_size < 3 {
+            return if self.is_divergence() { 0 } else { u64::max_value() };
+        }
+        let (mut left, mut right) = (self.left, self.right);
+        let mut rounds = 0;
+        while right - left > 1 {
+            let points = slice(left, right, query_size);
+            let widest = points
+                .windows(2)
+                .max_by_key(|w| w[1] - w[0])
+                .unwrap();
+            left = widest[0];
+            right = widest[1];
+            rounds += 1;
+        }
+        rounds
+    }
+}
+
+/// Query array set by `PartitionInstantiator.slice` for `[left, right]`
+pub fn slice(left: u64, right: u64, query_size: usize) -> Vec<u64> {
+    if query_size < 2 {
+        return vec![left, right];
+    }
+    let last = (query_size - 1) as u64;
+    let interval = right - left;
+    let mut points: Vec<u64> = if interval < 2 * last {
+        (0..last)
+            .map(|i| if left + i < right { left + i } else { right })
+            .collect()
+    } else {
+        let division = interval / last;
+        (0..last).map(|i| left + i * division).collect()
+    };
+    points.push(right);
+    points
+}
+
+/// `PartitionInstantiator.log2OverTwo`, the approximate number of rounds
+/// the contracts budget for a partition of `x` cycles
+pub fn log2_over_two(x: u64) -> u64 {
+    if x == 0 {
+        return 0;
+    }
+    (63 - u64::from(x.leading_zeros())) / 2
+}
+
+/// Query arrays the contract may set after `query_array`, one for each
+/// interval the challenger may query without presenting a divergence
+pub fn next_query_arrays(query_array: &[u64], query_size: usize) -> Vec<Vec<u64>> {
+    let mut arrays: Vec<Vec<u64>> = Vec::new();
+    for pair in query_array.windows(2) {
+        if pair[1] > pair[0].saturating_add(1) {
+            let next = slice(pair[0], pair[1], query_size);
+            if !arrays.contains(&next) {
+                arrays.push(next);
+            }
+        }
+    }
+    arrays
+}
+
+/// Whether the run results needed to answer the query that follows
+/// `interval` are already in the archive
+pub fn is_next_query_cached(
+    archive: &Archive,
+    machine_id: &str,
+    interval: &QueryInterval,
+    query_size: usize,
+) -> bool {
+    // presenting the divergence needs no further run
+    if interval.is_divergence() {
+        return true;
+    }
+    let times = slice(interval.left, interval.right, query_size);
+    let request = SessionRunRequest {
+        session_id: machine_id.to_string(),
+        times: times.clone(),
+    };
+    match archive.get_response(
+        EMULATOR_SERVICE_NAME.to_string(),
+        ArchiveKey::Run { id: machine_id.to_string(), times }.to_string(),
+        EMULATOR_METHOD_RUN.to_string(),
+        request.into(),
+    ) {
+        Ok(bin) => match SessionRunResponse::try_from(bin) {
+            Ok(response) => match response.one_of {
+                SessionRunResponseOneOf::RunResult(_) => true,
+                SessionRunResponseOneOf::RunProgress(_) => false,
+            },
+            Err(_) => false,
+        },
+        Err(_) => false,
+    }
+}
+
+/// Chooses the next interval queried by the challenger
+pub trait QueryStrategy {
+    /// Position in `candidates` of the interval to query; `candidates`
+    /// is never empty and every entry contains a divergence
+    fn choose(
+        &self,
+        candidates: &[QueryInterval],
+        query_size: usize,
+        archive: &Archive,
+        machine_id: &str,
+    ) -> usize;
+}
+
+/// Minimizes the number of partition rounds left, preferring intervals
+/// whose next query is already cached and then the earliest one
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Bisection;
+
+impl QueryStrategy for Bisection {
+    fn choose(
+        &self,
+        candidates: &[QueryInterval],
+        query_size: usize,
+        archive: &Archive,
+        machine_id: &str,
+    ) -> usize {
+        candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, c)| {
+                (
+                    c.rounds(query_size),
+                    !is_next_query_cached(archive, machine_id, c, query_size),
+                )
+            })
+            .map(|(i, _)| i)
+            .unwrap_or(0)
+    }
+}
+
+/// Minimizes the estimated gas the challenger pays until the divergence
+/// is presented, preferring cached intervals among equally priced ones
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CheapestGas;
+
+fn calldata_gas(arguments: &[u64]) -> u64 {
+    // selector plus one 32 bytes word per argument
+    let mut gas = 4 * NONZERO_BYTE_GAS;
+    for argument in arguments {
+        let nonzero = 8 - argument.leading_zeros() as u64 / 8;
+        gas += nonzero * NONZERO_BYTE_GAS + (32 - nonzero) * ZERO_BYTE_GAS;
+    }
+    gas
+}
+
+impl CheapestGas {
+    /// Estimated gas of every transaction left in the partition if
+    /// `interval` is queried
+    pub fn estimate(interval: &QueryInterval, query_size: usize) -> u64 {
+        let rounds = interval.rounds(query_size);
+        let make_query = TRANSACTION_GAS
+            + MAKE_QUERY_GAS
+            + calldata_gas(&[interval.piece as u64, interval.left, interval.right]);
+        let present_divergence =
+            TRANSACTION_GAS + PRESENT_DIVERGENCE_GAS + calldata_gas(&[interval.left]);
+        rounds
+            .saturating_mul(make_query)
+            .saturating_add(present_divergence)
+    }
+}
+
+impl QueryStrategy for CheapestGas {
+    fn choose(
+        &self,
+        candidates: &[QueryInterval],
+        query_size: usize,
+        archive: &Archive,
+        machine_id: &str,
+    ) -> usize {
+        candidates
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, c)| {
+                (
+                    CheapestGas::estimate(c, query_size),
+                    !is_next_query_cached(archive, machine_id, c, query_size),
+                )
+            })
+            .map(|(i, _)| i)
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emulator_service::SessionRunResult;
+    use ethereum_types::H256;
+    use tests::MACHINEID;
+
+    fn interval(piece: usize, left: u64, right: u64) -> QueryInterval {
+        QueryInterval { piece, left, right }
+    }
+
+    #[test]
+    fn it_should_slice_like_the_contract() {
+        assert_eq!(slice(0, 4, 10), vec![0, 1, 2, 3, 4, 4, 4, 4, 4, 4]);
+        assert_eq!(slice(10, 100, 10), vec![10, 20, 30, 40, 50, 60, 70, 80, 90, 100]);
+        assert_eq!(slice(0, 20, 10), vec![0, 2, 4, 6, 8, 10, 12, 14, 16, 20]);
+        assert_eq!(slice(0, 17, 10), vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 17]);
+    }
+
+    #[test]
+    fn it_should_compute_log2_over_two_like_the_contract() {
+        assert_eq!(log2_over_two(0), 0);
+        assert_eq!(log2_over_two(1), 0);
+        assert_eq!(log2_over_two(4), 1);
+        assert_eq!(log2_over_two(1 << 20), 10);
+        assert_eq!(log2_over_two((1 << 21) - 1), 10);
+        assert_eq!(log2_over_two(u64::max_value()), 31);
+    }
+
+    #[test]
+    fn it_should_predict_next_query_arrays() {
+        assert_eq!(
+            next_query_arrays(&[0, 20, 21, 40], 3),
+            vec![vec![0, 10, 20], vec![21, 30, 40]]
+        );
+        // padded queries repeat their right end
+        assert_eq!(next_query_arrays(&[0, 1, 2, 3, 4, 4, 4, 4, 4, 4], 10), Vec::<Vec<u64>>::new());
+        assert_eq!(next_query_arrays(&slice(0, 100, 10), 10)[0], slice(0, 10, 10));
+    }
+
+    #[test]
+    fn it_should_count_rounds() {
+        assert_eq!(interval(0, 7, 8).rounds(10), 0);
+        assert_eq!(interval(0, 0, 9).rounds(10), 1);
+        assert_eq!(interval(0, 0, 100).rounds(10), 3);
+        assert_eq!(interval(0, 0, 100).rounds(2), u64::max_value());
+    }
+
+    #[test]
+    fn it_should_prefer_shorter_intervals() {
+        let archive = Archive::new().unwrap();
+        let id = String::from(MACHINEID);
+        let candidates = vec![interval(0, 0, 1000), interval(5, 5000, 5001)];
+        assert_eq!(Bisection.choose(&candidates, 10, &archive, &id), 1);
+        assert_eq!(CheapestGas.choose(&candidates, 10, &archive, &id), 1);
+    }
+
+    #[test]
+    fn it_should_prefer_cached_intervals() {
+        let mut archive = Archive::new().unwrap();
+        let id = String::from(MACHINEID);
+        let candidates = vec![interval(0, 0, 90), interval(5, 500, 590)];
+        assert_eq!(Bisection.choose(&candidates, 10, &archive, &id), 0);
+
+        let times = slice(500, 590, 10);
+        let bin: Vec<u8> = SessionRunResponse {
+            one_of: SessionRunResponseOneOf::RunResult(SessionRunResult {
+                hashes: vec![H256::zero(); times.len()],
+            }),
+        }
+        .into();
+        let key = ArchiveKey::Run {
+            id: id.clone(),
+            times,
+        };
+        archive.insert_response(key.to_string(), Ok(bin));
+        assert!(is_next_query_cached(&archive, &id, &candidates[1], 10));
+        assert_eq!(Bisection.choose(&candidates, 10, &archive, &id), 1);
+        // the cheaper calldata of the earlier interval wins over the cache
+        assert_eq!(CheapestGas.choose(&candidates, 10, &archive, &id), 0);
+    }
+}
@@ -0,0 +1,184 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! Local replay of a step access log, following what the memory manager
+//! contract does with each `proveRead` and `proveWrite`.
+
+use super::ethereum_types::H256;
+use emulator_service::{Access, AccessType};
+use merkle::{word_hash, ProofError, LOG2_WORD_SIZE};
+
+use std::fmt;
+
+/// A step log that the memory manager contract would reject
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayError {
+    /// Position of the offending access in the log
+    pub index: usize,
+    pub error: ProofError,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "access {} of step log: {}", self.index, self.error)
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+/// Expected result of `settleVerificationGame`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettlementOutcome {
+    ClaimerWins,
+    ChallengerWins,
+}
+
+impl SettlementOutcome {
+    /// Outcome given the hash left in the memory manager after all proofs
+    /// and the hash the claimer committed to right after the divergence
+    pub fn from_hashes(new_hash: &H256, hash_after_divergence: &H256) -> Self {
+        if new_hash != hash_after_divergence {
+            SettlementOutcome::ChallengerWins
+        } else {
+            SettlementOutcome::ClaimerWins
+        }
+    }
+}
+
+fn apply_access(root_hash: &H256, access: &Access) -> Result<H256, ProofError> {
+    let proof = &access.proof;
+    if proof.log2_target_size != LOG2_WORD_SIZE {
+        return Err(ProofError::InvalidSize {
+            log2_target_size: proof.log2_target_size,
+            log2_root_size: proof.log2_root_size,
+        });
+    }
+    // the value read must be in the tree we have so far
+    let computed = proof.root_with_target(&word_hash(&access.value_read))?;
+    if computed != *root_hash {
+        return Err(ProofError::RootMismatch {
+            expected: *root_hash,
+            computed,
+        });
+    }
+    match access.field_type {
+        AccessType::Read => Ok(*root_hash),
+        AccessType::Write => proof.root_with_target(&word_hash(&access.value_written)),
+    }
+}
+
+/// Apply every access of `log` to `initial_hash`, in order, returning the
+/// root hash of the machine after the step
+pub fn replay_access_log(
+    initial_hash: H256,
+    log: &[Access],
+) -> Result<H256, ReplayError> {
+    let mut root_hash = initial_hash;
+    for (index, access) in log.iter().enumerate() {
+        root_hash =
+            apply_access(&root_hash, access).map_err(|error| ReplayError { index, error })?;
+    }
+    Ok(root_hash)
+}
+
+/// Predict the result of `settleVerificationGame` if the whole `log` is
+/// proven on top of `hash_before_divergence`
+pub fn predict_settlement(
+    hash_before_divergence: H256,
+    hash_after_divergence: H256,
+    log: &[Access],
+) -> Result<SettlementOutcome, ReplayError> {
+    let new_hash = replay_access_log(hash_before_divergence, log)?;
+    Ok(SettlementOutcome::from_hashes(
+        &new_hash,
+        &hash_after_divergence,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use merkle::tests::build_word_proof;
+
+    const ADDRESS: u64 = 0x2000;
+
+    fn build_access(field_type: AccessType, value_read: [u8; 8], value_written: [u8; 8]) -> Access {
+        Access {
+            field_type,
+            address: ADDRESS,
+            value_read,
+            value_written,
+            proof: build_word_proof(ADDRESS, value_read),
+        }
+    }
+
+    #[test]
+    fn it_should_replay_reads_and_writes() {
+        let before = build_word_proof(ADDRESS, [1; 8]).root_hash;
+        let after = build_word_proof(ADDRESS, [2; 8]).root_hash;
+        let log = vec![
+            build_access(AccessType::Read, [1; 8], [1; 8]),
+            build_access(AccessType::Write, [1; 8], [2; 8]),
+            build_access(AccessType::Read, [2; 8], [2; 8]),
+        ];
+
+        assert_eq!(replay_access_log(before, &log[..1]), Ok(before));
+        assert_eq!(replay_access_log(before, &log), Ok(after));
+        assert_eq!(replay_access_log(before, &[]), Ok(before));
+    }
+
+    #[test]
+    fn it_should_reject_inconsistent_logs() {
+        let before = build_word_proof(ADDRESS, [1; 8]).root_hash;
+        let log = vec![
+            build_access(AccessType::Write, [1; 8], [2; 8]),
+            // reads the value from before the write
+            build_access(AccessType::Read, [1; 8], [1; 8]),
+        ];
+
+        let error = replay_access_log(before, &log).unwrap_err();
+        assert_eq!(error.index, 1);
+        assert!(matches!(error.error, ProofError::RootMismatch { .. }));
+
+        let error = replay_access_log(H256::zero(), &log).unwrap_err();
+        assert_eq!(error.index, 0);
+    }
+
+    #[test]
+    fn it_should_predict_settlement() {
+        let before = build_word_proof(ADDRESS, [1; 8]).root_hash;
+        let after = build_word_proof(ADDRESS, [2; 8]).root_hash;
+        let log = vec![build_access(AccessType::Write, [1; 8], [2; 8])];
+
+        assert_eq!(
+            predict_settlement(before, after, &log),
+            Ok(SettlementOutcome::ClaimerWins)
+        );
+        assert_eq!(
+            predict_settlement(before, H256::zero(), &log),
+            Ok(SettlementOutcome::ChallengerWins)
+        );
+    }
+}
@@ -0,0 +1,257 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! Tracker of the emulator runs still in progress. The machine manager
+//! answers a run it has not finished with its progress; the samples of
+//! the recent answers give the speed of the emulator and an estimate of
+//! when the run completes. The samples belong to whoever passes the DApps
+//! their parameters, see `DisputeParams::run_progress`.
+
+use super::ArchiveKey;
+use emulator_service::SessionRunProgress;
+
+use tracker::{self, Tracker};
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Mutex;
+
+/// Progress samples kept per run, the oldest being dropped first
+pub const MAX_SAMPLES: usize = 16;
+/// Runs tracked at once, the least recently sampled being dropped first
+pub const MAX_TRACKED_RUNS: usize = 256;
+
+/// Speed and expected completion of a run, from its progress samples
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RunEstimate {
+    /// Archive key of the run request
+    pub key: String,
+    pub cycle: u64,
+    /// Percentage of the run done
+    pub progress: u64,
+    pub application_progress: u64,
+    /// Time of the latest sample, in seconds since the UNIX epoch
+    pub updated_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cycles_per_second: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seconds_to_completion: Option<u64>,
+    /// Expected completion time, in seconds since the UNIX epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completion_at: Option<u64>,
+}
+
+impl fmt::Display for RunEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cycle {}, {}% done", self.cycle, self.progress)?;
+        if let Some(speed) = self.cycles_per_second {
+            write!(f, ", {:.0} cycles/s", speed)?;
+        }
+        if let Some(seconds) = self.seconds_to_completion {
+            write!(f, ", about {}s left", seconds)?;
+        }
+        Ok(())
+    }
+}
+
+fn estimate_of(key: &ArchiveKey, samples: &VecDeque<SessionRunProgress>) -> Option<RunEstimate> {
+    let (first, last) = (samples.front()?, samples.back()?);
+    let elapsed = last.updated_at.saturating_sub(first.updated_at);
+    let (cycles_per_second, seconds_to_completion) = if elapsed == 0 {
+        (None, None)
+    } else {
+        let cycles = last.cycle.saturating_sub(first.cycle) as f64;
+        // the progress percentage accounts for the cycles left, which
+        // the run response does not tell
+        let done = last.progress.saturating_sub(first.progress) as f64;
+        let left = 100u64.saturating_sub(last.progress) as f64;
+        let seconds = if done > 0.0 {
+            Some((left * elapsed as f64 / done).ceil() as u64)
+        } else {
+            None
+        };
+        (Some(cycles / elapsed as f64), seconds)
+    };
+    Some(RunEstimate {
+        key: key.to_string(),
+        cycle: last.cycle,
+        progress: last.progress,
+        application_progress: last.application_progress,
+        updated_at: last.updated_at,
+        cycles_per_second,
+        seconds_to_completion,
+        completion_at: seconds_to_completion.map(|s| last.updated_at.saturating_add(s)),
+    })
+}
+
+/// Progress samples of the runs in progress, by archive key
+#[derive(Debug)]
+pub struct RunProgress {
+    runs: Mutex<Tracker<ArchiveKey, VecDeque<SessionRunProgress>>>,
+}
+
+impl RunProgress {
+    pub fn new() -> Self {
+        RunProgress {
+            runs: Mutex::new(Tracker::new(MAX_TRACKED_RUNS)),
+        }
+    }
+
+    /// Add a progress sample of the run archived under `key`, returning
+    /// the updated estimate
+    pub fn record(&self, key: &ArchiveKey, progress: &SessionRunProgress) -> Option<RunEstimate> {
+        tracker::with(&self.runs, |runs| {
+            let samples = runs.entry(key.clone(), VecDeque::new);
+            // retries may see the same answer more than once
+            if samples.back().map(|s| s.updated_at) != Some(progress.updated_at) {
+                if samples.len() == MAX_SAMPLES {
+                    samples.pop_front();
+                }
+                samples.push_back(progress.clone());
+            }
+            estimate_of(key, samples)
+        })
+    }
+
+    /// Estimate of the run archived under `key`, if it is in progress
+    pub fn estimate(&self, key: &ArchiveKey) -> Option<RunEstimate> {
+        tracker::with(&self.runs, |runs| {
+            runs.get(key).and_then(|samples| estimate_of(key, samples))
+        })
+    }
+
+    /// Stop tracking the run archived under `key`, once it is finished
+    pub fn forget(&self, key: &ArchiveKey) {
+        tracker::with(&self.runs, |runs| {
+            runs.remove(key);
+        });
+    }
+
+    /// Estimates of the runs of session `id` still in progress
+    pub fn estimates(&self, id: &str) -> Vec<RunEstimate> {
+        tracker::with(&self.runs, |runs| {
+            runs.iter()
+                .filter(|(key, _)| match key {
+                    ArchiveKey::Run { id: run_id, .. } => run_id == id,
+                    _ => false,
+                })
+                .filter_map(|(key, samples)| estimate_of(key, samples))
+                .collect()
+        })
+    }
+}
+
+impl Default for RunProgress {
+    fn default() -> Self {
+        RunProgress::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dispatcher::Archive;
+    use emulator_service::{SessionRunResponse, SessionRunResponseOneOf, SessionRunResult};
+    use error::ErrorKind;
+    use get_run_result;
+
+    fn sample(cycle: u64, progress: u64, updated_at: u64) -> SessionRunProgress {
+        SessionRunProgress {
+            progress,
+            application_progress: 0,
+            updated_at,
+            cycle,
+        }
+    }
+
+    #[test]
+    fn it_should_estimate_speed_and_completion() {
+        let runs = RunProgress::new();
+        let key = ArchiveKey::Run {
+            id: String::from("tracker"),
+            times: vec![0, 100],
+        };
+        let first = runs.record(&key, &sample(1_000, 10, 100)).unwrap();
+        assert_eq!(first.cycles_per_second, None);
+        assert_eq!(first.seconds_to_completion, None);
+
+        runs.record(&key, &sample(3_000, 30, 120));
+        let estimate = runs.record(&key, &sample(3_000, 30, 120)).unwrap();
+        assert_eq!(estimate.key, key.to_string());
+        assert_eq!(estimate.cycles_per_second, Some(100.0));
+        // 20% took 20 seconds, 70% are left
+        assert_eq!(estimate.seconds_to_completion, Some(70));
+        assert_eq!(estimate.completion_at, Some(190));
+        assert_eq!(runs.estimate(&key), Some(estimate.clone()));
+        assert_eq!(runs.estimates("tracker"), vec![estimate]);
+        // sessions whose id only starts with the same name are apart
+        assert!(runs.estimates("track").is_empty());
+
+        runs.forget(&key);
+        assert!(runs.estimates("tracker").is_empty());
+    }
+
+    #[test]
+    fn it_should_report_estimates_in_retries() {
+        let mut archive = Archive::new().unwrap();
+        let runs = RunProgress::new();
+        let key = ArchiveKey::Run {
+            id: String::from("tracker"),
+            times: vec![0, 1_000],
+        };
+        let run = |archive: &Archive| {
+            get_run_result(archive, "Test".to_string(), &key, vec![], Some(&runs)).map(|_| ())
+        };
+        let bin: Vec<u8> = SessionRunResponse {
+            one_of: SessionRunResponseOneOf::RunProgress(sample(0, 0, 10)),
+        }
+        .into();
+        archive.insert_response(key.to_string(), Ok(bin));
+        assert!(run(&archive).is_err());
+        let bin: Vec<u8> = SessionRunResponse {
+            one_of: SessionRunResponseOneOf::RunProgress(sample(500, 50, 20)),
+        }
+        .into();
+        archive.insert_response(key.to_string(), Ok(bin));
+        let error = run(&archive).unwrap_err();
+        match error.kind() {
+            ErrorKind::ServiceNeedsRetry(_, _, _, _, _, _, progress, description) => {
+                assert_eq!(*progress, 50);
+                assert!(description.contains("50 cycles/s"), "{}", description);
+                assert!(description.contains("about 10s left"), "{}", description);
+            }
+            _ => panic!("Only retries: {:?}", error),
+        }
+        assert_eq!(runs.estimates("tracker").len(), 1);
+
+        let bin: Vec<u8> = SessionRunResponse {
+            one_of: SessionRunResponseOneOf::RunResult(SessionRunResult { hashes: vec![] }),
+        }
+        .into();
+        archive.insert_response(key.to_string(), Ok(bin));
+        assert!(run(&archive).is_ok());
+        assert_eq!(runs.estimate(&key), None);
+    }
+}
@@ -0,0 +1,1258 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! Two-party dispute simulator.
+//!
+//! The Compute, VG, Partition and MM instantiators are modeled after the
+//! contracts in `contracts/`. Both parties react to the model through
+//! `Compute::react`, each with its own archive and `MockMachineManager`,
+//! and their transactions are applied to the model until the dispute ends.
+
+use super::dispatcher::{Archive, DApp, Reaction};
+use super::error::Result;
+use super::ethabi::Token;
+use super::ethereum_types::{Address, H160, H256, U256};
+use super::transaction::TransactionRequest;
+use super::cartesi_machine;
+use compute::{Compute, ComputeParams, DisputeParams, InstanceParams};
+use forensic::ForensicBundle;
+use input::InputDrive;
+use journal::Journal;
+use emulator_service::AccessType;
+use merkle::{compute_root, word_hash, LOG2_MACHINE_SIZE, LOG2_WORD_SIZE};
+use mock_manager::{MockMachineManager, ToyMachine, COUNTER_ADDRESS};
+use output::OutputRange;
+use run_progress::RunProgress;
+use tests::{
+    build_service_status, encode, hash_from_string, CHALLENGERADDR,
+    CLAIMERADDR, MACHINEADDR, MACHINEID,
+};
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const COMPUTEADDR: &str = "0xc000000000000000000000000000000000000001";
+const VGADDR: &str = "0xc000000000000000000000000000000000000002";
+const PARTITIONADDR: &str = "0xc000000000000000000000000000000000000003";
+const MMADDR: &str = "0xc000000000000000000000000000000000000004";
+
+/// Query size used by the VG instantiator when creating a partition
+const QUERY_SIZE: usize = 10;
+/// Length of the arrays returned by `PartitionInstantiator.getState`
+const MAX_QUERY_SIZE: usize = 20;
+/// Upper bound on simulation rounds, so a livelock fails the test
+const MAX_ROUNDS: usize = 1000;
+
+type Revert = std::result::Result<(), String>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Party {
+    Claimer,
+    Challenger,
+}
+
+/// One side of the dispute, with its own view of the machine
+pub struct Player {
+    pub party: Party,
+    pub address: Address,
+    /// Offline players never react
+    pub online: bool,
+    pub mock: MockMachineManager,
+    pub archive: Archive,
+    /// Machine the player opens its session from, if it manages the
+    /// session itself
+    pub machine: Option<cartesi_machine::MachineRequest>,
+    /// Where the player writes forensic bundles
+    pub forensic_dir: Option<PathBuf>,
+    /// Output range the player claims or confirms
+    pub output: Option<OutputRange>,
+    /// Input drive the player injects into its session
+    pub input: Option<InputDrive>,
+    /// Whether the player runs the next partition rounds ahead
+    pub prefetch: bool,
+    /// Transactions the model rejected
+    pub reverts: Vec<String>,
+    /// Journal kept across the player's reactions
+    pub journal: Arc<Journal>,
+    /// Progress samples kept across the player's reactions
+    pub runs: Arc<RunProgress>,
+}
+
+impl Player {
+    pub fn new(party: Party, machine: ToyMachine) -> Self {
+        let address = match party {
+            Party::Claimer => hash_from_string::<H160>(CLAIMERADDR),
+            Party::Challenger => hash_from_string::<H160>(CHALLENGERADDR),
+        };
+        Player {
+            party,
+            address,
+            online: true,
+            mock: MockMachineManager::new(machine.clone()).with_session(MACHINEID, machine),
+            archive: Archive::new().unwrap(),
+            machine: None,
+            forensic_dir: None,
+            output: None,
+            input: None,
+            prefetch: false,
+            reverts: vec![],
+            journal: Arc::new(Journal::new()),
+            runs: Arc::new(RunProgress::new()),
+        }
+    }
+
+    /// A player whose machine session is opened and ended by `Compute`
+    pub fn managing_session(party: Party, machine: ToyMachine) -> Self {
+        let mut player = Player::new(party, machine.clone());
+        player.mock = MockMachineManager::new(machine);
+        player.machine = Some(cartesi_machine::MachineRequest::new());
+        player
+    }
+
+    pub fn offline(mut self) -> Self {
+        self.online = false;
+        self
+    }
+}
+
+struct ComputeModel {
+    final_time: u64,
+    initial_hash: H256,
+    claimed_final_hash: H256,
+    state: &'static str,
+    time_of_last_move: u64,
+}
+
+struct VGModel {
+    claimer_final_hash: H256,
+    hash_before_divergence: H256,
+    hash_after_divergence: H256,
+    divergence_time: u64,
+    state: &'static str,
+    time_of_last_move: u64,
+}
+
+struct PartitionModel {
+    final_time: u64,
+    query_array: Vec<u64>,
+    time_hash: HashMap<u64, H256>,
+    divergence_time: u64,
+    state: &'static str,
+    time_of_last_move: u64,
+}
+
+impl PartitionModel {
+    fn new(initial_hash: H256, claimer_final_hash: H256, final_time: u64, now: u64) -> Self {
+        let mut time_hash = HashMap::new();
+        time_hash.insert(0, initial_hash);
+        time_hash.insert(final_time, claimer_final_hash);
+        let mut partition = PartitionModel {
+            final_time,
+            query_array: vec![0; QUERY_SIZE],
+            time_hash,
+            divergence_time: 0,
+            state: "WaitingHashes",
+            time_of_last_move: now,
+        };
+        partition.slice(0, final_time);
+        partition
+    }
+
+    // PartitionInstantiator.slice
+    fn slice(&mut self, left: u64, right: u64) {
+        let interval = right - left;
+        let last = (QUERY_SIZE - 1) as u64;
+        if interval < 2 * last {
+            for i in 0..last {
+                self.query_array[i as usize] = if left + i < right { left + i } else { right };
+            }
+        } else {
+            let division = interval / last;
+            for i in 0..last {
+                self.query_array[i as usize] = left + i * division;
+            }
+        }
+        self.query_array[last as usize] = right;
+    }
+}
+
+struct MMModel {
+    initial_hash: H256,
+    new_hash: H256,
+    history: Vec<(bool, u64, [u8; 8])>,
+    state: &'static str,
+}
+
+/// Model of the four contracts for a single Compute instance
+pub struct Dispute {
+    /// Index of the Compute instance, unique per dispute so that no two
+    /// tests play the same instance
+    index: U256,
+    now: u64,
+    epoch: u64,
+    round_duration: u64,
+    claimer: Address,
+    challenger: Address,
+    truth: ToyMachine,
+    oracle: MockMachineManager,
+    compute: ComputeModel,
+    vg: Option<VGModel>,
+    partition: Option<PartitionModel>,
+    mm: Option<MMModel>,
+}
+
+fn uint(data: &[Token], i: usize) -> std::result::Result<u64, String> {
+    match data.get(i) {
+        Some(Token::Uint(u)) => Ok(u.low_u64()),
+        _ => Err(format!("argument {} is not a uint", i)),
+    }
+}
+
+fn fixed_bytes(data: &[Token], i: usize) -> std::result::Result<Vec<u8>, String> {
+    match data.get(i) {
+        Some(Token::FixedBytes(b)) => Ok(b.clone()),
+        _ => Err(format!("argument {} is not fixed bytes", i)),
+    }
+}
+
+fn word(data: &[Token], i: usize) -> std::result::Result<[u8; 8], String> {
+    let bytes = fixed_bytes(data, i)?;
+    if bytes.len() != 8 {
+        return Err(format!("argument {} is not a bytes8", i));
+    }
+    let mut word = [0u8; 8];
+    word.copy_from_slice(&bytes);
+    Ok(word)
+}
+
+fn array(data: &[Token], i: usize) -> std::result::Result<Vec<Token>, String> {
+    match data.get(i) {
+        Some(Token::Array(a)) => Ok(a.clone()),
+        _ => Err(format!("argument {} is not an array", i)),
+    }
+}
+
+fn require(condition: bool, message: &str) -> Revert {
+    if condition {
+        Ok(())
+    } else {
+        Err(message.to_string())
+    }
+}
+
+static NEXT_INDEX: AtomicU64 = AtomicU64::new(0);
+
+fn field<T: serde::Serialize>(name: &str, value: T, field_type: &str) -> serde_json::Value {
+    serde_json::json!({"name": name, "value": value, "type": field_type})
+}
+
+impl Dispute {
+    /// A Compute instance waiting for a claim about `truth` at `final_time`
+    pub fn new(truth: ToyMachine, final_time: u64) -> Self {
+        let oracle = MockMachineManager::new(truth.clone());
+        let initial_hash = oracle.hash_at(&truth, 0);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        Dispute {
+            index: U256::from(NEXT_INDEX.fetch_add(1, Ordering::SeqCst)),
+            now,
+            epoch: now,
+            round_duration: 100,
+            claimer: hash_from_string::<H160>(CLAIMERADDR),
+            challenger: hash_from_string::<H160>(CHALLENGERADDR),
+            truth,
+            oracle,
+            compute: ComputeModel {
+                final_time,
+                initial_hash,
+                claimed_final_hash: H256::zero(),
+                state: "WaitingClaim",
+                time_of_last_move: now,
+            },
+            vg: None,
+            partition: None,
+            mm: None,
+        }
+    }
+
+    fn deadline(&self, time_of_last_move: u64) -> U256 {
+        U256::from(time_of_last_move + self.round_duration)
+    }
+
+    fn after_deadline(&self, time_of_last_move: u64) -> bool {
+        self.now > time_of_last_move + self.round_duration
+    }
+
+    /// Winner of the dispute, once the Compute instance is finished
+    pub fn winner(&self) -> Option<Party> {
+        match self.compute.state {
+            "ConsensusResult" | "ClaimerWon" => Some(Party::Claimer),
+            "ClaimerMissedDeadline" | "ChallengerWon" => Some(Party::Challenger),
+            _ => None,
+        }
+    }
+
+    fn concern(&self, contract: &str, user: Address) -> configuration::Concern {
+        configuration::Concern {
+            contract_address: hash_from_string::<H160>(contract),
+            user_address: user,
+        }
+    }
+
+    fn instance(&self, name: &str, contract: &str, user: Address, json: serde_json::Value) -> state::Instance {
+        state::Instance {
+            name: name.to_string(),
+            concern: self.concern(contract, user),
+            index: U256::from(0),
+            service_status: build_service_status(),
+            json_data: serde_json::to_string(&json).unwrap(),
+            sub_instances: vec![],
+        }
+    }
+
+    fn partition_instance(&self, p: &PartitionModel, user: Address) -> state::Instance {
+        let mut submitted = vec![false; MAX_QUERY_SIZE];
+        let mut hashes = vec![H256::zero(); MAX_QUERY_SIZE];
+        for (j, t) in p.query_array.iter().enumerate() {
+            if let Some(h) = p.time_hash.get(t) {
+                submitted[j] = true;
+                hashes[j] = *h;
+            }
+        }
+        let query_array: Vec<U256> = p.query_array.iter().map(|t| U256::from(*t)).collect();
+        let uint_values = vec![
+            U256::from(p.final_time),
+            U256::from(QUERY_SIZE),
+            self.deadline(p.time_of_last_move),
+            U256::from(p.divergence_time),
+        ];
+        self.instance(
+            "Partition",
+            PARTITIONADDR,
+            user,
+            serde_json::json!([
+                field("challenger", self.challenger, "address"),
+                field("claimer", self.claimer, "address"),
+                field("queryArray", query_array, "uint256[]"),
+                field("submittedArray", submitted, "bool[]"),
+                field("hashArray", hashes, "bytes32[]"),
+                field("currentState", encode(p.state), "bytes32"),
+                field("uintValues", uint_values, "uint256[]"),
+            ]),
+        )
+    }
+
+    fn mm_instance(&self, m: &MMModel, user: Address) -> state::Instance {
+        self.instance(
+            "MM",
+            MMADDR,
+            user,
+            serde_json::json!([
+                field("provider", self.challenger, "address"),
+                field("initialHash", m.initial_hash, "bytes32"),
+                field("newHash", m.new_hash, "bytes32"),
+                field("historyLength", U256::from(m.history.len()), "uint256"),
+                field("currentState", encode(m.state), "bytes32"),
+            ]),
+        )
+    }
+
+    fn vg_instance(&self, vg: &VGModel, user: Address) -> state::Instance {
+        let deadline = match (vg.state, &self.partition) {
+            ("WaitPartition", Some(p)) => self.deadline(p.time_of_last_move),
+            _ => self.deadline(vg.time_of_last_move),
+        };
+        let uint_values = vec![
+            U256::from(self.compute.final_time),
+            deadline,
+            U256::from(0),
+            U256::from(0),
+            U256::from(vg.divergence_time),
+        ];
+        let mut instance = self.instance(
+            "VG",
+            VGADDR,
+            user,
+            serde_json::json!([
+                field("challenger", self.challenger, "address"),
+                field("claimer", self.claimer, "address"),
+                field("machine", hash_from_string::<H160>(MACHINEADDR), "address"),
+                field("initialHash", self.compute.initial_hash, "bytes32"),
+                field("claimerFinalHash", vg.claimer_final_hash, "bytes32"),
+                field("hashBeforeDivergence", vg.hash_before_divergence, "bytes32"),
+                field("hashAfterDivergence", vg.hash_after_divergence, "bytes32"),
+                field("currentState", encode(vg.state), "bytes32"),
+                field("uintValues", uint_values, "uint256[]"),
+            ]),
+        );
+        match vg.state {
+            "WaitPartition" => {
+                if let Some(ref p) = self.partition {
+                    instance.sub_instances = vec![Box::new(self.partition_instance(p, user))];
+                }
+            }
+            "WaitMemoryProveValues" => {
+                if let Some(ref m) = self.mm {
+                    instance.sub_instances = vec![Box::new(self.mm_instance(m, user))];
+                }
+            }
+            _ => {}
+        }
+        instance
+    }
+
+    /// The instance tree the dispatcher would hand to `user`
+    pub fn compute_instance(&self, user: Address) -> state::Instance {
+        let c = &self.compute;
+        let mut instance = self.instance(
+            "Compute",
+            COMPUTEADDR,
+            user,
+            serde_json::json!([
+                field("challenger", self.challenger, "address"),
+                field("claimer", self.claimer, "address"),
+                field("deadline", self.deadline(c.time_of_last_move), "uint256"),
+                field("machine", hash_from_string::<H160>(MACHINEADDR), "address"),
+                field("initialHash", c.initial_hash, "bytes32"),
+                field("finalTime", U256::from(c.final_time), "uint256"),
+                field("claimedFinalHash", c.claimed_final_hash, "bytes32"),
+                field("currentState", encode(c.state), "bytes32"),
+            ]),
+        );
+        instance.index = self.index;
+        if c.state == "WaitingChallenge" {
+            if let Some(ref vg) = self.vg {
+                instance.sub_instances = vec![Box::new(self.vg_instance(vg, user))];
+            }
+        }
+        instance
+    }
+
+    /// Apply a transaction sent by `sender`, or return why it reverts
+    pub fn apply(&mut self, sender: Address, request: &TransactionRequest) -> Revert {
+        let contract = request.concern.contract_address;
+        let function = request.function.as_str();
+        let data = &request.data;
+        if contract == hash_from_string::<H160>(COMPUTEADDR) {
+            self.apply_compute(sender, function, data)
+        } else if contract == hash_from_string::<H160>(VGADDR) {
+            self.apply_vg(sender, function)
+        } else if contract == hash_from_string::<H160>(PARTITIONADDR) {
+            self.apply_partition(sender, function, data)
+        } else if contract == hash_from_string::<H160>(MMADDR) {
+            self.apply_mm(sender, function, data)
+        } else {
+            Err(format!("unknown contract {:?}", contract))
+        }
+    }
+
+    fn apply_compute(&mut self, sender: Address, function: &str, data: &[Token]) -> Revert {
+        let now = self.now;
+        let after_deadline = self.after_deadline(self.compute.time_of_last_move);
+        match function {
+            "submitClaim" => {
+                require(sender == self.claimer, "onlyBy claimer")?;
+                require(self.compute.state == "WaitingClaim", "State should be WaitingClaim")?;
+                self.compute.claimed_final_hash = H256::from_slice(&fixed_bytes(data, 1)?);
+                self.compute.state = "WaitingConfirmation";
+            }
+            "confirm" => {
+                require(sender == self.challenger, "onlyBy challenger")?;
+                require(
+                    self.compute.state == "WaitingConfirmation",
+                    "State should be WaitingConfirmation",
+                )?;
+                self.compute.state = "ConsensusResult";
+            }
+            "challenge" => {
+                require(sender == self.challenger, "onlyBy challenger")?;
+                require(
+                    self.compute.state == "WaitingConfirmation",
+                    "State should be WaitingConfirmation",
+                )?;
+                self.vg = Some(VGModel {
+                    claimer_final_hash: self.compute.claimed_final_hash,
+                    hash_before_divergence: H256::zero(),
+                    hash_after_divergence: H256::zero(),
+                    divergence_time: 0,
+                    state: "WaitPartition",
+                    time_of_last_move: now,
+                });
+                self.partition = Some(PartitionModel::new(
+                    self.compute.initial_hash,
+                    self.compute.claimed_final_hash,
+                    self.compute.final_time,
+                    now,
+                ));
+                self.compute.state = "WaitingChallenge";
+            }
+            "winByVG" => {
+                require(
+                    self.compute.state == "WaitingChallenge",
+                    "State is not WaitingChallenge, cannot winByVG",
+                )?;
+                let vg_state = self.vg.as_ref().map(|vg| vg.state).unwrap_or("");
+                self.compute.state = match vg_state {
+                    "FinishedChallengerWon" => "ChallengerWon",
+                    "FinishedClaimerWon" => "ClaimerWon",
+                    _ => return Err("State of VG is not final".to_string()),
+                };
+            }
+            "claimVictoryByTime" => {
+                require(after_deadline, "Deadline is not over for this specific state")?;
+                self.compute.state = match self.compute.state {
+                    "WaitingClaim" if sender == self.challenger => "ClaimerMissedDeadline",
+                    "WaitingConfirmation" if sender == self.claimer => "ConsensusResult",
+                    _ => return Err("Fail to ClaimVictoryByTime in current condition".to_string()),
+                };
+            }
+            _ => return Err(format!("unknown Compute function {}", function)),
+        }
+        self.compute.time_of_last_move = now;
+        Ok(())
+    }
+
+    fn apply_vg(&mut self, sender: Address, function: &str) -> Revert {
+        let now = self.now;
+        let (claimer, challenger) = (self.claimer, self.challenger);
+        let after_deadline = match self.vg {
+            Some(ref vg) => self.after_deadline(vg.time_of_last_move),
+            None => return Err("VG not instantiated".to_string()),
+        };
+        let partition_state = self.partition.as_ref().map(|p| p.state).unwrap_or("");
+        let settlement = self.settlement();
+        let vg = self.vg.as_mut().unwrap();
+        match function {
+            "winByPartitionTimeout" => {
+                require(vg.state == "WaitPartition", "State should be WaitPartition")?;
+                vg.state = match partition_state {
+                    "ChallengerWon" => "FinishedChallengerWon",
+                    "ClaimerWon" => "FinishedClaimerWon",
+                    _ => {
+                        return Err(
+                            "Fail to WinByPartitionTimeout in current condition".to_string()
+                        )
+                    }
+                };
+            }
+            "startMachineRunChallenge" => {
+                require(vg.state == "WaitPartition", "State should be WaitPartition")?;
+                require(partition_state == "DivergenceFound", "Divergence should be found")?;
+                let p = self.partition.as_ref().unwrap();
+                vg.divergence_time = p.divergence_time;
+                vg.hash_before_divergence = p.time_hash[&p.divergence_time];
+                vg.hash_after_divergence = p.time_hash[&(p.divergence_time + 1)];
+                self.mm = Some(MMModel {
+                    initial_hash: vg.hash_before_divergence,
+                    new_hash: vg.hash_before_divergence,
+                    history: vec![],
+                    state: "WaitingProofs",
+                });
+                vg.state = "WaitMemoryProveValues";
+                vg.time_of_last_move = now;
+            }
+            "settleVerificationGame" => {
+                require(sender == challenger, "onlyBy challenger")?;
+                require(
+                    vg.state == "WaitMemoryProveValues",
+                    "State should be WaitMemoryProveValues",
+                )?;
+                let challenger_won = settlement?;
+                self.mm.as_mut().unwrap().state = "FinishedReplay";
+                vg.state = if challenger_won {
+                    "FinishedChallengerWon"
+                } else {
+                    "FinishedClaimerWon"
+                };
+            }
+            "claimVictoryByTime" => {
+                require(sender == claimer, "onlyBy claimer")?;
+                require(after_deadline, "Duration of WaitMemoryProveValues must be over")?;
+                require(
+                    vg.state == "WaitMemoryProveValues",
+                    "State should be WaitMemoryProveValues",
+                )?;
+                vg.state = "FinishedClaimerWon";
+            }
+            _ => return Err(format!("unknown VG function {}", function)),
+        }
+        Ok(())
+    }
+
+    /// Whether `settleVerificationGame` makes the challenger win: the step
+    /// must execute with the proven values and reach a different hash
+    fn settlement(&self) -> std::result::Result<bool, String> {
+        let (vg, mm) = match (&self.vg, &self.mm) {
+            (Some(vg), Some(mm)) => (vg, mm),
+            _ => return Err("MM not instantiated".to_string()),
+        };
+        require(mm.state == "WaitingReplay", "State of MM should be WaitingReplay")?;
+        let expected: Vec<(bool, u64, [u8; 8])> = self
+            .oracle
+            .step_at(&self.truth, vg.divergence_time)
+            .log
+            .into_iter()
+            .map(|access| match access.field_type {
+                AccessType::Read => (true, access.address, access.value_read),
+                AccessType::Write => (false, access.address, access.value_written),
+            })
+            .collect();
+        let step_ok = expected == mm.history;
+        Ok(step_ok && mm.new_hash != vg.hash_after_divergence)
+    }
+
+    fn apply_partition(&mut self, sender: Address, function: &str, data: &[Token]) -> Revert {
+        let now = self.now;
+        let (claimer, challenger) = (self.claimer, self.challenger);
+        let after_deadline = match self.partition {
+            Some(ref p) => self.after_deadline(p.time_of_last_move),
+            None => return Err("Partition not instantiated".to_string()),
+        };
+        let p = self.partition.as_mut().unwrap();
+        match function {
+            "replyQuery" => {
+                require(sender == claimer, "onlyBy claimer")?;
+                require(
+                    p.state == "WaitingHashes",
+                    "CurrentState is not WaitingHashes, cannot replyQuery",
+                )?;
+                let times = array(data, 1)?;
+                let hashes = array(data, 2)?;
+                require(times.len() == QUERY_SIZE, "postedTimes.length != querySize")?;
+                require(hashes.len() == QUERY_SIZE, "postedHashes.length != querySize")?;
+                for i in 0..QUERY_SIZE {
+                    let time = uint(&times, i)?;
+                    require(time == p.query_array[i], "postedTimes[i] != queryArray[i]")?;
+                    let hash = H256::from_slice(&fixed_bytes(&hashes, i)?);
+                    p.time_hash.entry(time).or_insert(hash);
+                }
+                p.state = "WaitingQuery";
+            }
+            "makeQuery" => {
+                require(sender == challenger, "onlyBy challenger")?;
+                require(
+                    p.state == "WaitingQuery",
+                    "CurrentState is not WaitingQuery, cannot makeQuery",
+                )?;
+                let piece = uint(data, 1)? as usize;
+                let left = uint(data, 2)?;
+                let right = uint(data, 3)?;
+                require(piece < QUERY_SIZE - 1, "queryPiece is bigger than querySize - 1")?;
+                require(left == p.query_array[piece], "leftPoint != queryArray[queryPiece]")?;
+                require(right == p.query_array[piece + 1], "rightPoint != queryArray[queryPiece]")?;
+                require(right - left > 1, "Interval is less than one")?;
+                p.slice(left, right);
+                p.state = "WaitingHashes";
+            }
+            "presentDivergence" => {
+                require(sender == challenger, "onlyBy challenger")?;
+                let time = uint(data, 1)?;
+                require(time < p.final_time, "divergence time has to be less than finalTime")?;
+                require(
+                    p.time_hash.contains_key(&time),
+                    "divergenceTime has to have been submitted",
+                )?;
+                require(
+                    p.time_hash.contains_key(&(time + 1)),
+                    "divergenceTime + 1 has to have been submitted",
+                )?;
+                p.divergence_time = time;
+                p.state = "DivergenceFound";
+            }
+            "claimVictoryByTime" => {
+                require(after_deadline, "Fail to ClaimVictoryByTime in current condition")?;
+                p.state = match p.state {
+                    "WaitingHashes" if sender == challenger => "ChallengerWon",
+                    "WaitingQuery" if sender == claimer => "ClaimerWon",
+                    _ => return Err("Fail to ClaimVictoryByTime in current condition".to_string()),
+                };
+            }
+            _ => return Err(format!("unknown Partition function {}", function)),
+        }
+        p.time_of_last_move = now;
+        Ok(())
+    }
+
+    fn apply_mm(&mut self, sender: Address, function: &str, data: &[Token]) -> Revert {
+        require(sender == self.challenger, "onlyBy provider")?;
+        let mm = match self.mm {
+            Some(ref mut mm) => mm,
+            None => return Err("MM not instantiated".to_string()),
+        };
+        require(
+            mm.state == "WaitingProofs",
+            "CurrentState is not WaitingProofs",
+        )?;
+        // Merkle.getRoot takes the siblings from the leaf up
+        let root = |position: u64, value: &[u8; 8], proof: &[Token]| {
+            let siblings = proof
+                .iter()
+                .rev()
+                .map(|t| match t {
+                    Token::FixedBytes(b) => Ok(H256::from_slice(b)),
+                    _ => Err("proof is not bytes32[]".to_string()),
+                })
+                .collect::<std::result::Result<Vec<H256>, String>>()?;
+            compute_root(
+                position,
+                LOG2_WORD_SIZE,
+                LOG2_MACHINE_SIZE,
+                &word_hash(value),
+                &siblings,
+            )
+            .map_err(|e| e.to_string())
+        };
+        match function {
+            "proveRead" => {
+                let position = uint(data, 1)?;
+                let value = word(data, 2)?;
+                let proof = array(data, 3)?;
+                require(root(position, &value, &proof)? == mm.new_hash, "Merkle proof does not match")?;
+                mm.history.push((true, position, value));
+            }
+            "proveWrite" => {
+                let position = uint(data, 1)?;
+                let old_value = word(data, 2)?;
+                let new_value = word(data, 3)?;
+                let proof = array(data, 4)?;
+                require(
+                    root(position, &old_value, &proof)? == mm.new_hash,
+                    "Merkle proof of write does not match",
+                )?;
+                mm.new_hash = root(position, &new_value, &proof)?;
+                mm.history.push((false, position, new_value));
+            }
+            "finishProofPhase" => {
+                mm.state = "WaitingReplay";
+            }
+            _ => return Err(format!("unknown MM function {}", function)),
+        }
+        Ok(())
+    }
+
+    fn params(&self, player: &Player) -> ComputeParams {
+        // the players see the simulated time as the latest block timestamp
+        let mut params = ComputeParams::with_block_timestamp(String::from(MACHINEID), self.now, 0);
+        if let Some(machine) = &player.machine {
+            params = params.with_machine(machine.clone());
+        }
+        if let Some(dir) = &player.forensic_dir {
+            params = params.with_forensic_dir(dir.clone());
+        }
+        if let Some(range) = player.output {
+            params = params.with_output(range);
+        }
+        if let Some(drive) = &player.input {
+            params = params.with_input_drive(drive.clone());
+        }
+        if player.prefetch {
+            params = params.with_prefetch();
+        }
+        params
+            .with_journal(player.journal.clone())
+            .with_run_progress(player.runs.clone())
+    }
+
+    /// Emulator session `player` plays this dispute with
+    fn session_id(&self, player: &Player) -> String {
+        let params = self.params(player);
+        let instance = self.compute_instance(player.address);
+        InstanceParams::new(&instance, &params).machine_id().clone()
+    }
+
+    fn react(&self, player: &mut Player) -> Result<Reaction> {
+        let instance = self.compute_instance(player.address);
+        let params = self.params(player);
+        player.mock.react_with(&mut player.archive, |archive| {
+            Compute::react(&instance, archive, &None, &params)
+        })
+    }
+
+    /// Let both players react until the dispute ends, returning the winner.
+    /// Simulated time only moves when nobody has anything to send.
+    pub fn run(&mut self, claimer: &mut Player, challenger: &mut Player) -> Result<Party> {
+        for _ in 0..MAX_ROUNDS {
+            if let Some(winner) = self.winner() {
+                return Ok(winner);
+            }
+            let mut moved = false;
+            for player in vec![&mut *claimer, &mut *challenger] {
+                if !player.online || self.winner().is_some() {
+                    continue;
+                }
+                if let Reaction::Transaction(request) = self.react(player)? {
+                    match self.apply(player.address, &request) {
+                        Ok(()) => moved = true,
+                        Err(e) => player.reverts.push(format!("{}: {}", request.function, e)),
+                    }
+                }
+            }
+            if !moved {
+                self.now += self.round_duration + 1;
+            }
+        }
+        Err(format!(
+            "dispute did not finish after {} rounds ({} seconds simulated)",
+            MAX_ROUNDS,
+            self.now - self.epoch
+        )
+        .into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use emulator_service::{SemanticVersion, EMULATOR_METHOD_READ};
+    use ArchiveKey;
+
+    const FINAL_TIME: u64 = 100;
+
+    fn play(claimer: ToyMachine, challenger: ToyMachine) -> (Party, Player, Player) {
+        let mut dispute = Dispute::new(ToyMachine::new(1), FINAL_TIME);
+        let mut claimer = Player::new(Party::Claimer, claimer);
+        let mut challenger = Player::new(Party::Challenger, challenger);
+        let winner = dispute.run(&mut claimer, &mut challenger).unwrap();
+        (winner, claimer, challenger)
+    }
+
+    #[test]
+    fn it_should_reach_consensus_between_honest_parties() {
+        let (winner, claimer, challenger) = play(ToyMachine::new(1), ToyMachine::new(1));
+        assert_eq!(winner, Party::Claimer);
+        assert!(claimer.reverts.is_empty());
+        assert!(challenger.reverts.is_empty());
+    }
+
+    #[test]
+    fn it_should_let_honest_challenger_win() {
+        for diverge_at in vec![0, 1, 37, FINAL_TIME - 2, FINAL_TIME - 1] {
+            let (winner, _, _) = play(
+                ToyMachine::new(1).diverging_at(diverge_at),
+                ToyMachine::new(1),
+            );
+            assert_eq!(winner, Party::Challenger, "divergence at {}", diverge_at);
+        }
+    }
+
+    #[test]
+    fn it_should_let_honest_claimer_win() {
+        for diverge_at in vec![0, 1, 37, FINAL_TIME - 2, FINAL_TIME - 1] {
+            let (winner, _, _) = play(
+                ToyMachine::new(1),
+                ToyMachine::new(1).diverging_at(diverge_at),
+            );
+            assert_eq!(winner, Party::Claimer, "divergence at {}", diverge_at);
+        }
+    }
+
+    #[test]
+    fn it_should_win_when_other_party_misses_deadlines() {
+        {
+            // claimer never submits a claim
+            let mut dispute = Dispute::new(ToyMachine::new(1), FINAL_TIME);
+            let mut claimer = Player::new(Party::Claimer, ToyMachine::new(1)).offline();
+            let mut challenger = Player::new(Party::Challenger, ToyMachine::new(1));
+            let winner = dispute.run(&mut claimer, &mut challenger).unwrap();
+            assert_eq!(winner, Party::Challenger);
+            assert_eq!(dispute.compute.state, "ClaimerMissedDeadline");
+        }
+        {
+            // challenger never answers the claim
+            let mut dispute = Dispute::new(ToyMachine::new(1), FINAL_TIME);
+            let mut claimer = Player::new(Party::Claimer, ToyMachine::new(1));
+            let mut challenger = Player::new(Party::Challenger, ToyMachine::new(1)).offline();
+            let winner = dispute.run(&mut claimer, &mut challenger).unwrap();
+            assert_eq!(winner, Party::Claimer);
+            assert_eq!(dispute.compute.state, "ConsensusResult");
+        }
+    }
+
+    #[test]
+    fn it_should_win_when_other_party_stops_mid_dispute() {
+        // a dishonest claimer that goes offline once challenged
+        let mut dispute = Dispute::new(ToyMachine::new(1), FINAL_TIME);
+        let mut claimer = Player::new(Party::Claimer, ToyMachine::new(1).diverging_at(10));
+        let mut challenger = Player::new(Party::Challenger, ToyMachine::new(1));
+        while dispute.compute.state != "WaitingChallenge" {
+            for player in vec![&mut claimer, &mut challenger] {
+                if let Reaction::Transaction(request) = dispute.react(player).unwrap() {
+                    dispute.apply(player.address, &request).unwrap();
+                }
+            }
+        }
+        claimer.online = false;
+        let winner = dispute.run(&mut claimer, &mut challenger).unwrap();
+        assert_eq!(winner, Party::Challenger);
+        assert_eq!(
+            dispute.vg.as_ref().unwrap().state,
+            "FinishedChallengerWon"
+        );
+    }
+
+    #[test]
+    fn it_should_open_and_end_managed_sessions() {
+        let mut dispute = Dispute::new(ToyMachine::new(1), FINAL_TIME);
+        let mut claimer =
+            Player::managing_session(Party::Claimer, ToyMachine::new(1).diverging_at(37));
+        let mut challenger = Player::managing_session(Party::Challenger, ToyMachine::new(1));
+        let session = dispute.session_id(&claimer);
+        assert_ne!(session, MACHINEID);
+        assert!(!claimer.mock.has_session(&session));
+        dispute.react(&mut claimer).unwrap();
+        assert!(claimer.mock.has_session(&session));
+
+        let winner = dispute.run(&mut claimer, &mut challenger).unwrap();
+        assert_eq!(winner, Party::Challenger);
+        assert!(challenger.mock.has_session(&dispute.session_id(&challenger)));
+        for player in vec![&mut claimer, &mut challenger] {
+            assert!(matches!(dispute.react(player).unwrap(), Reaction::Idle));
+            assert!(!player.mock.has_session(&dispute.session_id(player)));
+        }
+    }
+
+    #[test]
+    fn it_should_keep_sessions_of_instances_of_the_same_machine_apart() {
+        let mut first = Dispute::new(ToyMachine::new(1), FINAL_TIME);
+        let second = Dispute::new(ToyMachine::new(1), FINAL_TIME);
+        let mut claimer = Player::managing_session(Party::Claimer, ToyMachine::new(1));
+        let mut challenger = Player::new(Party::Challenger, ToyMachine::new(1));
+        assert_ne!(first.session_id(&claimer), second.session_id(&claimer));
+        second.react(&mut claimer).unwrap();
+
+        let winner = first.run(&mut claimer, &mut challenger).unwrap();
+        assert_eq!(winner, Party::Claimer);
+        first.react(&mut claimer).unwrap();
+        assert!(!claimer.mock.has_session(&first.session_id(&claimer)));
+        assert!(claimer.mock.has_session(&second.session_id(&claimer)));
+    }
+
+    /// A dispute in which `party` has to compute the final hash: the
+    /// claimer before claiming, the challenger before confirming
+    fn computing_dispute(party: Party) -> Dispute {
+        let mut dispute = Dispute::new(ToyMachine::new(1), FINAL_TIME);
+        if party == Party::Challenger {
+            let mut claimer = Player::new(Party::Claimer, ToyMachine::new(1));
+            if let Reaction::Transaction(request) = dispute.react(&mut claimer).unwrap() {
+                dispute.apply(claimer.address, &request).unwrap();
+            }
+            assert_eq!(dispute.compute.state, "WaitingConfirmation");
+        }
+        dispute
+    }
+
+    /// Take the emulator away from `player`: its machine manager neither
+    /// knows the disputed machine nor holds a session of it
+    fn without_emulator(player: &mut Player) {
+        player.mock = MockMachineManager::new(ToyMachine::new(2));
+        player.archive = Archive::new().unwrap();
+    }
+
+    /// Replace the machine manager of `player` by a version we refuse
+    fn with_incompatible_emulator(player: &mut Player) {
+        player.mock = MockMachineManager::new(ToyMachine::new(1))
+            .with_session(MACHINEID, ToyMachine::new(1))
+            .with_version(SemanticVersion::new(0, 9, 0));
+        player.archive = Archive::new().unwrap();
+    }
+
+    #[test]
+    fn it_should_refuse_to_play_another_machine() {
+        for mut player in vec![
+            Player::new(Party::Claimer, ToyMachine::new(2)),
+            Player::managing_session(Party::Challenger, ToyMachine::new(2)),
+        ] {
+            let dispute = computing_dispute(player.party);
+            let error = dispute.react(&mut player).unwrap_err();
+            let error = format!("{:?}", error);
+            assert!(error.contains("Refusing to play Compute"), "{}", error);
+            assert!(error.contains("InitialHashMismatch"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn it_should_refuse_incompatible_machine_managers() {
+        for mut player in vec![
+            Player::new(Party::Claimer, ToyMachine::new(1)),
+            Player::managing_session(Party::Challenger, ToyMachine::new(1)),
+        ] {
+            let dispute = computing_dispute(player.party);
+            player.mock = player.mock.with_version(SemanticVersion::new(0, 9, 0));
+            let error = format!("{:?}", dispute.react(&mut player).unwrap_err());
+            assert!(error.contains("IncompatibleEmulator"), "{}", error);
+            if player.machine.is_some() {
+                // failed before any session request
+                assert!(!player.mock.has_session(&dispute.session_id(&player)));
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_wait_on_the_other_party_without_the_emulator() {
+        let mut dispute = Dispute::new(ToyMachine::new(1), FINAL_TIME);
+        // neither a compatible machine manager nor the disputed machine
+        let mut challenger = Player::managing_session(Party::Challenger, ToyMachine::new(2));
+        challenger.mock = challenger.mock.with_version(SemanticVersion::new(0, 9, 0));
+        assert!(matches!(dispute.react(&mut challenger).unwrap(), Reaction::Idle));
+
+        dispute.now += dispute.round_duration + 1;
+        match dispute.react(&mut challenger).unwrap() {
+            Reaction::Transaction(request) => assert_eq!(request.function, "claimVictoryByTime"),
+            _ => panic!("the challenger should claim victory by time"),
+        }
+        assert!(!challenger.mock.has_session(&dispute.session_id(&challenger)));
+    }
+
+    #[test]
+    fn it_should_win_by_time_mid_game_without_a_usable_emulator() {
+        // the challenger stalls before querying, or in the MM
+        let stalls: Vec<fn(&Dispute) -> bool> = vec![
+            |d| d.partition.as_ref().map_or(false, |p| p.state == "WaitingQuery"),
+            |d| d.mm.is_some(),
+        ];
+        let takeaways: Vec<fn(&mut Player)> = vec![without_emulator, with_incompatible_emulator];
+        for stalled in &stalls {
+            for take_away in &takeaways {
+                let mut dispute = Dispute::new(ToyMachine::new(1), FINAL_TIME);
+                let mut claimer = Player::new(Party::Claimer, ToyMachine::new(1));
+                let mut challenger =
+                    Player::new(Party::Challenger, ToyMachine::new(1).diverging_at(37));
+                'playing: loop {
+                    for player in vec![&mut claimer, &mut challenger] {
+                        if stalled(&dispute) {
+                            break 'playing;
+                        }
+                        if let Reaction::Transaction(request) = dispute.react(player).unwrap() {
+                            dispute.apply(player.address, &request).unwrap();
+                        }
+                    }
+                }
+                challenger.online = false;
+                take_away(&mut claimer);
+
+                dispute.now += dispute.round_duration + 1;
+                let winner = dispute.run(&mut claimer, &mut challenger).unwrap();
+                assert_eq!(winner, Party::Claimer);
+                assert!(claimer.reverts.is_empty());
+                assert_eq!(dispute.vg.as_ref().unwrap().state, "FinishedClaimerWon");
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_let_claimer_win_when_challenger_stalls_in_mm() {
+        let mut dispute = Dispute::new(ToyMachine::new(1), FINAL_TIME);
+        let mut claimer = Player::new(Party::Claimer, ToyMachine::new(1));
+        let mut challenger = Player::new(Party::Challenger, ToyMachine::new(1).diverging_at(10));
+        while dispute.mm.is_none() {
+            for player in vec![&mut claimer, &mut challenger] {
+                if let Reaction::Transaction(request) = dispute.react(player).unwrap() {
+                    dispute.apply(player.address, &request).unwrap();
+                }
+            }
+        }
+        challenger.online = false;
+        let winner = dispute.run(&mut claimer, &mut challenger).unwrap();
+        assert_eq!(winner, Party::Claimer);
+        assert!(claimer.reverts.is_empty());
+        assert_eq!(dispute.vg.as_ref().unwrap().state, "FinishedClaimerWon");
+    }
+
+    #[test]
+    fn it_should_dump_lost_disputes_for_offline_replay() {
+        let dir = std::env::temp_dir().join(format!("forensic-{}", std::process::id()));
+        let mut dispute = Dispute::new(ToyMachine::new(1), FINAL_TIME);
+        let mut claimer =
+            Player::managing_session(Party::Claimer, ToyMachine::new(1).diverging_at(37));
+        claimer.forensic_dir = Some(dir.clone());
+        let range = OutputRange::new(COUNTER_ADDRESS, 4).unwrap();
+        claimer.output = Some(range);
+        let mut challenger = Player::new(Party::Challenger, ToyMachine::new(1));
+        let winner = dispute.run(&mut claimer, &mut challenger).unwrap();
+        assert_eq!(winner, Party::Challenger);
+
+        let paths: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        // the lost game is seen on every reaction, but dumped once
+        assert_eq!(paths.len(), 1, "{:?}", paths);
+        let json = std::fs::read_to_string(&paths[0]).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let bundle = ForensicBundle::from_json(&json).unwrap();
+        assert!(bundle.reason.contains("lost the verification game"), "{}", bundle.reason);
+        assert_eq!(bundle.instance.name, "Compute");
+        assert_eq!(bundle.instance.sub_instances[0].name, "VG");
+        // runs of cycle 0 and of the final time, the claimed output and
+        // the divergence step
+        assert!(bundle.responses.len() >= 5, "{:?}", bundle.responses);
+        assert_eq!(bundle.output, Some(range));
+        assert!(bundle.responses.iter().any(|r| r.method == EMULATOR_METHOD_READ));
+        // the claimer lost as its own machine says it should have won
+        assert!(matches!(bundle.replay().unwrap(), Reaction::Idle));
+    }
+
+    #[test]
+    fn it_should_expose_proven_output_of_settled_claims() {
+        let range = OutputRange::new(COUNTER_ADDRESS, 4).unwrap();
+        let mut dispute = Dispute::new(ToyMachine::new(1), FINAL_TIME);
+        let mut claimer = Player::new(Party::Claimer, ToyMachine::new(1));
+        let mut challenger = Player::new(Party::Challenger, ToyMachine::new(1));
+        claimer.output = Some(range);
+        challenger.output = Some(range);
+        let winner = dispute.run(&mut claimer, &mut challenger).unwrap();
+        assert_eq!(winner, Party::Claimer);
+        assert_eq!(dispute.compute.state, "ConsensusResult");
+
+        let mut data = FINAL_TIME.to_le_bytes().to_vec();
+        data.extend_from_slice(&1u64.to_le_bytes());
+        for player in vec![&claimer, &challenger] {
+            let pretty = Compute::get_pretty_instance(
+                &dispute.compute_instance(player.address),
+                &player.archive,
+                &dispute.params(player),
+            )
+            .unwrap();
+            let json: serde_json::Value = serde_json::from_str(&pretty.json_data).unwrap();
+            assert_eq!(json["output"]["data"], format!("0x{}", hex::encode(&data)));
+            assert_eq!(json["output"]["time"], FINAL_TIME);
+            assert_eq!(json["current_state"], "ConsensusResult");
+        }
+    }
+
+    #[test]
+    fn it_should_confirm_matching_hashes_whatever_the_output() {
+        let range = OutputRange::new(COUNTER_ADDRESS, 4).unwrap();
+        let mut dispute = Dispute::new(ToyMachine::new(1), FINAL_TIME);
+        let mut claimer = Player::new(Party::Claimer, ToyMachine::new(1));
+        let mut challenger = Player::new(Party::Challenger, ToyMachine::new(1));
+        challenger.output = Some(range);
+        // a proof of the output the challenger cannot make sense of
+        let key = ArchiveKey::Proof {
+            id: dispute.session_id(&challenger),
+            time: FINAL_TIME,
+            address: range.address,
+            log2_size: range.log2_size,
+        };
+        challenger.archive.insert_response(key.to_string(), Ok(vec![0xff]));
+        let winner = dispute.run(&mut claimer, &mut challenger).unwrap();
+        assert_eq!(winner, Party::Claimer);
+        assert_eq!(dispute.compute.state, "ConsensusResult");
+    }
+
+    #[test]
+    fn it_should_inject_input_drives_into_machine_templates() {
+        let input = b"input of this instance".to_vec();
+        let mut truth = ToyMachine::new(1);
+        truth.write(0x2000, &input).unwrap();
+        let drive = InputDrive::from_content(0x2000, 32, input).unwrap();
+
+        let mut dispute = Dispute::new(truth, FINAL_TIME);
+        let mut claimer = Player::managing_session(Party::Claimer, ToyMachine::new(1));
+        let mut challenger = Player::new(Party::Challenger, ToyMachine::new(1));
+        claimer.input = Some(drive.clone());
+        challenger.input = Some(drive);
+        let winner = dispute.run(&mut claimer, &mut challenger).unwrap();
+        assert_eq!(winner, Party::Claimer);
+        assert_eq!(dispute.compute.state, "ConsensusResult");
+        assert!(claimer.reverts.is_empty());
+        assert!(challenger.reverts.is_empty());
+
+        // another input of the template never shares the managed session
+        let mut other = Player::managing_session(Party::Claimer, ToyMachine::new(1));
+        other.input = Some(InputDrive::from_content(0x2000, 32, b"another input".to_vec()).unwrap());
+        assert_ne!(dispute.session_id(&other), dispute.session_id(&claimer));
+
+        // the template alone, or with another input, is another machine
+        for input in vec![None, Some(b"another input".to_vec())] {
+            let mut player = Player::new(Party::Claimer, ToyMachine::new(1));
+            player.input = input.map(|data| InputDrive::from_content(0x2000, 32, data).unwrap());
+            let dispute = Dispute::new(dispute.truth.clone(), FINAL_TIME);
+            let error = format!("{:?}", dispute.react(&mut player).unwrap_err());
+            assert!(error.contains("InitialHashMismatch"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn it_should_show_dispute_progress_in_pretty_instances() {
+        let mut dispute = Dispute::new(ToyMachine::new(1), FINAL_TIME);
+        let mut claimer = Player::new(Party::Claimer, ToyMachine::new(1).diverging_at(37));
+        let mut challenger = Player::new(Party::Challenger, ToyMachine::new(1));
+        while dispute.partition.is_none() {
+            for player in vec![&mut claimer, &mut challenger] {
+                if let Reaction::Transaction(request) = dispute.react(player).unwrap() {
+                    dispute.apply(player.address, &request).unwrap();
+                }
+            }
+        }
+
+        let pretty = Compute::get_pretty_instance(
+            &dispute.compute_instance(challenger.address),
+            &challenger.archive,
+            &dispute.params(&challenger),
+        )
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&pretty.json_data).unwrap();
+        assert_eq!(json["current_state"], "WaitingChallenge");
+        assert_eq!(json["progress"]["role"], "Challenger");
+        assert!(!json["progress"]["last_reaction"].is_null());
+
+        let vg = &pretty.sub_instances[0];
+        let partition = &vg.sub_instances[0];
+        let vg: serde_json::Value = serde_json::from_str(&vg.json_data).unwrap();
+        let partition: serde_json::Value = serde_json::from_str(&partition.json_data).unwrap();
+        let next = partition["progress"]["next_to_act"].clone();
+        assert_eq!(vg["progress"]["next_to_act"], next);
+        assert_eq!(json["progress"]["next_to_act"], next);
+        assert_eq!(json["progress"]["our_move"], next == "Challenger");
+        assert!(vg["progress"]["seconds_to_deadline"].as_i64().unwrap() > 0);
+        assert!(partition["progress"]["interval_width"].as_u64().unwrap() <= FINAL_TIME);
+        assert!(partition["progress"]["remaining_rounds"].as_u64().unwrap() >= 1);
+    }
+
+    #[test]
+    fn it_should_play_the_same_with_prefetched_runs() {
+        for (claimer, challenger, expected) in vec![
+            (ToyMachine::new(1).diverging_at(37), ToyMachine::new(1), Party::Challenger),
+            (ToyMachine::new(1), ToyMachine::new(1).diverging_at(37), Party::Claimer),
+        ] {
+            let mut dispute = Dispute::new(ToyMachine::new(1), FINAL_TIME);
+            let mut claimer = Player::new(Party::Claimer, claimer);
+            let mut challenger = Player::new(Party::Challenger, challenger);
+            claimer.prefetch = true;
+            challenger.prefetch = true;
+            let winner = dispute.run(&mut claimer, &mut challenger).unwrap();
+            assert_eq!(winner, expected);
+            assert!(claimer.reverts.is_empty());
+            assert!(challenger.reverts.is_empty());
+        }
+    }
+}
@@ -0,0 +1,146 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! States of the instantiator contracts, mirroring the `enum state`
+//! declarations of their Solidity interfaces.
+
+use super::error::*;
+
+use std::fmt;
+use std::str::FromStr;
+
+macro_rules! contract_state {
+    ($(#[$meta:meta])* $name:ident { $($variant:ident),+ $(,)* }) => {
+        $(#[$meta])*
+        #[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum $name {
+            $($variant),+
+        }
+
+        impl FromStr for $name {
+            type Err = Error;
+
+            /// Parse the `currentState` returned by `getState`; any name
+            /// missing from the contract enum is an `InvalidContractState`
+            fn from_str(s: &str) -> Result<$name> {
+                match s {
+                    $(stringify!($variant) => Ok($name::$variant),)+
+                    _ => Err(Error::from(ErrorKind::InvalidContractState(format!(
+                        "Unknown current state {}",
+                        s
+                    )))),
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match self {
+                    $($name::$variant => f.write_str(stringify!($variant)),)+
+                }
+            }
+        }
+    };
+}
+
+contract_state!(
+    /// `ComputeInterface.state`
+    ComputeState {
+        WaitingClaim,
+        WaitingConfirmation,
+        ClaimerMissedDeadline,
+        WaitingChallenge,
+        ChallengerWon,
+        ClaimerWon,
+        ConsensusResult,
+    }
+);
+
+contract_state!(
+    /// `VGInterface.state`
+    VGState {
+        WaitPartition,
+        WaitMemoryProveValues,
+        FinishedClaimerWon,
+        FinishedChallengerWon,
+    }
+);
+
+contract_state!(
+    /// `PartitionInterface.state`
+    PartitionState {
+        WaitingQuery,
+        WaitingHashes,
+        ChallengerWon,
+        ClaimerWon,
+        DivergenceFound,
+    }
+);
+
+contract_state!(
+    /// `MMInterface.state`
+    MMState {
+        WaitingProofs,
+        WaitingReplay,
+        FinishedReplay,
+    }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_round_trip_state_names() {
+        for state in &[
+            ComputeState::WaitingClaim,
+            ComputeState::ClaimerMissedDeadline,
+            ComputeState::ConsensusResult,
+        ] {
+            assert_eq!(state.to_string().parse::<ComputeState>().unwrap(), *state);
+        }
+        assert_eq!(
+            "WaitMemoryProveValues".parse::<VGState>().unwrap(),
+            VGState::WaitMemoryProveValues
+        );
+        assert_eq!(
+            "DivergenceFound".parse::<PartitionState>().unwrap(),
+            PartitionState::DivergenceFound
+        );
+        assert_eq!(
+            serde_json::to_string(&MMState::WaitingReplay).unwrap(),
+            "\"WaitingReplay\""
+        );
+    }
+
+    #[test]
+    fn it_should_reject_states_of_other_contracts() {
+        assert!("WaitingProofs".parse::<ComputeState>().is_err());
+        assert!("ChallengerWon".parse::<VGState>().is_err());
+        assert!("ChallengerWon".parse::<MMState>().is_err());
+        let error = "Unknown State".parse::<PartitionState>().unwrap_err();
+        assert!(format!("{:?}", error).contains("Unknown current state Unknown State"));
+    }
+}
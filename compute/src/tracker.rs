@@ -0,0 +1,139 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! Bounded maps of what the DApps remember across reactions, like the
+//! journal of every dispute we play. Long-running nodes play an unbounded
+//! number of disputes, so trackers only keep the most recently used
+//! entries.
+
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Map of at most `capacity` entries, the least recently used being
+/// dropped first
+#[derive(Debug)]
+pub struct Tracker<K, V> {
+    capacity: usize,
+    /// Incremented at every use, to tell the least recently used entry
+    uses: u64,
+    entries: BTreeMap<K, (u64, V)>,
+}
+
+impl<K: Ord + Clone, V> Tracker<K, V> {
+    pub const fn new(capacity: usize) -> Self {
+        Tracker {
+            capacity,
+            uses: 0,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Entry of `key`, inserted from `default` if missing, and marked as
+    /// the most recently used
+    pub fn entry<F>(&mut self, key: K, default: F) -> &mut V
+    where
+        F: FnOnce() -> V,
+    {
+        self.uses += 1;
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            let least_recent = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (used, _))| *used)
+                .map(|(k, _)| k.clone());
+            if let Some(k) = least_recent {
+                self.entries.remove(&k);
+            }
+        }
+        let uses = self.uses;
+        let entry = self.entries.entry(key).or_insert_with(|| (uses, default()));
+        entry.0 = uses;
+        &mut entry.1
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entries.get(key).map(|(_, value)| value)
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entries.remove(key).map(|(_, value)| value)
+    }
+
+    /// Entries in key order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(key, (_, value))| (key, value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Run `f` on the tracker behind `lock`. A panic while holding the lock
+/// cannot leave a tracker inconsistent, so a poisoned lock is recovered
+pub fn with<K, V, T, F>(lock: &Mutex<Tracker<K, V>>, f: F) -> T
+where
+    F: FnOnce(&mut Tracker<K, V>) -> T,
+{
+    let mut tracker = lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(&mut tracker)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_drop_least_recently_used_entries() {
+        let mut tracker = Tracker::new(2);
+        *tracker.entry(3, || 0) += 1;
+        *tracker.entry(1, || 0) += 1;
+        // using 3 again makes 1 the least recent, though its key is lower
+        *tracker.entry(3, || 0) += 1;
+        tracker.entry(2, || 0);
+        assert_eq!(tracker.len(), 2);
+        assert_eq!(tracker.get(&1), None);
+        assert_eq!(tracker.get(&3), Some(&2));
+        assert_eq!(
+            tracker.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(tracker.remove(&2), Some(0));
+        assert_eq!(tracker.len(), 1);
+    }
+}
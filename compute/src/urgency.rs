@@ -0,0 +1,121 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! Urgency of the transactions sent by the DApps, given how close the
+//! contract deadline is, and how it maps to transaction settings.
+
+use super::clock::Clock;
+use super::error::Result;
+use super::ethereum_types::U256;
+use super::transaction;
+
+/// Seconds before the deadline from which transactions are urgent
+pub const URGENT_SECONDS: u64 = 600;
+/// Seconds before the deadline from which transactions are critical
+pub const CRITICAL_SECONDS: u64 = 120;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Urgency {
+    Normal,
+    Urgent,
+    Critical,
+}
+
+impl Default for Urgency {
+    fn default() -> Self {
+        Urgency::Normal
+    }
+}
+
+impl Urgency {
+    /// Urgency of a transaction that must land before `deadline`
+    pub fn of(deadline: u64, clock: &dyn Clock) -> Result<Urgency> {
+        let left = deadline.saturating_sub(clock.now()?);
+        Ok(if left <= CRITICAL_SECONDS {
+            Urgency::Critical
+        } else if left <= URGENT_SECONDS {
+            Urgency::Urgent
+        } else {
+            Urgency::Normal
+        })
+    }
+}
+
+/// Gas and strategy of the transactions sent with a given urgency
+pub trait TransactionPolicy {
+    fn gas(&self, _urgency: Urgency) -> Option<U256> {
+        None
+    }
+
+    fn strategy(&self, _urgency: Urgency) -> transaction::Strategy {
+        transaction::Strategy::Simplest
+    }
+}
+
+/// Leaves gas and fees to the transaction manager whatever the urgency.
+/// Only a higher gas price gets an underpriced transaction mined before
+/// the deadline, and the requests of this dispatcher carry no gas price,
+/// while `Strategy::Simplest` is the only strategy its transaction
+/// manager implements. Nodes whose transaction manager can raise the fee
+/// should provide a policy picking its strategy as urgency grows
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefaultPolicy;
+
+impl TransactionPolicy for DefaultPolicy {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::BlockClock;
+
+    #[test]
+    fn it_should_escalate_as_deadline_nears() {
+        let deadline = 10_000;
+        let urgency = |timestamp| Urgency::of(deadline, &BlockClock::new(timestamp, 0)).unwrap();
+        assert_eq!(urgency(0), Urgency::Normal);
+        assert_eq!(urgency(deadline - URGENT_SECONDS - 1), Urgency::Normal);
+        assert_eq!(urgency(deadline - URGENT_SECONDS), Urgency::Urgent);
+        assert_eq!(urgency(deadline - CRITICAL_SECONDS - 1), Urgency::Urgent);
+        assert_eq!(urgency(deadline - CRITICAL_SECONDS), Urgency::Critical);
+        assert_eq!(urgency(deadline + 1), Urgency::Critical);
+    }
+
+    #[test]
+    fn it_should_let_policies_depend_on_urgency() {
+        struct Escalating;
+        impl TransactionPolicy for Escalating {
+            fn gas(&self, urgency: Urgency) -> Option<U256> {
+                match urgency {
+                    Urgency::Normal => None,
+                    _ => Some(U256::from(1_000_000)),
+                }
+            }
+        }
+        let policy: &dyn TransactionPolicy = &Escalating;
+        assert_eq!(policy.gas(Urgency::Normal), None);
+        assert_eq!(policy.gas(Urgency::Critical), Some(U256::from(1_000_000)));
+        assert_eq!(DefaultPolicy.gas(Urgency::Critical), None);
+    }
+}
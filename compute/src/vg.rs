@@ -31,91 +31,140 @@ use super::ethabi::Token;
 use super::ethereum_types::{Address, H256, U256};
 use super::transaction::TransactionRequest;
 use super::{Partition, Role, MM};
-use compute::win_by_deadline_or_idle;
-use mm::{MMCtx, MMCtxParsed, MMParams};
+use checked::CheckedConversion;
+use compute::{win_by_deadline_or_idle, DisputeParams};
+use forensic::parse_ctx;
+use mm::{get_step_log, proofs_agree_with, MMCtx, MMCtxParsed, MMParams};
 use partition::{PartitionCtx, PartitionCtxParsed};
+use pretty::{role_of, vg_next, Pretty, Progress};
+use replay::{predict_settlement, SettlementOutcome};
+use states::{MMState, PartitionState, VGState};
+use urgency::Urgency;
+
+use std::convert::TryFrom;
 
 pub struct VG();
 
-// !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
-// these two structs and the From trait below shuld be
-// obtained from a simple derive
-// !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
-#[derive(Serialize, Deserialize)]
-pub struct VGCtxParsed(
-    AddressField,  // challenger
-    AddressField,  // claimer
-    AddressField,  // machine
-    Bytes32Field,  // initialHash
-    Bytes32Field,  // claimedFinalHash
-    Bytes32Field,  // hashBeforeDivergence
-    Bytes32Field,  // hashAfterDivergence
-    String32Field, // currentState
-    U256Array,     // uint values: finalTime
-                   //              deadline
-                   //              timeOfLastMove
-                   //              mmInstance
-                   //              partitionInstance
-                   //              divergenceTime
-);
-
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, ContractCtx)]
+#[ctx(parsed = "VGCtxParsed")]
 pub struct VGCtx {
+    #[ctx(field = "AddressField")]
     pub challenger: Address,
+    #[ctx(field = "AddressField")]
     pub claimer: Address,
+    #[ctx(field = "AddressField")]
     pub machine: Address,
+    #[ctx(field = "Bytes32Field")]
     pub initial_hash: H256,
+    #[ctx(field = "Bytes32Field")]
     pub claimer_final_hash: H256,
+    #[ctx(field = "Bytes32Field")]
     pub hash_before_divergence: H256,
+    #[ctx(field = "Bytes32Field")]
     pub hash_after_divergence: H256,
-    pub current_state: String,
+    #[ctx(field = "String32Field", parse)]
+    pub current_state: VGState,
+    #[ctx(packed = "U256Array", index = 0)]
     pub final_time: U256,
+    #[ctx(packed = "U256Array", index = 1)]
     pub deadline: U256,
+    #[ctx(packed = "U256Array", index = 2)]
     pub mm_instance: U256,
+    #[ctx(packed = "U256Array", index = 3)]
     pub partition_instance: U256,
+    #[ctx(packed = "U256Array", index = 4)]
     pub divergence_time: U256,
 }
 
-impl From<VGCtxParsed> for VGCtx {
-    fn from(parsed: VGCtxParsed) -> VGCtx {
-        VGCtx {
-            challenger: parsed.0.value,
-            claimer: parsed.1.value,
-            machine: parsed.2.value,
-            initial_hash: parsed.3.value,
-            claimer_final_hash: parsed.4.value,
-            hash_before_divergence: parsed.5.value,
-            hash_after_divergence: parsed.6.value,
-            current_state: parsed.7.value,
-            final_time: parsed.8.value[0],
-            deadline: parsed.8.value[1],
-            mm_instance: parsed.8.value[2],
-            partition_instance: parsed.8.value[3],
-            divergence_time: parsed.8.value[4],
-        }
+/// Contract names under which the dispatcher lists the sub-instances
+const PARTITION_NAME: &str = "Partition";
+const MM_NAME: &str = "MM";
+
+/// Sub-instance of the VG running the `name` game at `index`. The
+/// dispatcher may list the partition and the MM in any order, or both, so
+/// both the index and the contract name must match. Unnamed sub-instances
+/// usually share index 0, so when several have the expected index, only
+/// those whose state `parses` as the expected context are kept
+fn sub_instance<'a>(
+    instance: &'a state::Instance,
+    name: &str,
+    index: U256,
+    parses: fn(&str) -> bool,
+) -> Result<&'a state::Instance> {
+    let mut candidates: Vec<&state::Instance> = instance
+        .sub_instances
+        .iter()
+        .map(|sub| sub.as_ref())
+        .filter(|sub| sub.index == index)
+        .filter(|sub| sub.name.is_empty() || sub.name.starts_with(name))
+        .collect();
+    if candidates.len() > 1 {
+        candidates.retain(|sub| parses(&sub.json_data));
     }
+    match candidates.as_slice() {
+        [sub] => Ok(*sub),
+        [] => Err(Error::from(ErrorKind::InvalidContractState(format!(
+            "There is no {} instance {} for VG (index: {})",
+            name, index, instance.index
+        )))),
+        _ => Err(Error::from(ErrorKind::InvalidContractState(format!(
+            "Ambiguous {} instance {} for VG (index: {})",
+            name, index, instance.index
+        )))),
+    }
+}
+
+/// Partition sub-instance of the VG at `index`
+fn partition_instance(instance: &state::Instance, index: U256) -> Result<&state::Instance> {
+    sub_instance(instance, PARTITION_NAME, index, |json_data| {
+        parse_ctx::<PartitionCtxParsed, PartitionCtx>(json_data).is_some()
+    })
+}
+
+/// Memory manager sub-instance of the VG at `index`
+fn mm_instance(instance: &state::Instance, index: U256) -> Result<&state::Instance> {
+    sub_instance(instance, MM_NAME, index, |json_data| {
+        parse_ctx::<MMCtxParsed, MMCtx>(json_data).is_some()
+    })
+}
+
+/// Party the verification game `instance` waits on, if its context and
+/// that of its partition can be parsed
+pub fn next_to_act(instance: &state::Instance) -> Option<Role> {
+    let parsed: VGCtxParsed = serde_json::from_str(&instance.json_data).ok()?;
+    let ctx = VGCtx::try_from(parsed).ok()?;
+    let partition = match ctx.current_state {
+        VGState::WaitPartition => {
+            let sub = partition_instance(instance, ctx.partition_instance).ok()?;
+            let parsed: PartitionCtxParsed = serde_json::from_str(&sub.json_data).ok()?;
+            Some(PartitionCtx::try_from(parsed).ok()?.current_state)
+        }
+        _ => None,
+    };
+    vg_next(ctx.current_state, partition)
 }
 
-impl DApp<String> for VG {
+impl<P: DisputeParams> DApp<P> for VG {
     fn react(
         instance: &state::Instance,
         archive: &Archive,
         _post_payload: &Option<String>,
-        machine_id: &String,
+        params: &P,
     ) -> Result<Reaction> {
+        let machine_id = params.machine_id();
         let parsed: VGCtxParsed = serde_json::from_str(&instance.json_data).chain_err(|| {
             format!(
                 "Could not parse vg instance json_data: {}",
                 &instance.json_data
             )
         })?;
-        let ctx: VGCtx = parsed.into();
+        let ctx = VGCtx::try_from(parsed)?;
         trace!("Context for vg (index {}) {:?}", instance.index, ctx);
 
         // should not happen as it indicates an innactive instance,
         // but it is possible that the blockchain state changed between queries
-        match ctx.current_state.as_ref() {
-            "FinishedClaimerWon" | "FinishedChallengerWon" => {
+        match ctx.current_state {
+            VGState::FinishedClaimerWon | VGState::FinishedChallengerWon => {
                 return Ok(Reaction::Idle);
             }
             _ => {}
@@ -133,16 +182,22 @@ impl DApp<String> for VG {
         };
         trace!("Role played (index {}) is: {:?}", instance.index, role);
 
+        // the closer the deadline, the more aggressive our transactions
+        let deadline = ctx.deadline.to_u64("VG deadline")?;
+        let urgency = Urgency::of(deadline, params.clock())?;
+        let policy = params.transaction_policy();
+        if urgency != Urgency::Normal {
+            debug!(
+                "{:?} deadline for VG (index {}): {}",
+                urgency, instance.index, ctx.deadline
+            );
+        }
+
         match role {
-            Role::Claimer => match ctx.current_state.as_ref() {
-                "WaitPartition" => {
+            Role::Claimer => match ctx.current_state {
+                VGState::WaitPartition => {
                     // get the partition instance to see if its is finished
-                    let partition_instance = instance.sub_instances.get(0).ok_or(Error::from(
-                        ErrorKind::InvalidContractState(format!(
-                            "There is no partition instance {}",
-                            ctx.current_state
-                        )),
-                    ))?;
+                    let partition_instance = partition_instance(instance, ctx.partition_instance)?;
 
                     let partition_parsed: PartitionCtxParsed =
                         serde_json::from_str(&partition_instance.json_data).chain_err(|| {
@@ -151,10 +206,10 @@ impl DApp<String> for VG {
                                 &instance.json_data
                             )
                         })?;
-                    let partition_ctx: PartitionCtx = partition_parsed.into();
+                    let partition_ctx = PartitionCtx::try_from(partition_parsed)?;
 
-                    match partition_ctx.current_state.as_ref() {
-                        "ClaimerWon" => {
+                    match partition_ctx.current_state {
+                        PartitionState::ClaimerWon => {
                             // claim victory by partition timeout
                             info!(
                                 "Claiming victory by Partition timeout (index: {})",
@@ -171,12 +226,12 @@ impl DApp<String> for VG {
                                 // them inside the transaction manager
                                 // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
                                 data: vec![Token::Uint(instance.index)],
-                                gas: None,
-                                strategy: transaction::Strategy::Simplest,
+                                gas: policy.gas(urgency),
+                                strategy: policy.strategy(urgency),
                             };
                             return Ok(Reaction::Transaction(request));
                         }
-                        "DivergenceFound" => {
+                        PartitionState::DivergenceFound => {
                             // start the machine run challenge
                             info!(
                                 "Starting machine run challenage for VG (index: {})",
@@ -193,50 +248,89 @@ impl DApp<String> for VG {
                                 // them inside the transaction manager
                                 // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
                                 data: vec![Token::Uint(instance.index)],
-                                gas: None,
-                                strategy: transaction::Strategy::Simplest,
+                                gas: policy.gas(urgency),
+                                strategy: policy.strategy(urgency),
                             };
                             return Ok(Reaction::Transaction(request));
                         }
-                        _ => {
+                        PartitionState::WaitingQuery
+                        | PartitionState::WaitingHashes
+                        | PartitionState::ChallengerWon => {
                             // partition is still running,
                             // pass control to the partition dapp
                             return Partition::react(
                                 partition_instance,
                                 archive,
                                 &None,
-                                &machine_id,
+                                params,
                             );
                         }
                     }
                 }
-                "WaitMemoryProveValues" => {
-                    return win_by_deadline_or_idle(
+                VGState::WaitMemoryProveValues => {
+                    // a challenger that stalls loses by time
+                    let reaction = win_by_deadline_or_idle(
                         &instance.concern,
                         instance.index,
-                        ctx.deadline.as_u64(),
-                    );
+                        deadline,
+                        params,
+                    )?;
+                    if let Reaction::Transaction(_) = reaction {
+                        return Ok(reaction);
+                    }
+
+                    // only the challenger may settle the game, so the
+                    // claimer follows the proofs against its own step
+                    let mm_instance = mm_instance(instance, ctx.mm_instance)?;
+                    let mm_parsed: MMCtxParsed = serde_json::from_str(&mm_instance.json_data)
+                        .chain_err(|| {
+                            format!(
+                                "Could not parse mm instance json_data: {}",
+                                &instance.json_data
+                            )
+                        })?;
+                    let mm_ctx = MMCtx::try_from(mm_parsed)?;
+                    params.prepare_session(archive)?;
+                    let step_log = get_step_log(archive, "VG", machine_id, ctx.divergence_time)?;
+
+                    if !proofs_agree_with(&mm_ctx, &step_log) {
+                        warn!(
+                            "Challenger proved values in MM (index: {}) that differ from our step at time {}, the replay should fail",
+                            mm_instance.index, ctx.divergence_time
+                        );
+                    } else if mm_ctx.current_state == MMState::WaitingReplay {
+                        let outcome = predict_settlement(
+                            ctx.hash_before_divergence,
+                            ctx.hash_after_divergence,
+                            &step_log,
+                        )
+                        .chain_err(|| {
+                            format!(
+                                "Invalid proof from emulator in step log of machine {} at time {}",
+                                machine_id, ctx.divergence_time
+                            )
+                        })?;
+                        if outcome == SettlementOutcome::ChallengerWins {
+                            warn!(
+                                "Settling VG (index: {}) is predicted to make the claimer lose",
+                                instance.index
+                            );
+                        }
+                    }
+                    return Ok(Reaction::Idle);
                 }
-                _ => {
-                    return Err(Error::from(ErrorKind::InvalidContractState(format!(
-                        "Unknown current state {}",
-                        ctx.current_state
-                    ))));
+                VGState::FinishedClaimerWon | VGState::FinishedChallengerWon => {
+                    return Ok(Reaction::Idle);
                 }
             },
-            Role::Challenger => match ctx.current_state.as_ref() {
-                "WaitPartition" => {
+            Role::Challenger => match ctx.current_state {
+                VGState::WaitPartition => {
                     // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
                     // deduplicate code with wait partition above
                     // not quite the same
                     // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
                     // get the partition instance to see if its is finished
-                    let partition_instance = instance.sub_instances.get(0).ok_or(Error::from(
-                        ErrorKind::InvalidContractState(format!(
-                            "There is no partition instance {}",
-                            ctx.current_state
-                        )),
-                    ))?;
+                    let partition_instance = partition_instance(instance, ctx.partition_instance)?;
 
                     let partition_parsed: PartitionCtxParsed =
                         serde_json::from_str(&partition_instance.json_data).chain_err(|| {
@@ -245,10 +339,10 @@ impl DApp<String> for VG {
                                 &instance.json_data
                             )
                         })?;
-                    let partition_ctx: PartitionCtx = partition_parsed.into();
+                    let partition_ctx = PartitionCtx::try_from(partition_parsed)?;
 
-                    match partition_ctx.current_state.as_ref() {
-                        "ChallengerWon" => {
+                    match partition_ctx.current_state {
+                        PartitionState::ChallengerWon => {
                             // claim victory by partition timeout
                             info!(
                                 "Claiming victory by Partition timeout (index: {})",
@@ -265,12 +359,12 @@ impl DApp<String> for VG {
                                 // them inside the transaction manager
                                 // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
                                 data: vec![Token::Uint(instance.index)],
-                                gas: None,
-                                strategy: transaction::Strategy::Simplest,
+                                gas: policy.gas(urgency),
+                                strategy: policy.strategy(urgency),
                             };
                             return Ok(Reaction::Transaction(request));
                         }
-                        "DivergenceFound" => {
+                        PartitionState::DivergenceFound => {
                             // start the machine run challenge
                             info!(
                                 "Starting machine run challenage for VG (index: {})",
@@ -287,30 +381,27 @@ impl DApp<String> for VG {
                                 // them inside the transaction manager
                                 // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
                                 data: vec![Token::Uint(instance.index)],
-                                gas: None,
-                                strategy: transaction::Strategy::Simplest,
+                                gas: policy.gas(urgency),
+                                strategy: policy.strategy(urgency),
                             };
                             return Ok(Reaction::Transaction(request));
                         }
-                        _ => {
+                        PartitionState::WaitingQuery
+                        | PartitionState::WaitingHashes
+                        | PartitionState::ClaimerWon => {
                             // partition is still running,
                             // pass control to the partition dapp
                             return Partition::react(
                                 partition_instance,
                                 archive,
                                 &None,
-                                machine_id,
+                                params,
                             );
                         }
                     }
                 }
-                "WaitMemoryProveValues" => {
-                    let mm_instance = instance.sub_instances.get(0).ok_or(Error::from(
-                        ErrorKind::InvalidContractState(format!(
-                            "There is no memory manager instance {}",
-                            ctx.current_state
-                        )),
-                    ))?;
+                VGState::WaitMemoryProveValues => {
+                    let mm_instance = mm_instance(instance, ctx.mm_instance)?;
 
                     let mm_parsed: MMCtxParsed = serde_json::from_str(&mm_instance.json_data)
                         .chain_err(|| {
@@ -319,18 +410,36 @@ impl DApp<String> for VG {
                                 &instance.json_data
                             )
                         })?;
-                    let mm_ctx: MMCtx = mm_parsed.into();
+                    let mm_ctx = MMCtx::try_from(mm_parsed)?;
 
-                    match mm_ctx.current_state.as_ref() {
-                        "WaitingProofs" => {
+                    match mm_ctx.current_state {
+                        MMState::WaitingProofs => {
+                            // the proofs come from our step
+                            params.prepare_session(archive)?;
                             let params = MMParams {
                                 divergence_time: ctx.divergence_time,
                                 machine_id: machine_id.clone(),
+                                hash_after_divergence: ctx.hash_after_divergence,
+                                urgency,
+                                policy,
                             };
                             return MM::react(mm_instance, archive, &None, &params);
                         }
-                        "WaitingReplay" => {
-                            // start the machine run challenge
+                        MMState::WaitingReplay => {
+                            // all proofs are in, the new hash decides the game
+                            let outcome = SettlementOutcome::from_hashes(
+                                &mm_ctx.final_hash,
+                                &ctx.hash_after_divergence,
+                            );
+                            if outcome != SettlementOutcome::ChallengerWins {
+                                // settling would only hand the claimer its
+                                // victory, which it can claim by time
+                                warn!(
+                                    "Not settling VG (index: {}), as it is predicted to end with {:?}",
+                                    instance.index, outcome
+                                );
+                                return Ok(Reaction::Idle);
+                            }
                             info!("Settling VG (index: {})", instance.index);
                             let request = TransactionRequest {
                                 contract_name: None, // Name not needed, is concern
@@ -343,26 +452,19 @@ impl DApp<String> for VG {
                                 // them inside the transaction manager
                                 // !!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!
                                 data: vec![Token::Uint(instance.index)],
-                                gas: None,
-                                strategy: transaction::Strategy::Simplest,
+                                gas: policy.gas(urgency),
+                                strategy: policy.strategy(urgency),
                             };
                             return Ok(Reaction::Transaction(request));
                         }
-                        "FinishedReplay" => {
+                        MMState::FinishedReplay => {
                             warn!("Strange state for vg and mm");
                             return Ok(Reaction::Idle);
                         }
-                        _ => {
-                            warn!("Unknown state for vg and mm");
-                            return Ok(Reaction::Idle);
-                        }
                     }
                 }
-                _ => {
-                    return Err(Error::from(ErrorKind::InvalidContractState(format!(
-                        "Unknown current state {}",
-                        ctx.current_state
-                    ))));
+                VGState::FinishedClaimerWon | VGState::FinishedChallengerWon => {
+                    return Ok(Reaction::Idle);
                 }
             },
         }
@@ -371,8 +473,9 @@ impl DApp<String> for VG {
     fn get_pretty_instance(
         instance: &state::Instance,
         archive: &Archive,
-        machine_id: &String,
+        params: &P,
     ) -> Result<state::Instance> {
+        let machine_id = params.machine_id();
         // get context (state) of the vg instance
         let parsed: VGCtxParsed = serde_json::from_str(&instance.json_data).chain_err(|| {
             format!(
@@ -380,33 +483,44 @@ impl DApp<String> for VG {
                 &instance.json_data
             )
         })?;
-        let ctx: VGCtx = parsed.into();
-        let json_data = serde_json::to_string(&ctx).unwrap();
+        let ctx = VGCtx::try_from(parsed)?;
+        let progress = Progress::new(
+            role_of(instance.concern.user_address, ctx.claimer, ctx.challenger),
+            next_to_act(instance),
+        )
+        .with_deadline(ctx.deadline, params.clock());
+        let urgency = progress.urgency.unwrap_or_default();
+        let json_data = serde_json::to_string(&Pretty {
+            ctx: &ctx,
+            progress,
+        })
+        .unwrap();
 
         // get context (state) of the sub instances
 
         let mut pretty_sub_instances: Vec<Box<state::Instance>> = vec![];
 
-        match ctx.current_state.as_ref() {
-            "WaitPartition" => {
-                for sub in &instance.sub_instances {
-                    pretty_sub_instances.push(Box::new(
-                        Partition::get_pretty_instance(sub, archive, machine_id).unwrap(),
-                    ))
-                }
+        match ctx.current_state {
+            VGState::WaitPartition => {
+                let sub = partition_instance(instance, ctx.partition_instance)?;
+                pretty_sub_instances.push(Box::new(Partition::get_pretty_instance(
+                    sub, archive, params,
+                )?))
             }
-            "WaitMemoryProveValues" => {
+            VGState::WaitMemoryProveValues => {
                 let params = MMParams {
                     divergence_time: ctx.divergence_time,
                     machine_id: machine_id.clone(),
+                    hash_after_divergence: ctx.hash_after_divergence,
+                    urgency,
+                    ..Default::default()
                 };
-                for sub in &instance.sub_instances {
-                    pretty_sub_instances.push(Box::new(
-                        MM::get_pretty_instance(sub, archive, &params).unwrap(),
-                    ))
-                }
+                let sub = mm_instance(instance, ctx.mm_instance)?;
+                pretty_sub_instances.push(Box::new(MM::get_pretty_instance(
+                    sub, archive, &params,
+                )?))
             }
-            _ => {}
+            VGState::FinishedClaimerWon | VGState::FinishedChallengerWon => {}
         }
 
         let pretty_instance = state::Instance {
@@ -424,8 +538,11 @@ impl DApp<String> for VG {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use emulator_service;
+    use merkle;
     use mm;
     use partition;
+    use {ArchiveKey, SessionStepResponse};
     use tests::{
         build_concern, build_service_status, build_state, encode, CHALLENGERADDR, CLAIMERADDR,
         MACHINEADDR, MACHINEID, UNKNOWNSTATE,
@@ -506,7 +623,7 @@ mod tests {
     #[test]
     fn it_should_call_win_by_deadline_as_claimer() {
         let current_state = encode("WaitMemoryProveValues");
-        let archive = Archive::new().unwrap();
+        let mut archive = Archive::new().unwrap();
         let concern = build_concern(CLAIMERADDR);
 
         let mut state_instance = build_state(concern, None);
@@ -527,10 +644,38 @@ mod tests {
             }
         }
         {
-            // Idle
+            // Idle, following the challenger's proofs
             let deadline = "0x1fffffffffffff";
             state_instance.json_data =
                 build_vg_state_json_data(current_state.as_str(), Option::from(deadline));
+            let proof = merkle::tests::build_word_proof(0x100, [0; 8]);
+            let mut mm_instance = build_state(concern, None);
+            mm_instance.json_data = mm::tests::build_mm_state_json_data_with_hash(
+                encode("WaitingReplay").as_str(),
+                Some("0x1"),
+                &format!("{:?}", proof.root_hash),
+            );
+            state_instance.sub_instances = vec![Box::from(mm_instance)];
+
+            // our own step is needed first
+            let result = VG::react(&state_instance, &archive, &None, &String::from(MACHINEID));
+            assert!(format!("{:?}", result.unwrap_err()).contains("ResponseMissError"));
+
+            let bin: Vec<u8> = SessionStepResponse {
+                log: vec![emulator_service::Access {
+                    field_type: emulator_service::AccessType::Read,
+                    address: 0x100,
+                    value_read: [0; 8],
+                    value_written: [0; 8],
+                    proof,
+                }],
+            }
+            .into();
+            let key = ArchiveKey::Step {
+                id: String::from(MACHINEID),
+                time: 0,
+            };
+            archive.insert_response(key.to_string(), Ok(bin));
             let result = VG::react(&state_instance, &archive, &None, &String::from(MACHINEID));
             assert!(matches!(result.unwrap(), Reaction::Idle));
         }
@@ -733,7 +878,10 @@ mod tests {
                 mm::tests::build_mm_state_json_data(current_state_mm.as_str(), None);
             state_instance.sub_instances = vec![Box::from(mm_instance.clone())];
             let result = VG::react(&state_instance, &archive, &None, &String::from(MACHINEID));
-            assert!(matches!(result.unwrap(), Reaction::Idle));
+            match result {
+                Err(e) => assert!(format!("{:?}", e).contains("Unknown current state Unknown State")),
+                Ok(_) => panic!("Unknown mm state should be an error"),
+            }
         }
         {
             //WaitingProofs
@@ -745,6 +893,135 @@ mod tests {
                 VG::react(&state_instance, &archive, &None, &String::from(MACHINEID)).unwrap();
         }
     }
+    #[test]
+    fn it_should_not_settle_games_predicted_to_be_lost_as_challenger() {
+        let archive = Archive::new().unwrap();
+        let concern = build_concern(CHALLENGERADDR);
+        let mut mm_data: serde_json::Value = serde_json::from_str(
+            &mm::tests::build_mm_state_json_data(encode("WaitingReplay").as_str(), None),
+        )
+        .unwrap();
+        // the proofs reach the hash the claimer committed to
+        mm_data[2]["value"] = serde_json::json!(
+            "0xa70817cd86277772e8f71cfe28d32da866b05f981d80e4d17eae915321930003"
+        );
+        let mm_instance = build_state(concern, Some(mm_data.to_string()));
+
+        let mut state_instance = build_state(concern, None);
+        state_instance.json_data =
+            build_vg_state_json_data(encode("WaitMemoryProveValues").as_str(), None);
+        state_instance.sub_instances = vec![Box::from(mm_instance)];
+        let result = VG::react(&state_instance, &archive, &None, &String::from(MACHINEID));
+        assert!(matches!(result.unwrap(), Reaction::Idle));
+    }
+
+    #[test]
+    fn it_should_select_sub_instances_by_name_and_index() {
+        let concern = build_concern(CHALLENGERADDR);
+        let archive = Archive::new().unwrap();
+        let build_vg = |current_state: &str| {
+            let mut data: serde_json::Value = serde_json::from_str(&build_vg_state_json_data(
+                encode(current_state).as_str(),
+                Some("0x1fffffffffffff"),
+            ))
+            .unwrap();
+            // mm instance 3, partition instance 5
+            data[8]["value"][2] = serde_json::json!("0x3");
+            data[8]["value"][3] = serde_json::json!("0x5");
+            data.to_string()
+        };
+        let sub = |name: &str, index: u64, json_data: String| {
+            let mut sub = build_state(concern, Some(json_data));
+            sub.name = name.to_string();
+            sub.index = U256::from(index);
+            Box::new(sub)
+        };
+        let mm_json = mm::tests::build_mm_state_json_data(encode("WaitingReplay").as_str(), None);
+        let partition_json = partition::tests::build_partition_state_json_data(
+            encode("DivergenceFound").as_str(),
+            None,
+            None,
+            None,
+            None,
+        );
+
+        let mut state_instance = build_state(concern, None);
+        state_instance.sub_instances = vec![
+            sub("MM", 5, mm_json.clone()),
+            sub("MM", 3, mm_json.clone()),
+            sub("Partition", 3, partition_json.clone()),
+            sub("Partition", 5, partition_json.clone()),
+        ];
+        let function = |state_instance: &state::Instance| {
+            match VG::react(state_instance, &archive, &None, &String::from(MACHINEID)).unwrap() {
+                Reaction::Transaction(transaction) => transaction.function,
+                _ => panic!("Only transaction"),
+            }
+        };
+
+        state_instance.json_data = build_vg("WaitPartition");
+        assert_eq!(function(&state_instance), "startMachineRunChallenge");
+        state_instance.json_data = build_vg("WaitMemoryProveValues");
+        assert_eq!(function(&state_instance), "settleVerificationGame");
+
+        // unnamed sub-instances are told apart by index, and by their
+        // state when they share it
+        state_instance.sub_instances = vec![
+            sub("", 3, mm_json.clone()),
+            sub("", 5, partition_json.clone()),
+        ];
+        assert_eq!(function(&state_instance), "settleVerificationGame");
+        let build_vg_at_zero = |current_state: &str| {
+            let mut data: serde_json::Value =
+                serde_json::from_str(&build_vg(current_state)).unwrap();
+            data[8]["value"][2] = serde_json::json!("0x0");
+            data[8]["value"][3] = serde_json::json!("0x0");
+            data.to_string()
+        };
+        state_instance.sub_instances = vec![
+            sub("", 0, mm_json.clone()),
+            sub("", 0, partition_json.clone()),
+        ];
+        state_instance.json_data = build_vg_at_zero("WaitPartition");
+        assert_eq!(function(&state_instance), "startMachineRunChallenge");
+        state_instance.json_data = build_vg_at_zero("WaitMemoryProveValues");
+        assert_eq!(function(&state_instance), "settleVerificationGame");
+        state_instance.sub_instances = vec![sub("", 5, partition_json)];
+        let result = VG::react(&state_instance, &archive, &None, &String::from(MACHINEID));
+        assert!(format!("{:?}", result.unwrap_err()).contains("There is no MM instance 3"));
+    }
+
+    #[test]
+    fn it_should_reject_short_uint_values() {
+        let mut data: serde_json::Value = serde_json::from_str(&build_vg_state_json_data(
+            encode("WaitPartition").as_str(),
+            None,
+        ))
+        .unwrap();
+        data[8]["value"] = serde_json::json!(["0x0", "0x0", "0x0"]);
+        let mut instance = build_state(build_concern(CLAIMERADDR), None);
+        instance.json_data = serde_json::to_string(&data).unwrap();
+
+        let error = VG::react(&instance, &Archive::new().unwrap(), &None, &String::from(MACHINEID))
+            .unwrap_err();
+        let error = format!("{:?}", error);
+        assert!(error.contains("VGCtxParsed has no partition_instance (element 3)"), "{}", error);
+    }
+
+    #[test]
+    fn it_should_reject_deadline_beyond_64_bits() {
+        let mut instance = build_state(build_concern(CLAIMERADDR), None);
+        instance.json_data = build_vg_state_json_data(
+            encode("WaitPartition").as_str(),
+            Some("0x10000000000000000"),
+        );
+
+        let error = VG::react(&instance, &Archive::new().unwrap(), &None, &String::from(MACHINEID))
+            .unwrap_err();
+        let error = format!("{:?}", error);
+        assert!(error.contains("VG deadline 18446744073709551616 is out of range"), "{}", error);
+    }
+
     #[test]
     fn it_should_call_get_pretty_instance() {
         let current_state = encode("WaitPartition");
@@ -798,7 +1075,7 @@ mod tests {
         {
             // WaitMemoryProveValues
             let current_state = encode("WaitMemoryProveValues");
-            let current_state_mm = encode("ChallengerWon");
+            let current_state_mm = encode("WaitingReplay");
             let mut mm_instance = build_state(concern, None);
             mm_instance.json_data =
                 mm::tests::build_mm_state_json_data(current_state_mm.as_str(), None);
@@ -826,7 +1103,7 @@ mod tests {
                 serde_json::from_str(&pretty_sub.json_data).unwrap();
             assert!(pretty_sub_json.is_object());
             assert_eq!(
-                serde_json::json!("ChallengerWon"),
+                serde_json::json!("WaitingReplay"),
                 pretty_sub_json["current_state"]
             );
             assert_eq!(serde_json::json!("0x0"), pretty_sub_json["history_length"]);
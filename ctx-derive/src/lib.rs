@@ -0,0 +1,246 @@
+// Arbitration DLib is the combination of the on-chain protocol and off-chain
+// protocol that work together to resolve any disputes that might occur during the
+// execution of a Cartesi DApp.
+
+// Copyright (C) 2019 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! `#[derive(ContractCtx)]` generates, from a DApp context with named
+//! fields, the tuple struct the dispatcher json of the contract `getState`
+//! is parsed into, and the fallible conversion from it.
+//!
+//! The context lists its fields in the order `getState` returns them:
+//!
+//! ```ignore
+//! #[derive(Serialize, Debug, ContractCtx)]
+//! #[ctx(parsed = "PartitionCtxParsed")]
+//! pub struct PartitionCtx {
+//!     #[ctx(field = "AddressField")]
+//!     pub challenger: Address,
+//!     #[ctx(field = "String32Field", parse)]
+//!     pub current_state: PartitionState,
+//!     #[ctx(packed = "U256Array", index = 0)]
+//!     pub final_time: U256,
+//!     #[ctx(packed = "U256Array", index = 1)]
+//!     pub query_size: U256,
+//! }
+//! ```
+//!
+//! * `field` fields take the `value` of their own element of type `field`;
+//!   with `parse` the value is parsed with `FromStr`.
+//! * `packed` fields take the element `index` of the `value` array of an
+//!   element of type `packed`. Index 0 starts a new element, and the
+//!   following indices must come right after it. An array too short for
+//!   the context is an `InvalidContractState` error, never a panic.
+//!
+//! The generated code refers to `Error`, `ErrorKind` and `Result` as the
+//! DApp modules import them from the `error` crate.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+#[macro_use]
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{Attribute, Data, DeriveInput, Fields, Ident, Index, Lit, Meta, NestedMeta, Type};
+
+#[proc_macro_derive(ContractCtx, attributes(ctx))]
+pub fn derive_contract_ctx(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// How a context field is read from the parsed tuple
+enum Source {
+    Field { ty: Type, parse: bool },
+    Packed { ty: Type, index: usize },
+}
+
+/// Element of the parsed tuple
+struct Element {
+    ty: Type,
+    /// Context fields read from the element
+    fields: Vec<String>,
+    /// Last index read, for packed elements
+    last_index: Option<usize>,
+}
+
+/// Arguments of the `#[ctx(...)]` attributes among `attrs`
+fn ctx_args(attrs: &[Attribute]) -> syn::Result<Vec<NestedMeta>> {
+    let mut args = vec![];
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("ctx")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => args.extend(list.nested),
+            meta => return Err(syn::Error::new_spanned(meta, "expected #[ctx(...)]")),
+        }
+    }
+    Ok(args)
+}
+
+fn string_arg(lit: &Lit) -> syn::Result<String> {
+    match lit {
+        Lit::Str(s) => Ok(s.value()),
+        _ => Err(syn::Error::new_spanned(lit, "expected a string")),
+    }
+}
+
+fn parsed_name(input: &DeriveInput) -> syn::Result<Ident> {
+    match ctx_args(&input.attrs)?.as_slice() {
+        [NestedMeta::Meta(Meta::NameValue(nv))] if nv.path.is_ident("parsed") => {
+            Ok(Ident::new(&string_arg(&nv.lit)?, nv.lit.span()))
+        }
+        _ => Err(syn::Error::new(
+            input.ident.span(),
+            "expected a single #[ctx(parsed = \"...\")]",
+        )),
+    }
+}
+
+fn source(field: &syn::Field) -> syn::Result<Source> {
+    let mut field_ty = None;
+    let mut packed_ty = None;
+    let mut index = None;
+    let mut parse = false;
+    for arg in ctx_args(&field.attrs)? {
+        match arg {
+            NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("field") => {
+                field_ty = Some(syn::parse_str::<Type>(&string_arg(&nv.lit)?)?);
+            }
+            NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("packed") => {
+                packed_ty = Some(syn::parse_str::<Type>(&string_arg(&nv.lit)?)?);
+            }
+            NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.path.is_ident("index") => {
+                index = Some(match nv.lit {
+                    Lit::Int(ref i) => i.base10_parse::<usize>()?,
+                    ref lit => return Err(syn::Error::new_spanned(lit, "expected an index")),
+                });
+            }
+            NestedMeta::Meta(Meta::Path(ref path)) if path.is_ident("parse") => parse = true,
+            arg => return Err(syn::Error::new_spanned(arg, "unknown ctx argument")),
+        }
+    }
+    match (field_ty, packed_ty, index) {
+        (Some(ty), None, None) => Ok(Source::Field { ty, parse }),
+        (None, Some(ty), Some(index)) if !parse => Ok(Source::Packed { ty, index }),
+        _ => Err(syn::Error::new_spanned(
+            field,
+            "expected #[ctx(field = \"...\")] or #[ctx(packed = \"...\", index = N)]",
+        )),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let vis = &input.vis;
+    let parsed = parsed_name(input)?;
+    let fields = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(input, "expected named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(input, "expected a struct")),
+    };
+
+    let mut elements: Vec<Element> = vec![];
+    let mut conversions = vec![];
+    for field in fields {
+        let ident = field.ident.as_ref().unwrap();
+        let field_name = ident.to_string();
+        match source(field)? {
+            Source::Field { ty, parse } => {
+                let position = Index::from(elements.len());
+                elements.push(Element {
+                    ty,
+                    fields: vec![field_name],
+                    last_index: None,
+                });
+                conversions.push(if parse {
+                    quote! { #ident: parsed.#position.value.parse()? }
+                } else {
+                    quote! { #ident: parsed.#position.value }
+                });
+            }
+            Source::Packed { ty, index } => {
+                let type_name = quote!(#ty).to_string();
+                if index == 0 {
+                    elements.push(Element {
+                        ty,
+                        fields: vec![],
+                        last_index: None,
+                    });
+                }
+                let follows = match elements.last() {
+                    Some(element) => {
+                        let element_ty = &element.ty;
+                        element.last_index.map_or(0, |i| i + 1) == index
+                            && quote!(#element_ty).to_string() == type_name
+                    }
+                    None => false,
+                };
+                if !follows {
+                    return Err(syn::Error::new_spanned(
+                        field,
+                        "packed indices must start at 0 and follow each other",
+                    ));
+                }
+                let element = elements.last_mut().unwrap();
+                element.fields.push(field_name.clone());
+                element.last_index = Some(index);
+                let position = Index::from(elements.len() - 1);
+                let missing = format!("{} has no {} (element {})", parsed, field_name, index);
+                conversions.push(quote! {
+                    #ident: parsed.#position.value.get(#index).cloned().ok_or_else(|| {
+                        Error::from(ErrorKind::InvalidContractState(String::from(#missing)))
+                    })?
+                });
+            }
+        }
+    }
+
+    let types = elements.iter().map(|element| &element.ty);
+    let docs = elements.iter().map(|element| element.fields.join(", "));
+    let parsed_doc = format!("`getState` of `{}`, as listed in the dispatcher json", name);
+    Ok(quote! {
+        #[doc = #parsed_doc]
+        #[derive(Serialize, Deserialize)]
+        #vis struct #parsed(
+            #(
+                #[doc = #docs]
+                pub #types,
+            )*
+        );
+
+        impl ::std::convert::TryFrom<#parsed> for #name {
+            type Error = Error;
+
+            fn try_from(parsed: #parsed) -> Result<#name> {
+                Ok(#name {
+                    #(#conversions,)*
+                })
+            }
+        }
+    })
+}
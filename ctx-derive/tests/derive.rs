@@ -0,0 +1,100 @@
+//! The derive against stand-ins for the dispatcher fields and the error
+//! crate, which are all the generated code refers to
+
+#[macro_use]
+extern crate ctx_derive;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+use std::convert::TryFrom;
+
+#[derive(Serialize, Deserialize)]
+pub struct UintField {
+    pub value: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StringField {
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UintArray {
+    pub value: Vec<u64>,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    InvalidContractState(String),
+}
+
+#[derive(Debug)]
+pub struct Error(ErrorKind);
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error(kind)
+    }
+}
+
+impl From<std::num::ParseIntError> for Error {
+    fn from(e: std::num::ParseIntError) -> Self {
+        Error(ErrorKind::InvalidContractState(e.to_string()))
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, ContractCtx)]
+#[ctx(parsed = "ToyCtxParsed")]
+pub struct ToyCtx {
+    #[ctx(field = "UintField")]
+    pub deadline: u64,
+    #[ctx(field = "StringField", parse)]
+    pub count: u32,
+    #[ctx(field = "UintArray")]
+    pub query_array: Vec<u64>,
+    #[ctx(packed = "UintArray", index = 0)]
+    pub final_time: u64,
+    #[ctx(packed = "UintArray", index = 1)]
+    pub query_size: u64,
+    #[ctx(packed = "UintArray", index = 0)]
+    pub other_time: u64,
+}
+
+fn parse(json: &str) -> Result<ToyCtx> {
+    let parsed: ToyCtxParsed = serde_json::from_str(json).unwrap();
+    ToyCtx::try_from(parsed)
+}
+
+#[test]
+fn it_should_convert_positional_fields() {
+    let ctx = parse(
+        r#"[{"value": 10}, {"value": "7"}, {"value": [1, 2]}, {"value": [100, 4]}, {"value": [9]}]"#,
+    )
+    .unwrap();
+    assert_eq!(ctx.deadline, 10);
+    assert_eq!(ctx.count, 7);
+    assert_eq!(ctx.query_array, vec![1, 2]);
+    assert_eq!(ctx.final_time, 100);
+    assert_eq!(ctx.query_size, 4);
+    assert_eq!(ctx.other_time, 9);
+}
+
+#[test]
+fn it_should_reject_short_arrays_and_bad_values() {
+    let error = parse(
+        r#"[{"value": 10}, {"value": "7"}, {"value": []}, {"value": [100]}, {"value": [9]}]"#,
+    )
+    .unwrap_err();
+    match error.0 {
+        ErrorKind::InvalidContractState(message) => {
+            assert_eq!(message, "ToyCtxParsed has no query_size (element 1)")
+        }
+    }
+    assert!(parse(
+        r#"[{"value": 10}, {"value": "x"}, {"value": []}, {"value": [100, 4]}, {"value": [9]}]"#,
+    )
+    .is_err());
+}
@@ -0,0 +1,315 @@
+// Copyright (C) 2020 Cartesi Pte. Ltd.
+
+// This program is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later
+// version.
+
+// This program is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE. See the GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// Note: This component currently has dependencies that are licensed under the GNU
+// GPL, version 3, and so you should treat this component as a whole as being under
+// the GPL version 3. But all Cartesi-written code in this component is licensed
+// under the Apache License, version 2, or a compatible permissive license, and can
+// be used independently under the Apache v2 license. After this component is
+// rewritten, the entire component will be released under the Apache v2 license.
+
+//! Typed synchronous client of the machine manager. Requests and
+//! responses are any types convertible to and from the generated
+//! messages, like the `emulator_service` types of the `compute` crate,
+//! so tools and tests can talk to a machine manager without going
+//! through the dispatcher.
+
+use futures::executor::{self, Notify, NotifyHandle};
+use futures::Async;
+use grpc::ClientStub;
+
+use cartesi_machine;
+use machine_manager;
+use machine_manager_grpc::{self, MachineManager};
+use versioning;
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+/// Failure of a machine manager call
+#[derive(Debug)]
+pub enum ClientError {
+    /// No answer to `method` within `timeout`
+    Timeout {
+        method: &'static str,
+        timeout: Duration,
+    },
+    /// The machine manager answered `method` with a gRPC error status
+    Status {
+        method: &'static str,
+        status: i32,
+        message: String,
+    },
+    /// `method` did not reach the machine manager, or its answer did not
+    /// reach us
+    Transport {
+        method: &'static str,
+        message: String,
+    },
+    /// The answer to `method` does not convert to the expected type
+    InvalidResponse {
+        method: &'static str,
+        message: String,
+    },
+}
+
+impl ClientError {
+    fn from_grpc(method: &'static str, error: grpc::Error) -> Self {
+        match error {
+            grpc::Error::GrpcMessage(e) => ClientError::Status {
+                method,
+                status: e.grpc_status,
+                message: e.grpc_message,
+            },
+            e => ClientError::Transport {
+                method,
+                message: format!("{:?}", e),
+            },
+        }
+    }
+
+    /// Method of the failed call
+    pub fn method(&self) -> &'static str {
+        match self {
+            ClientError::Timeout { method, .. }
+            | ClientError::Status { method, .. }
+            | ClientError::Transport { method, .. }
+            | ClientError::InvalidResponse { method, .. } => *method,
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Timeout { method, timeout } => {
+                write!(f, "{} timed out after {:?}", method, timeout)
+            }
+            ClientError::Status {
+                method,
+                status,
+                message,
+            } => write!(f, "{} failed with status {}: {}", method, status, message),
+            ClientError::Transport { method, message } => {
+                write!(f, "{} failed: {}", method, message)
+            }
+            ClientError::InvalidResponse { method, message } => {
+                write!(f, "invalid response to {}: {}", method, message)
+            }
+        }
+    }
+}
+
+impl Error for ClientError {}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
+
+/// Wakes up the thread waiting for a call when its response may be ready
+struct ThreadNotify(Thread);
+
+impl Notify for ThreadNotify {
+    fn notify(&self, _id: usize) {
+        self.0.unpark();
+    }
+}
+
+/// Machine manager client waiting at most `timeout` for each call. Calls
+/// that time out are dropped, which cancels them
+pub struct MachineManagerClient<M = machine_manager_grpc::MachineManagerClient> {
+    stub: Arc<M>,
+    timeout: Duration,
+}
+
+impl MachineManagerClient {
+    /// Connect to the machine manager listening on `host`:`port`
+    pub fn connect(host: &str, port: u16, timeout: Duration) -> Result<Self> {
+        let client = grpc::Client::new_plain(host, port, grpc::ClientConf::new())
+            .map_err(|e| ClientError::from_grpc("Connect", e))?;
+        Ok(MachineManagerClient::with_stub(
+            machine_manager_grpc::MachineManagerClient::with_client(Arc::new(client)),
+            timeout,
+        ))
+    }
+}
+
+impl<M: MachineManager> MachineManagerClient<M> {
+    /// Call `stub`, which may be an in-process machine manager
+    pub fn with_stub(stub: M, timeout: Duration) -> Self {
+        MachineManagerClient {
+            stub: Arc::new(stub),
+            timeout,
+        }
+    }
+
+    /// Same machine manager, waiting at most `timeout` for each call, as
+    /// long runs may need a longer wait than the other calls
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        MachineManagerClient {
+            stub: self.stub.clone(),
+            timeout,
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Poll `response` in the calling thread until it is ready or the
+    /// timeout expires. On timeout the response future is dropped, which
+    /// cancels the call, so no thread is left waiting on it
+    fn wait<T: Send + 'static>(
+        &self,
+        method: &'static str,
+        response: grpc::SingleResponse<T>,
+    ) -> Result<T> {
+        let deadline = Instant::now() + self.timeout;
+        let mut call = executor::spawn(response.drop_metadata());
+        let notify = NotifyHandle::from(Arc::new(ThreadNotify(thread::current())));
+        loop {
+            match call.poll_future_notify(&notify, 0) {
+                Ok(Async::Ready(message)) => return Ok(message),
+                Ok(Async::NotReady) => {}
+                Err(e) => return Err(ClientError::from_grpc(method, e)),
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(ClientError::Timeout {
+                    method,
+                    timeout: self.timeout,
+                });
+            }
+            // woken up early by the notification, or spuriously, in which
+            // case polling again is harmless
+            thread::park_timeout(deadline - now);
+        }
+    }
+
+    fn call<T, R>(&self, method: &'static str, response: grpc::SingleResponse<T>) -> Result<R>
+    where
+        T: Send + 'static,
+        R: TryFrom<T>,
+        R::Error: fmt::Display,
+    {
+        let message = self.wait(method, response)?;
+        R::try_from(message).map_err(|e| ClientError::InvalidResponse {
+            method,
+            message: e.to_string(),
+        })
+    }
+
+    pub fn get_version<R>(&self) -> Result<R>
+    where
+        R: TryFrom<versioning::GetVersionResponse>,
+        R::Error: fmt::Display,
+    {
+        let response = self
+            .stub
+            .get_version(grpc::RequestOptions::new(), cartesi_machine::Void::new());
+        self.call("GetVersion", response)
+    }
+
+    pub fn new_session<Q, R>(&self, request: Q) -> Result<R>
+    where
+        Q: Into<machine_manager::NewSessionRequest>,
+        R: TryFrom<cartesi_machine::Hash>,
+        R::Error: fmt::Display,
+    {
+        let response = self
+            .stub
+            .new_session(grpc::RequestOptions::new(), request.into());
+        self.call("NewSession", response)
+    }
+
+    pub fn session_run<Q, R>(&self, request: Q) -> Result<R>
+    where
+        Q: Into<machine_manager::SessionRunRequest>,
+        R: TryFrom<machine_manager::SessionRunResponse>,
+        R::Error: fmt::Display,
+    {
+        let response = self
+            .stub
+            .session_run(grpc::RequestOptions::new(), request.into());
+        self.call("SessionRun", response)
+    }
+
+    pub fn session_step<Q, R>(&self, request: Q) -> Result<R>
+    where
+        Q: Into<machine_manager::SessionStepRequest>,
+        R: TryFrom<machine_manager::SessionStepResponse>,
+        R::Error: fmt::Display,
+    {
+        let response = self
+            .stub
+            .session_step(grpc::RequestOptions::new(), request.into());
+        self.call("SessionStep", response)
+    }
+
+    pub fn session_read_memory<Q, R>(&self, request: Q) -> Result<R>
+    where
+        Q: Into<machine_manager::SessionReadMemoryRequest>,
+        R: TryFrom<machine_manager::SessionReadMemoryResponse>,
+        R::Error: fmt::Display,
+    {
+        let response = self
+            .stub
+            .session_read_memory(grpc::RequestOptions::new(), request.into());
+        self.call("SessionReadMemory", response)
+    }
+
+    pub fn session_write_memory<Q>(&self, request: Q) -> Result<()>
+    where
+        Q: Into<machine_manager::SessionWriteMemoryRequest>,
+    {
+        let response = self
+            .stub
+            .session_write_memory(grpc::RequestOptions::new(), request.into());
+        self.wait("SessionWriteMemory", response).map(|_| ())
+    }
+
+    pub fn session_replace_memory_range<Q>(&self, request: Q) -> Result<()>
+    where
+        Q: Into<machine_manager::SessionReplaceMemoryRangeRequest>,
+    {
+        let response = self
+            .stub
+            .session_replace_memory_range(grpc::RequestOptions::new(), request.into());
+        self.wait("SessionReplaceMemoryRange", response).map(|_| ())
+    }
+
+    pub fn session_get_proof<Q, R>(&self, request: Q) -> Result<R>
+    where
+        Q: Into<machine_manager::SessionGetProofRequest>,
+        R: TryFrom<cartesi_machine::MerkleTreeProof>,
+        R::Error: fmt::Display,
+    {
+        let response = self
+            .stub
+            .session_get_proof(grpc::RequestOptions::new(), request.into());
+        self.call("SessionGetProof", response)
+    }
+
+    pub fn end_session<Q>(&self, request: Q) -> Result<()>
+    where
+        Q: Into<machine_manager::EndSessionRequest>,
+    {
+        let response = self
+            .stub
+            .end_session(grpc::RequestOptions::new(), request.into());
+        self.wait("EndSession", response).map(|_| ())
+    }
+}
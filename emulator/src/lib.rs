@@ -19,6 +19,7 @@
 // be used independently under the Apache v2 license. After this component is
 // rewritten, the entire component will be released under the Apache v2 license.
 
+extern crate futures;
 extern crate grpc;
 extern crate protobuf;
 
@@ -27,3 +28,7 @@ pub mod machine_manager_grpc;
 pub mod cartesi_machine;
 pub mod cartesi_machine_grpc;
 pub mod versioning;
+
+pub mod client;
+
+pub use client::{ClientError, MachineManagerClient};